@@ -0,0 +1,120 @@
+//! Learns and checks a synthetic corpus, run with `cargo bench`.
+//! Number of lines defaults to 1000000 and can be set by `LOGMAP_BENCH_LINES`,
+//! `LOGMAP_BENCH_PREFIX_TOKENS` switches to the prefix index.
+extern crate logmap;
+
+use std::env;
+use std::time::Instant;
+
+use logmap::logmap::LogFilters;
+
+const TEMPLATES: usize = 3000;
+const VOCABULARY: usize = 5000;
+
+/// Linear congruential generator, keeps the corpus the same between runs
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % bound
+    }
+
+    /// Low values are much more likely, so some words are shared by many templates
+    fn skewed(&mut self, bound: usize) -> usize {
+        let value = self.next(bound);
+        value * value / bound
+    }
+}
+
+/// Message templates, `{}` is replaced by a value varying between lines
+fn templates(random: &mut Random) -> Vec<Vec<String>> {
+    (0..TEMPLATES)
+        .map(|_| {
+            let length = 4 + random.next(5);
+            let parameter = random.next(length);
+            (0..length)
+                .map(|position| {
+                    if position == parameter {
+                        "{}".to_string()
+                    } else {
+                        format!("w{}", random.skewed(VOCABULARY))
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn corpus(lines: usize) -> Vec<String> {
+    let mut random = Random(42);
+    let templates = templates(&mut random);
+    (0..lines)
+        .map(|line_index| {
+            let template = &templates[random.skewed(TEMPLATES)];
+            let message: Vec<String> = template
+                .iter()
+                .map(|word| {
+                    if word == "{}" {
+                        format!("u{}", random.next(20))
+                    } else {
+                        word.clone()
+                    }
+                })
+                .collect();
+            format!(
+                "Jan 1 00:{:02}:{:02} web01 app[{}]: {}",
+                line_index / 60 % 60,
+                line_index % 60,
+                random.next(100000),
+                message.join(" ")
+            )
+        })
+        .collect()
+}
+
+fn main() {
+    let lines = env_usize("LOGMAP_BENCH_LINES").unwrap_or(1_000_000);
+    let corpus = corpus(lines);
+
+    let mut log_filters = LogFilters::new();
+    // host is kept, so it is a word of every filter
+    log_filters.ignore_first_columns = 1;
+    log_filters.max_allowed_new_alternatives = 1;
+    log_filters.max_slot_alternatives = 8;
+    log_filters.set_prefix_index_tokens(env_usize("LOGMAP_BENCH_PREFIX_TOKENS").unwrap_or(0));
+    let start = Instant::now();
+    for line in &corpus {
+        log_filters.learn_line(line);
+    }
+    report("learn", lines, start);
+    println!("filters: {}", log_filters.filters_count());
+
+    let start = Instant::now();
+    let known = corpus
+        .iter()
+        .filter(|line| log_filters.is_line_known(line))
+        .count();
+    report("check", lines, start);
+    println!("known:   {}", known);
+}
+
+fn env_usize(name: &str) -> Option<usize> {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+}
+
+fn report(name: &str, lines: usize, start: Instant) {
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "{}:   {} lines in {:.2} s, {:.0} lines/s",
+        name,
+        lines,
+        seconds,
+        lines as f64 / seconds
+    );
+}
@@ -0,0 +1,179 @@
+//! Primitives of the binary model format, see `LogFilters::save_binary`.
+//! Numbers are stored little endian, strings and lists are preceded by their
+//! length and the file ends with a checksum of everything before it.
+
+/// Starts every binary model, last byte is the version of the format
+pub const MAGIC: &[u8] = b"LOGMAP\x00\x01";
+const CHECKSUM_BYTES: usize = 8;
+
+/// File starts like a binary model, text models start with a number
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// 64 bit FNV-1a hash
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+pub(crate) struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new() -> Self {
+        Writer {
+            bytes: MAGIC.to_vec(),
+        }
+    }
+
+    pub(crate) fn u32(&mut self, value: usize) {
+        if value > u32::MAX as usize {
+            panic!("Value {} does not fit binary model", value);
+        }
+        self.bytes.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: usize) {
+        self.bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    pub(crate) fn str(&mut self, value: &str) {
+        self.u32(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    /// Bytes written so far followed by their checksum
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        let checksum = checksum(&self.bytes);
+        self.bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        self.bytes
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Reader of values following `MAGIC`, the checksum is verified upfront
+    pub(crate) fn new(bytes: &'a [u8]) -> Result<Self, String> {
+        if !is_binary(bytes) || bytes.len() < MAGIC.len() + CHECKSUM_BYTES {
+            return Err("Not a binary model".to_string());
+        }
+        let (content, stored) = bytes.split_at(bytes.len() - CHECKSUM_BYTES);
+        let mut stored_checksum = [0; CHECKSUM_BYTES];
+        stored_checksum.copy_from_slice(stored);
+        if u64::from_le_bytes(stored_checksum) != checksum(content) {
+            return Err("Checksum does not match, file is corrupted".to_string());
+        }
+
+        Ok(Reader {
+            bytes: content,
+            position: MAGIC.len(),
+        })
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.position < length {
+            return Err(format!(
+                "Unexpected end of binary model at byte {}",
+                self.position
+            ));
+        }
+        let bytes = &self.bytes[self.position..self.position + length];
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<usize, String> {
+        let mut value = [0; 4];
+        value.copy_from_slice(self.take(4)?);
+
+        Ok(u32::from_le_bytes(value) as usize)
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<usize, String> {
+        let mut value = [0; 8];
+        value.copy_from_slice(self.take(8)?);
+
+        Ok(u64::from_le_bytes(value) as usize)
+    }
+
+    pub(crate) fn str(&mut self) -> Result<&'a str, String> {
+        let length = self.u32()?;
+        let position = self.position;
+        match ::std::str::from_utf8(self.take(length)?) {
+            Err(why) => Err(format!("Invalid string at byte {}: {}", position, why)),
+            Ok(value) => Ok(value),
+        }
+    }
+
+    /// Number of values of a list, each taking at least `value_bytes`, an error if
+    /// bytes left cannot hold them. Lengths read from a corrupted model so never
+    /// allocate more than the model takes.
+    pub(crate) fn count(&mut self, value_bytes: usize) -> Result<usize, String> {
+        let position = self.position;
+        let count = self.u32()?;
+        if count > (self.bytes.len() - self.position) / value_bytes {
+            return Err(format!(
+                "List of {} values at byte {} exceeds binary model",
+                count, position
+            ));
+        }
+
+        Ok(count)
+    }
+
+    /// Every value was read, nothing is left before the checksum
+    pub(crate) fn is_done(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_read() {
+        let mut writer = Writer::new();
+        writer.u32(7);
+        writer.u64(1 << 40);
+        writer.str("sshd");
+        let bytes = writer.finish();
+        assert!(is_binary(&bytes));
+        assert!(!is_binary(b"1\n.\ntrue\n2\n"));
+
+        let mut reader = Reader::new(&bytes).unwrap();
+        assert_eq!(reader.u32(), Ok(7));
+        assert_eq!(reader.u64(), Ok(1 << 40));
+        assert_eq!(reader.str(), Ok("sshd"));
+        assert!(reader.is_done());
+        assert!(reader.u32().is_err());
+
+        let mut writer = Writer::new();
+        writer.u32(2);
+        writer.u32(1);
+        writer.u32(1 << 30);
+        let bytes = writer.finish();
+        let mut reader = Reader::new(&bytes).unwrap();
+        assert_eq!(reader.count(4), Ok(2));
+        assert_eq!(reader.u32(), Ok(1));
+        assert!(reader.count(4).is_err());
+
+        let mut corrupted = bytes.clone();
+        corrupted[MAGIC.len()] += 1;
+        assert!(Reader::new(&corrupted).is_err());
+        assert!(Reader::new(&bytes[..MAGIC.len() + 4]).is_err());
+    }
+}
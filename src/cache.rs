@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use logmap::{LineCheck, LogFilters};
+
+/// Outcome of matching words of a line, see `LogFilters::check_words`
+struct Entry {
+    key: String,
+    line_check: LineCheck,
+    /// Entries used right after and right before this one
+    newer: Option<usize>,
+    older: Option<usize>,
+}
+
+/// Least recently used entries of at most `capacity`, linked from the newest to the oldest
+struct Entries {
+    capacity: usize,
+    entries: Vec<Entry>,
+    indexes: HashMap<String, usize>,
+    newest: Option<usize>,
+    oldest: Option<usize>,
+    /// `LogFilters::generation` and `max_allowed_new_alternatives` entries were matched with
+    matched_by: (usize, usize),
+}
+
+impl Entries {
+    fn get(&mut self, key: &str) -> Option<LineCheck> {
+        let index = *self.indexes.get(key)?;
+        self.unlink(index);
+        self.link_newest(index);
+
+        Some(self.entries[index].line_check.clone())
+    }
+
+    fn insert(&mut self, key: String, line_check: LineCheck) {
+        if self.capacity == 0 || self.indexes.contains_key(&key) {
+            return;
+        }
+        let entry = Entry {
+            key: key.clone(),
+            line_check,
+            newer: None,
+            older: None,
+        };
+        let index = match self.oldest {
+            Some(oldest) if self.entries.len() >= self.capacity => {
+                self.unlink(oldest);
+                self.indexes.remove(&self.entries[oldest].key);
+                self.entries[oldest] = entry;
+                oldest
+            }
+            _ => {
+                self.entries.push(entry);
+                self.entries.len() - 1
+            }
+        };
+        self.indexes.insert(key, index);
+        self.link_newest(index);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.indexes.clear();
+        self.newest = None;
+        self.oldest = None;
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (newer, older) = (self.entries[index].newer, self.entries[index].older);
+        match newer {
+            None => self.newest = older,
+            Some(newer) => self.entries[newer].older = older,
+        }
+        match older {
+            None => self.oldest = newer,
+            Some(older) => self.entries[older].newer = newer,
+        }
+    }
+
+    fn link_newest(&mut self, index: usize) {
+        self.entries[index].newer = None;
+        self.entries[index].older = self.newest;
+        match self.newest {
+            None => self.oldest = Some(index),
+            Some(newest) => self.entries[newest].newer = Some(index),
+        }
+        self.newest = Some(index);
+    }
+}
+
+/// Bounded cache of `LogFilters::check_line` outcomes for lines made of the same
+/// words, least recently used lines are dropped first. Entries are dropped once
+/// filters change, see `LogFilters::generation`. May be shared by threads, lines
+/// are matched while the cache is not locked.
+pub struct LineCache {
+    entries: Mutex<Entries>,
+    hits: AtomicUsize,
+    lookups: AtomicUsize,
+}
+
+impl LineCache {
+    /// Cache of at most `capacity` lines, 0 caches nothing
+    pub fn new(capacity: usize) -> Self {
+        LineCache {
+            entries: Mutex::new(Entries {
+                capacity,
+                entries: Vec::new(),
+                indexes: HashMap::new(),
+                newest: None,
+                oldest: None,
+                matched_by: (0, 0),
+            }),
+            hits: AtomicUsize::new(0),
+            lookups: AtomicUsize::new(0),
+        }
+    }
+
+    /// Same as `log_filters.check_line(log_line)`, lines made of words seen before
+    /// are not matched again
+    pub fn check_line(&self, log_filters: &LogFilters, log_line: &str) -> LineCheck {
+        let words = log_filters.line_words(log_line);
+        let texts: Vec<&str> = words.iter().map(|word| word.text).collect();
+        // lines never hold new line characters so words cannot either
+        let key = texts.join("\n");
+        let matched_by = (
+            log_filters.generation(),
+            log_filters.max_allowed_new_alternatives,
+        );
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        let cached = {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.matched_by != matched_by {
+                entries.clear();
+                entries.matched_by = matched_by;
+            }
+            entries.get(&key)
+        };
+        let line_check = match cached {
+            Some(line_check) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                line_check
+            }
+            None => {
+                let line_check = log_filters.check_words(&words);
+                let mut entries = self.entries.lock().unwrap();
+                if entries.matched_by == matched_by {
+                    entries.insert(key, line_check.clone());
+                }
+                line_check
+            }
+        };
+
+        log_filters.escalate(line_check, log_line)
+    }
+
+    /// Share of lines found in the cache, 0 before any line was checked
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.lookups.load(Ordering::Relaxed);
+        if lookups == 0 {
+            return 0.0;
+        }
+
+        self.hits.load(Ordering::Relaxed) as f64 / lookups as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use severity::Severity;
+
+    #[test]
+    fn entries() {
+        let cache = LineCache::new(2);
+        let mut entries = cache.entries.lock().unwrap();
+        entries.insert("a".to_string(), LineCheck::Known(0));
+        entries.insert("b".to_string(), LineCheck::Known(1));
+        assert_eq!(entries.get("a"), Some(LineCheck::Known(0)));
+        // "b" is the least recently used
+        entries.insert("c".to_string(), LineCheck::Unknown);
+        assert_eq!(entries.get("b"), None);
+        assert_eq!(entries.get("a"), Some(LineCheck::Known(0)));
+        assert_eq!(entries.get("c"), Some(LineCheck::Unknown));
+        assert_eq!(entries.entries.len(), 2);
+        entries.insert("d".to_string(), LineCheck::Known(2));
+        assert_eq!(entries.get("a"), None);
+        assert_eq!(entries.get("c"), Some(LineCheck::Unknown));
+        assert_eq!(entries.get("d"), Some(LineCheck::Known(2)));
+
+        let cache = LineCache::new(0);
+        let mut entries = cache.entries.lock().unwrap();
+        entries.insert("a".to_string(), LineCheck::Known(0));
+        assert_eq!(entries.get("a"), None);
+    }
+
+    #[test]
+    fn check_line() {
+        let mut log_filters = LogFilters::new();
+        log_filters.learn_line("Sep 26 09:13:15 host sshd[1]: Accepted publickey for alice");
+        let cache = LineCache::new(16);
+        assert_eq!(cache.hit_rate(), 0.0);
+
+        let line = "Sep 27 09:13:15 host sshd[2]: Accepted publickey for alice";
+        assert_eq!(cache.check_line(&log_filters, line), LineCheck::Known(0));
+        // ignored columns and numbers are not part of the key
+        let line = "Sep 28 10:13:15 host sshd[3]: Accepted publickey for alice";
+        assert_eq!(cache.check_line(&log_filters, line), LineCheck::Known(0));
+        assert_eq!(cache.hit_rate(), 0.5);
+        // severity of the line is considered for cached lines as well
+        log_filters.filters_meta[0].severity = Some(Severity::Info);
+        let line = "<3>Sep 28 10:13:15 host sshd[3]: Accepted publickey for alice";
+        assert_eq!(
+            cache.check_line(&log_filters, line),
+            LineCheck::Escalated(0, Severity::Err)
+        );
+
+        let line = "Sep 28 10:13:15 host sshd[3]: Accepted publickey for bob";
+        assert_eq!(cache.check_line(&log_filters, line), LineCheck::Unknown);
+        log_filters.max_allowed_new_alternatives = 1;
+        assert_eq!(
+            cache.check_line(&log_filters, line),
+            LineCheck::NewValues(0, vec![(4, "bob".to_string())])
+        );
+        // learning a line known already leaves the cache as it was
+        log_filters.learn_line("Sep 28 10:13:15 host sshd[3]: Accepted publickey for alice");
+        let hits = cache.hits.load(Ordering::Relaxed);
+        assert_eq!(
+            cache.check_line(&log_filters, line),
+            LineCheck::NewValues(0, vec![(4, "bob".to_string())])
+        );
+        assert_eq!(cache.hits.load(Ordering::Relaxed), hits + 1);
+        // learned alternative makes the line known
+        log_filters.learn_line(line);
+        assert_eq!(cache.check_line(&log_filters, line), LineCheck::Known(0));
+
+        let line = "Sep 28 10:13:15 host sshd[3]: Accepted publickey for carol";
+        assert_eq!(
+            cache.check_line(&log_filters, line),
+            LineCheck::NewValues(0, vec![(4, "carol".to_string())])
+        );
+        log_filters.set_slot_varying(0, 4).unwrap();
+        assert_eq!(cache.check_line(&log_filters, line), LineCheck::Known(0));
+    }
+}
@@ -0,0 +1,850 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use logmap::LogFilters;
+use severity::Severity;
+
+/// Name of the section whose values are inherited by every other profile
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Settings of `LogFilters`, each one left as it is unless given:
+///
+/// ```
+/// use logmap::config::LogFiltersConfig;
+///
+/// let log_filters = LogFiltersConfig::new()
+///     .max_allowed_new_alternatives(1)
+///     .ignore_first_columns(3)
+///     .build()
+///     .unwrap();
+/// assert_eq!(log_filters.ignore_first_columns, 3);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LogFiltersConfig {
+    max_allowed_new_alternatives: Option<usize>,
+    denote_optional: Option<String>,
+    ignore_numeric_words: Option<bool>,
+    ignore_first_columns: Option<usize>,
+    delimiters: Option<String>,
+    max_slot_alternatives: Option<usize>,
+    infer_slot_types: Option<bool>,
+    min_gap_slots: Option<usize>,
+    rate_bucket_seconds: Option<usize>,
+    learn_transitions: Option<bool>,
+    /// `Some(None)` learns transitions within a single stream
+    transition_stream_column: Option<Option<usize>>,
+    prefix_index_tokens: Option<usize>,
+}
+
+/// Which settings are kept by `LogFilters::load_with`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precedence {
+    /// Settings stored in the model, given settings are only reported as conflicts
+    Model,
+    /// Given settings, settings of the model they differ from are replaced
+    Given,
+}
+
+/// Setting given with a value other than the one stored in the model
+#[derive(Clone, Debug, PartialEq)]
+pub struct SettingConflict {
+    pub name: &'static str,
+    pub stored: String,
+    pub given: String,
+}
+
+impl LogFiltersConfig {
+    pub fn new() -> Self {
+        LogFiltersConfig::default()
+    }
+
+    /// Every setting of `log_filters`
+    pub fn of(log_filters: &LogFilters) -> Self {
+        LogFiltersConfig {
+            max_allowed_new_alternatives: Some(log_filters.max_allowed_new_alternatives),
+            denote_optional: Some(log_filters.denote_optional().to_string()),
+            ignore_numeric_words: Some(log_filters.ignore_numeric_words),
+            ignore_first_columns: Some(log_filters.ignore_first_columns),
+            delimiters: Some(log_filters.delimiters()),
+            max_slot_alternatives: Some(log_filters.max_slot_alternatives),
+            infer_slot_types: Some(log_filters.infer_slot_types),
+            min_gap_slots: Some(log_filters.min_gap_slots),
+            rate_bucket_seconds: Some(log_filters.rate_bucket_seconds),
+            learn_transitions: Some(log_filters.learn_transitions),
+            transition_stream_column: Some(log_filters.transition_stream_column),
+            prefix_index_tokens: Some(log_filters.prefix_index_tokens()),
+        }
+    }
+
+    pub fn max_allowed_new_alternatives(mut self, value: usize) -> Self {
+        self.max_allowed_new_alternatives = Some(value);
+        self
+    }
+
+    /// Word marking optional columns, markers of other columns are derived from it.
+    /// It can only change while there are no filters yet.
+    pub fn denote_optional(mut self, value: &str) -> Self {
+        self.denote_optional = Some(value.to_string());
+        self
+    }
+
+    pub fn ignore_numeric_words(mut self, value: bool) -> Self {
+        self.ignore_numeric_words = Some(value);
+        self
+    }
+
+    pub fn ignore_first_columns(mut self, value: usize) -> Self {
+        self.ignore_first_columns = Some(value);
+        self
+    }
+
+    /// See `LogFilters::set_delimiters`
+    pub fn delimiters(mut self, value: &str) -> Self {
+        self.delimiters = Some(value.to_string());
+        self
+    }
+
+    pub fn max_slot_alternatives(mut self, value: usize) -> Self {
+        self.max_slot_alternatives = Some(value);
+        self
+    }
+
+    pub fn infer_slot_types(mut self, value: bool) -> Self {
+        self.infer_slot_types = Some(value);
+        self
+    }
+
+    pub fn min_gap_slots(mut self, value: usize) -> Self {
+        self.min_gap_slots = Some(value);
+        self
+    }
+
+    pub fn rate_bucket_seconds(mut self, value: usize) -> Self {
+        self.rate_bucket_seconds = Some(value);
+        self
+    }
+
+    pub fn learn_transitions(mut self, value: bool) -> Self {
+        self.learn_transitions = Some(value);
+        self
+    }
+
+    /// Transitions are learned within streams told apart by given column
+    pub fn transition_stream_column(mut self, value: usize) -> Self {
+        self.transition_stream_column = Some(Some(value));
+        self
+    }
+
+    /// Transitions are learned within a single stream, whichever column was set before
+    pub fn no_transition_stream_column(mut self) -> Self {
+        self.transition_stream_column = Some(None);
+        self
+    }
+
+    /// See `LogFilters::set_prefix_index_tokens`
+    pub fn prefix_index_tokens(mut self, value: usize) -> Self {
+        self.prefix_index_tokens = Some(value);
+        self
+    }
+
+    /// Settings given in `self` but not in `other`, e.g. values of a profile which
+    /// are not overridden on command line
+    pub fn without(&self, other: &LogFiltersConfig) -> Self {
+        fn unless<T: Clone>(value: &Option<T>, other: &Option<T>) -> Option<T> {
+            match other {
+                None => value.clone(),
+                Some(_) => None,
+            }
+        }
+
+        LogFiltersConfig {
+            max_allowed_new_alternatives: unless(
+                &self.max_allowed_new_alternatives,
+                &other.max_allowed_new_alternatives,
+            ),
+            denote_optional: unless(&self.denote_optional, &other.denote_optional),
+            ignore_numeric_words: unless(&self.ignore_numeric_words, &other.ignore_numeric_words),
+            ignore_first_columns: unless(&self.ignore_first_columns, &other.ignore_first_columns),
+            delimiters: unless(&self.delimiters, &other.delimiters),
+            max_slot_alternatives: unless(
+                &self.max_slot_alternatives,
+                &other.max_slot_alternatives,
+            ),
+            infer_slot_types: unless(&self.infer_slot_types, &other.infer_slot_types),
+            min_gap_slots: unless(&self.min_gap_slots, &other.min_gap_slots),
+            rate_bucket_seconds: unless(&self.rate_bucket_seconds, &other.rate_bucket_seconds),
+            learn_transitions: unless(&self.learn_transitions, &other.learn_transitions),
+            transition_stream_column: unless(
+                &self.transition_stream_column,
+                &other.transition_stream_column,
+            ),
+            prefix_index_tokens: unless(&self.prefix_index_tokens, &other.prefix_index_tokens),
+        }
+    }
+
+    /// New filters with given settings and defaults of `LogFilters::new` otherwise
+    pub fn build(&self) -> Result<LogFilters, String> {
+        let mut log_filters = LogFilters::new();
+        self.apply(&mut log_filters)?;
+
+        Ok(log_filters)
+    }
+
+    /// Settings given can be applied to `log_filters` together
+    pub fn validate(&self, log_filters: &LogFilters) -> Result<(), String> {
+        if let Some(ref denote_optional) = self.denote_optional {
+            if denote_optional.is_empty() {
+                return Err("`denote_optional` cannot be empty".to_string());
+            }
+            if denote_optional.contains(['[', ']', ',', '\n']) {
+                return Err(format!(
+                    "`denote_optional` cannot contain `[`, `]`, `,` or new line, found `{}`",
+                    denote_optional
+                ));
+            }
+            if denote_optional != log_filters.denote_optional() && log_filters.filters_count() > 0 {
+                return Err("`denote_optional` cannot change once filters are added".to_string());
+            }
+        }
+        let denote_optional = self
+            .denote_optional
+            .clone()
+            .unwrap_or_else(|| log_filters.denote_optional().to_string());
+        let delimiters = self
+            .delimiters
+            .clone()
+            .unwrap_or_else(|| log_filters.delimiters());
+        LogFilters::check_delimiters(&delimiters, &denote_optional)?;
+        if self.learn_transitions == Some(false)
+            && self.transition_stream_column.flatten().is_some()
+        {
+            return Err(
+                "`transition_stream_column` requires transitions to be learned".to_string(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Replace settings of `log_filters` with those given, nothing is changed unless
+    /// all of them are valid
+    pub fn apply(&self, log_filters: &mut LogFilters) -> Result<(), String> {
+        self.validate(log_filters)?;
+
+        if let Some(ref value) = self.denote_optional {
+            if value != log_filters.denote_optional() {
+                log_filters.set_denote_optional(value);
+            }
+        }
+        if let Some(ref value) = self.delimiters {
+            log_filters.set_delimiters(value)?;
+        }
+        if let Some(value) = self.max_allowed_new_alternatives {
+            log_filters.max_allowed_new_alternatives = value;
+        }
+        if let Some(value) = self.ignore_numeric_words {
+            log_filters.ignore_numeric_words = value;
+        }
+        if let Some(value) = self.ignore_first_columns {
+            log_filters.ignore_first_columns = value;
+        }
+        if let Some(value) = self.max_slot_alternatives {
+            log_filters.max_slot_alternatives = value;
+        }
+        if let Some(value) = self.infer_slot_types {
+            log_filters.infer_slot_types = value;
+        }
+        if let Some(value) = self.min_gap_slots {
+            log_filters.min_gap_slots = value;
+        }
+        if let Some(value) = self.rate_bucket_seconds {
+            log_filters.rate_bucket_seconds = value;
+        }
+        if let Some(value) = self.learn_transitions {
+            log_filters.learn_transitions = value;
+        }
+        match self.transition_stream_column {
+            None => (),
+            Some(None) => log_filters.transition_stream_column = None,
+            Some(Some(value)) => {
+                log_filters.learn_transitions = true;
+                log_filters.transition_stream_column = Some(value);
+            }
+        }
+        if let Some(value) = self.prefix_index_tokens {
+            if value != log_filters.prefix_index_tokens() {
+                log_filters.set_prefix_index_tokens(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Given settings differing from those of `log_filters`
+    pub fn conflicts(&self, log_filters: &LogFilters) -> Vec<SettingConflict> {
+        let stored = LogFiltersConfig::of(log_filters).values();
+        self.values()
+            .into_iter()
+            .filter_map(|(name, given)| {
+                let stored = stored
+                    .iter()
+                    .find(|(stored_name, _)| *stored_name == name)
+                    .map_or("none".to_string(), |(_, value)| value.clone());
+                if stored == given {
+                    return None;
+                }
+                Some(SettingConflict {
+                    name,
+                    stored,
+                    given,
+                })
+            })
+            .collect()
+    }
+
+    /// Name and value of each setting given, names as used by `LogFilters::save`
+    fn values(&self) -> Vec<(&'static str, String)> {
+        let mut values = Vec::new();
+        push_value(
+            &mut values,
+            "max_allowed_new_alternatives",
+            &self.max_allowed_new_alternatives,
+        );
+        push_value(&mut values, "denote_optional", &self.denote_optional);
+        push_value(
+            &mut values,
+            "ignore_numeric_words",
+            &self.ignore_numeric_words,
+        );
+        push_value(
+            &mut values,
+            "ignore_first_columns",
+            &self.ignore_first_columns,
+        );
+        push_value(&mut values, "delimiters", &self.delimiters);
+        push_value(
+            &mut values,
+            "max_slot_alternatives",
+            &self.max_slot_alternatives,
+        );
+        push_value(&mut values, "infer_slot_types", &self.infer_slot_types);
+        push_value(&mut values, "min_gap_slots", &self.min_gap_slots);
+        push_value(
+            &mut values,
+            "rate_bucket_seconds",
+            &self.rate_bucket_seconds,
+        );
+        push_value(&mut values, "learn_transitions", &self.learn_transitions);
+        push_value(
+            &mut values,
+            "transition_stream_column",
+            &self
+                .transition_stream_column
+                .map(|column| column.map_or("none".to_string(), |column| column.to_string())),
+        );
+        push_value(
+            &mut values,
+            "prefix_index_tokens",
+            &self.prefix_index_tokens,
+        );
+
+        values
+    }
+}
+
+fn push_value<T: ToString>(
+    values: &mut Vec<(&'static str, String)>,
+    name: &'static str,
+    value: &Option<T>,
+) {
+    if let Some(value) = value {
+        values.push((name, value.to_string()));
+    }
+}
+
+/// Settings of a single log source, every value is optional so that
+/// profiles can fall back to `[default]` section and to `LogFilters` defaults
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Path of the filters file used by the profile
+    pub model: Option<String>,
+    /// Path of the rules file used by the profile, see `rules::load_rules`
+    pub rules: Option<String>,
+    /// Path of the alerts file used by the profile, see `rules::load_alerts`
+    pub alerts: Option<String>,
+    /// Lowest severity of lines reported by `check`
+    pub min_severity: Option<Severity>,
+    /// Factor of unusual rates reported by `check`, see `rates::RateMonitor`
+    pub rate_factor: Option<f64>,
+    /// Transitions seen less times are reported by `check`, see `transitions::TransitionMonitor`
+    pub min_transitions: Option<usize>,
+    pub max_allowed_new_alternatives: Option<usize>,
+    pub ignore_numeric_words: Option<bool>,
+    pub ignore_first_columns: Option<usize>,
+    pub delimiters: Option<String>,
+    pub max_slot_alternatives: Option<usize>,
+    pub infer_slot_types: Option<bool>,
+    pub min_gap_slots: Option<usize>,
+    pub rate_bucket_seconds: Option<usize>,
+    pub learn_transitions: Option<bool>,
+    pub transition_stream_column: Option<usize>,
+    pub prefix_index_tokens: Option<usize>,
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Self {
+        Profile {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Settings of `LogFilters` present in the profile
+    pub fn log_filters_config(&self) -> LogFiltersConfig {
+        LogFiltersConfig {
+            max_allowed_new_alternatives: self.max_allowed_new_alternatives,
+            denote_optional: None,
+            ignore_numeric_words: self.ignore_numeric_words,
+            ignore_first_columns: self.ignore_first_columns,
+            delimiters: self.delimiters.clone(),
+            max_slot_alternatives: self.max_slot_alternatives,
+            infer_slot_types: self.infer_slot_types,
+            min_gap_slots: self.min_gap_slots,
+            rate_bucket_seconds: self.rate_bucket_seconds,
+            // stream column implies transitions even if `[default]` turns them off
+            learn_transitions: match self.transition_stream_column {
+                None => self.learn_transitions,
+                Some(_) => Some(true),
+            },
+            transition_stream_column: self.transition_stream_column.map(Some),
+            prefix_index_tokens: self.prefix_index_tokens,
+        }
+    }
+
+    /// Copy settings present in the profile onto `log_filters`
+    pub fn apply(&self, log_filters: &mut LogFilters) -> Result<(), String> {
+        self.log_filters_config().apply(log_filters)
+    }
+
+    /// Fill values missing in `self` with values from `other`
+    fn inherit(&mut self, other: &Profile) {
+        if self.model.is_none() {
+            self.model = other.model.clone();
+        }
+        if self.rules.is_none() {
+            self.rules = other.rules.clone();
+        }
+        if self.alerts.is_none() {
+            self.alerts = other.alerts.clone();
+        }
+        if self.min_severity.is_none() {
+            self.min_severity = other.min_severity;
+        }
+        if self.max_allowed_new_alternatives.is_none() {
+            self.max_allowed_new_alternatives = other.max_allowed_new_alternatives;
+        }
+        if self.ignore_numeric_words.is_none() {
+            self.ignore_numeric_words = other.ignore_numeric_words;
+        }
+        if self.ignore_first_columns.is_none() {
+            self.ignore_first_columns = other.ignore_first_columns;
+        }
+        if self.delimiters.is_none() {
+            self.delimiters = other.delimiters.clone();
+        }
+        if self.max_slot_alternatives.is_none() {
+            self.max_slot_alternatives = other.max_slot_alternatives;
+        }
+        if self.infer_slot_types.is_none() {
+            self.infer_slot_types = other.infer_slot_types;
+        }
+        if self.min_gap_slots.is_none() {
+            self.min_gap_slots = other.min_gap_slots;
+        }
+        if self.rate_bucket_seconds.is_none() {
+            self.rate_bucket_seconds = other.rate_bucket_seconds;
+        }
+        if self.rate_factor.is_none() {
+            self.rate_factor = other.rate_factor;
+        }
+        if self.min_transitions.is_none() {
+            self.min_transitions = other.min_transitions;
+        }
+        if self.learn_transitions.is_none() {
+            self.learn_transitions = other.learn_transitions;
+        }
+        if self.transition_stream_column.is_none() {
+            self.transition_stream_column = other.transition_stream_column;
+        }
+        if self.prefix_index_tokens.is_none() {
+            self.prefix_index_tokens = other.prefix_index_tokens;
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "model" => self.model = Some(value.to_string()),
+            "rules" => self.rules = Some(value.to_string()),
+            "alerts" => self.alerts = Some(value.to_string()),
+            "min_severity" => match Severity::from_name(value) {
+                None => return Err(format!("Unknown severity `{}`", value)),
+                severity => self.min_severity = severity,
+            },
+            "max_allowed_new_alternatives" => {
+                self.max_allowed_new_alternatives = Some(parse_value(key, value)?)
+            }
+            "ignore_numeric_words" => self.ignore_numeric_words = Some(parse_value(key, value)?),
+            "ignore_first_columns" => self.ignore_first_columns = Some(parse_value(key, value)?),
+            "delimiters" => self.delimiters = Some(value.to_string()),
+            "max_slot_alternatives" => self.max_slot_alternatives = Some(parse_value(key, value)?),
+            "infer_slot_types" => self.infer_slot_types = Some(parse_value(key, value)?),
+            "min_gap_slots" => self.min_gap_slots = Some(parse_value(key, value)?),
+            "rate_bucket_seconds" => self.rate_bucket_seconds = Some(parse_value(key, value)?),
+            "rate_factor" => self.rate_factor = Some(parse_value(key, value)?),
+            "min_transitions" => self.min_transitions = Some(parse_value(key, value)?),
+            "learn_transitions" => self.learn_transitions = Some(parse_value(key, value)?),
+            "transition_stream_column" => {
+                self.transition_stream_column = Some(parse_value(key, value)?)
+            }
+            "prefix_index_tokens" => self.prefix_index_tokens = Some(parse_value(key, value)?),
+            _ => return Err(format!("Unknown key `{}`", key)),
+        }
+
+        Ok(())
+    }
+}
+
+/// Named profiles read from a TOML or INI style file:
+///
+/// ```text
+/// # comment
+/// [default]
+/// ignore_first_columns = 2
+///
+/// [nginx]
+/// model = "/var/lib/logmap/nginx.filters"
+/// max_allowed_new_alternatives = 1
+/// delimiters = " /,.:\"'(){}[]"
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub profiles: Vec<Profile>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut file = match File::open(path) {
+            Err(why) => return Err(format!("Couldn't open {}: {}", path.display(), why)),
+            Ok(file) => file,
+        };
+        let mut config_str = String::new();
+        if let Err(why) = file.read_to_string(&mut config_str) {
+            return Err(format!("Couldn't read {}: {}", path.display(), why));
+        }
+
+        Config::parse(&config_str).map_err(|why| format!("{}: {}", path.display(), why))
+    }
+
+    pub fn parse(config_str: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        for (line_index, line) in config_str.lines().enumerate() {
+            config
+                .parse_line(line)
+                .map_err(|why| format!("line {}: {}", line_index + 1, why))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Profile of given name with values missing in it taken from `[default]`
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        let mut profile = self.profiles.iter().find(|p| p.name == name)?.clone();
+        if let Some(default) = self.profiles.iter().find(|p| p.name == DEFAULT_PROFILE) {
+            profile.inherit(default);
+        }
+
+        Some(profile)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            return Ok(());
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(format!("Unterminated section `{}`", line));
+            }
+            let name = line[1..line.len() - 1].trim();
+            if name.is_empty() {
+                return Err("Section name cannot be empty".to_string());
+            }
+            if self.profiles.iter().any(|p| p.name == name) {
+                return Err(format!("Duplicate section `{}`", name));
+            }
+            self.profiles.push(Profile::new(name));
+            return Ok(());
+        }
+
+        let position = match line.find('=') {
+            None => return Err(format!("`key = value` expected, found `{}`", line)),
+            Some(position) => position,
+        };
+        let key = line[..position].trim();
+        let value = parse_string(line[position + 1..].trim())?;
+        match self.profiles.last_mut() {
+            None => Err(format!("Key `{}` found outside of any section", key)),
+            Some(profile) => profile.set(key, &value),
+        }
+    }
+}
+
+fn parse_value<T: ::std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    match value.parse::<T>() {
+        Err(_) => Err(format!("Couldn't parse value of `{}`: {}", key, value)),
+        Ok(value) => Ok(value),
+    }
+}
+
+/// Strip quotes from the value, quoted values may contain `\"` and `\\`
+fn parse_string(value: &str) -> Result<String, String> {
+    if !value.starts_with('"') {
+        // unquoted values may be followed by a comment
+        let value = match value.find(" #") {
+            None => value,
+            Some(position) => &value[..position],
+        };
+        return Ok(value.trim().to_string());
+    }
+
+    let mut parsed = String::new();
+    let mut chars = value[1..].chars();
+    loop {
+        match chars.next() {
+            None => return Err(format!("Unterminated string {}", value)),
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+                Some('"') => parsed.push('"'),
+                Some('\\') => parsed.push('\\'),
+                Some('t') => parsed.push('\t'),
+                other => return Err(format!("Unsupported escape sequence `\\{:?}`", other)),
+            },
+            Some(c) => parsed.push(c),
+        }
+    }
+    let rest = chars.as_str().trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("Unexpected characters after string: {}", rest));
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config = Config::parse(
+            "# comment\n\
+             [default]\n\
+             ignore_first_columns = 3\n\
+             alerts = /etc/logmap/alerts\n\
+             min_gap_slots = 3\n\
+             rate_bucket_seconds = 3600\n\
+             rate_factor = 5.5\n\
+             \n\
+             [nginx]\n\
+             model = \"/tmp/nginx.filters\" # trailing comment\n\
+             rules = /tmp/nginx.rules\n\
+             min_severity = warning\n\
+             max_allowed_new_alternatives = 1\n\
+             max_slot_alternatives = 20\n\
+             prefix_index_tokens = 2\n\
+             delimiters = \" /,.:\\\"[]\"\n\
+             ; ini style comment\n\
+             [journal]\n\
+             ignore_numeric_words = false\n\
+             infer_slot_types = true\n\
+             transition_stream_column = 6\n\
+             min_transitions = 2\n\
+             ignore_first_columns = 5\n",
+        )
+        .unwrap();
+        assert_eq!(config.profiles.len(), 3);
+
+        let nginx = config.profile("nginx").unwrap();
+        assert_eq!(nginx.model, Some("/tmp/nginx.filters".to_string()));
+        assert_eq!(nginx.rules, Some("/tmp/nginx.rules".to_string()));
+        assert_eq!(nginx.max_allowed_new_alternatives, Some(1));
+        assert_eq!(nginx.ignore_numeric_words, None);
+        assert_eq!(nginx.ignore_first_columns, Some(3));
+        assert_eq!(nginx.delimiters, Some(" /,.:\"[]".to_string()));
+        assert_eq!(nginx.max_slot_alternatives, Some(20));
+        assert_eq!(nginx.min_severity, Some(Severity::Warning));
+        assert_eq!(nginx.prefix_index_tokens, Some(2));
+
+        let journal = config.profile("journal").unwrap();
+        assert_eq!(journal.model, None);
+        assert_eq!(journal.alerts, Some("/etc/logmap/alerts".to_string()));
+        assert_eq!(journal.ignore_numeric_words, Some(false));
+        assert_eq!(journal.infer_slot_types, Some(true));
+        assert_eq!(journal.min_gap_slots, Some(3));
+        assert_eq!(journal.rate_bucket_seconds, Some(3600));
+        assert_eq!(journal.rate_factor, Some(5.5));
+        assert_eq!(journal.transition_stream_column, Some(6));
+        assert_eq!(journal.min_transitions, Some(2));
+        assert_eq!(journal.ignore_first_columns, Some(5));
+        assert_eq!(journal.prefix_index_tokens, None);
+
+        assert!(config.profile("missing").is_none());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Config::parse("ignore_first_columns = 3").is_err());
+        assert!(Config::parse("[a]\nunknown = 3").is_err());
+        assert!(Config::parse("[a]\nignore_first_columns = x").is_err());
+        assert!(Config::parse("[a]\nignore_numeric_words = 1").is_err());
+        assert!(Config::parse("[a]\nmin_severity = loud").is_err());
+        assert!(Config::parse("[a]\nmodel = \"unterminated").is_err());
+        assert!(Config::parse("[a]\n[a]").is_err());
+        assert!(Config::parse("[a").is_err());
+        assert!(Config::parse("[a]\nno value").is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let mut profile = Profile::new("test");
+        profile.max_allowed_new_alternatives = Some(2);
+        profile.ignore_first_columns = Some(0);
+        profile.delimiters = Some(" ,.[]".to_string());
+
+        let mut log_filters = LogFilters::new();
+        profile.apply(&mut log_filters).unwrap();
+        assert_eq!(log_filters.max_allowed_new_alternatives, 2);
+        assert!(log_filters.ignore_numeric_words);
+        assert_eq!(log_filters.ignore_first_columns, 0);
+        assert_eq!(log_filters.delimiters(), " ,.[]");
+
+        // delimiters must keep characters used by filters notation
+        profile.delimiters = Some(" .".to_string());
+        assert!(profile.apply(&mut log_filters).is_err());
+    }
+
+    #[test]
+    fn log_filters_config() {
+        let log_filters = LogFiltersConfig::new()
+            .denote_optional("?")
+            .delimiters(" ,?[]")
+            .transition_stream_column(4)
+            .prefix_index_tokens(1)
+            .build()
+            .unwrap();
+        assert_eq!(log_filters.denote_optional(), "?");
+        assert_eq!(log_filters.delimiters(), " ,?[]");
+        assert!(log_filters.learn_transitions);
+        assert_eq!(log_filters.transition_stream_column, Some(4));
+        assert_eq!(log_filters.prefix_index_tokens(), 1);
+        assert_eq!(
+            LogFiltersConfig::of(&log_filters),
+            LogFiltersConfig::new()
+                .max_allowed_new_alternatives(0)
+                .denote_optional("?")
+                .ignore_numeric_words(true)
+                .ignore_first_columns(2)
+                .delimiters(" ,?[]")
+                .max_slot_alternatives(0)
+                .infer_slot_types(false)
+                .min_gap_slots(0)
+                .rate_bucket_seconds(0)
+                .learn_transitions(true)
+                .transition_stream_column(4)
+                .prefix_index_tokens(1)
+        );
+
+        // default delimiters do not hold `?`
+        assert!(LogFiltersConfig::new()
+            .denote_optional("?")
+            .build()
+            .is_err());
+        assert!(LogFiltersConfig::new().denote_optional("").build().is_err());
+        assert!(LogFiltersConfig::new()
+            .denote_optional("[")
+            .delimiters(" ,[]")
+            .build()
+            .is_err());
+        assert!(LogFiltersConfig::new()
+            .learn_transitions(false)
+            .transition_stream_column(4)
+            .build()
+            .is_err());
+
+        // nothing changes unless every setting is valid
+        let mut log_filters = LogFilters::new();
+        log_filters.learn_line("Sep 26 09:13:15 host sshd[1]: Accepted publickey for alice");
+        let invalid = LogFiltersConfig::new()
+            .ignore_first_columns(0)
+            .denote_optional("~");
+        assert!(invalid.apply(&mut log_filters).is_err());
+        assert_eq!(log_filters.ignore_first_columns, 2);
+        assert!(LogFiltersConfig::new()
+            .denote_optional(".")
+            .apply(&mut log_filters)
+            .is_ok());
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut log_filters = LogFilters::new();
+        log_filters.max_allowed_new_alternatives = 1;
+        let settings = LogFiltersConfig::new()
+            .max_allowed_new_alternatives(1)
+            .ignore_first_columns(3)
+            .transition_stream_column(5);
+        assert_eq!(
+            settings.conflicts(&log_filters),
+            vec![
+                SettingConflict {
+                    name: "ignore_first_columns",
+                    stored: "2".to_string(),
+                    given: "3".to_string(),
+                },
+                SettingConflict {
+                    name: "transition_stream_column",
+                    stored: "none".to_string(),
+                    given: "5".to_string(),
+                },
+            ]
+        );
+        settings.apply(&mut log_filters).unwrap();
+        assert!(settings.conflicts(&log_filters).is_empty());
+
+        // stream column can be cleared again
+        let single_stream = LogFiltersConfig::new().no_transition_stream_column();
+        assert_eq!(
+            single_stream.conflicts(&log_filters),
+            vec![SettingConflict {
+                name: "transition_stream_column",
+                stored: "5".to_string(),
+                given: "none".to_string(),
+            }]
+        );
+        single_stream.apply(&mut log_filters).unwrap();
+        assert_eq!(log_filters.transition_stream_column, None);
+        assert!(log_filters.learn_transitions);
+
+        let given = LogFiltersConfig::new()
+            .ignore_first_columns(0)
+            .learn_transitions(true);
+        assert_eq!(
+            settings.without(&given),
+            LogFiltersConfig::new()
+                .max_allowed_new_alternatives(1)
+                .transition_stream_column(5)
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use logmap::{LogFilters, SlotType};
+
+/// Filter present in both models, see `diff`
+#[derive(Debug, PartialEq)]
+pub struct FilterChange {
+    pub old_index: usize,
+    pub new_index: usize,
+    /// Slot of the new filter holding each slot of the old filter
+    pub slots: Vec<usize>,
+}
+
+/// Differences between two models, filters are matched by content
+#[derive(Debug, Default, PartialEq)]
+pub struct FiltersDiff {
+    /// Settings differing between the models
+    pub settings: Vec<String>,
+    /// Indexes of new filters not derived from any old filter
+    pub added: Vec<usize>,
+    /// Indexes of old filters without any new counterpart
+    pub removed: Vec<usize>,
+    /// Filters which gained alternatives or optional slots
+    pub changed: Vec<FilterChange>,
+}
+
+impl FiltersDiff {
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+
+    /// One line per difference: `!` settings, `-` removed, `+` added and `~` changed
+    /// filters, words and slots gained by changed filters are prefixed with `+`
+    pub fn to_text(&self, old: &LogFilters, new: &LogFilters) -> String {
+        let mut text = String::new();
+        for setting in &self.settings {
+            text += &format!("! {}\n", setting);
+        }
+        for filter_index in &self.removed {
+            text += &format!(
+                "- #{} {}\n",
+                filter_index,
+                old.filter_to_string(*filter_index)
+            );
+        }
+        for filter_index in &self.added {
+            text += &format!(
+                "+ #{} {}\n",
+                filter_index,
+                new.filter_to_string(*filter_index)
+            );
+        }
+        for change in &self.changed {
+            text += &format!(
+                "~ #{} -> #{} {}\n",
+                change.old_index,
+                change.new_index,
+                render_change(old, new, change)
+            );
+        }
+
+        text
+    }
+}
+
+/// Compare filters of `old` with filters of `new`. New filter is considered
+/// a changed old filter if each old slot is included, in order, in a slot of
+/// the new filter and all remaining new slots are optional.
+pub fn diff(old: &LogFilters, new: &LogFilters) -> FiltersDiff {
+    let mut filters_diff = FiltersDiff {
+        settings: old.settings_conflicts(new),
+        ..Default::default()
+    };
+    let mut old_matched = vec![false; old.filters.len()];
+    // each model interns words on its own so filters are compared as words
+    let old_filters: Vec<Vec<Vec<String>>> = (0..old.filters.len())
+        .map(|old_index| old.filter_words(old_index))
+        .collect();
+
+    for new_index in 0..new.filters.len() {
+        let new_filter = &new.filter_words(new_index);
+        let mut candidates: Vec<usize> = Vec::new();
+        for word_alternatives in new_filter {
+            for word in word_alternatives {
+                if let Some(filter_indexes) = old.word_filters(word) {
+                    candidates.extend(filter_indexes);
+                }
+            }
+        }
+        candidates.sort();
+        candidates.dedup();
+        candidates.retain(|old_index| !old_matched[*old_index]);
+
+        // identical filter is preferred over any filter it might have been extended from
+        let identical = candidates
+            .iter()
+            .find(|old_index| old_filters[**old_index] == *new_filter);
+        if let Some(old_index) = identical {
+            old_matched[*old_index] = true;
+            continue;
+        }
+
+        let mut aligned = None;
+        for old_index in candidates {
+            if let Some(slots) = align(&old_filters[old_index], new_filter, &new.denote_optional) {
+                aligned = Some(FilterChange {
+                    old_index,
+                    new_index,
+                    slots,
+                });
+                break;
+            }
+        }
+        match aligned {
+            None => filters_diff.added.push(new_index),
+            Some(change) => {
+                old_matched[change.old_index] = true;
+                filters_diff.changed.push(change);
+            }
+        }
+    }
+    filters_diff.removed = (0..old.filters.len())
+        .filter(|old_index| !old_matched[*old_index])
+        .collect();
+
+    filters_diff
+}
+
+/// Slot of `new_filter` holding each slot of `old_filter`,
+/// `None` if `new_filter` could not have been extended from `old_filter`
+fn align(
+    old_filter: &[Vec<String>],
+    new_filter: &[Vec<String>],
+    denote_optional: &str,
+) -> Option<Vec<usize>> {
+    let is_optional = |word_alternatives: &Vec<String>| {
+        word_alternatives.iter().any(|word| word == denote_optional)
+    };
+    let denote_wildcard = denote_optional.to_string() + "*";
+    let denote_gap = denote_optional.to_string() + "**";
+    let is_gap = |word_alternatives: &Vec<String>| word_alternatives.contains(&denote_gap);
+    // wildcard holds every word of the old slot and type every word of that type
+    let holds = |new_alternatives: &Vec<String>, old_alternatives: &Vec<String>| {
+        let slot_type = new_alternatives
+            .iter()
+            .filter_map(|word| SlotType::from_marker(word, denote_optional))
+            .next();
+        new_alternatives.contains(&denote_wildcard)
+            || old_alternatives.iter().all(|word| {
+                new_alternatives.contains(word)
+                    || slot_type.is_some_and(|slot_type| slot_type.accepts(word, denote_optional))
+            })
+    };
+    let mut slots = Vec::new();
+    let mut new_slot = 0;
+    for old_alternatives in old_filter {
+        loop {
+            let new_alternatives = new_filter.get(new_slot)?;
+            if is_gap(new_alternatives) {
+                // gap holds old slots until one of them is held by the slot following it
+                match new_filter.get(new_slot + 1) {
+                    Some(next_alternatives) if holds(next_alternatives, old_alternatives) => {
+                        new_slot += 1;
+                        continue;
+                    }
+                    _ => {
+                        slots.push(new_slot);
+                        break;
+                    }
+                }
+            }
+            new_slot += 1;
+            if holds(new_alternatives, old_alternatives) {
+                slots.push(new_slot - 1);
+                break;
+            }
+            // skipped slot must have been added as optional one
+            if !is_optional(new_alternatives) {
+                return None;
+            }
+        }
+    }
+    if !new_filter[new_slot..]
+        .iter()
+        .all(|word_alternatives| is_optional(word_alternatives) || is_gap(word_alternatives))
+    {
+        return None;
+    }
+
+    Some(slots)
+}
+
+fn render_change(old: &LogFilters, new: &LogFilters, change: &FilterChange) -> String {
+    let old_filter = &old.filter_words(change.old_index);
+    let new_filter = &new.filter_words(change.new_index);
+    let mut rendered: Vec<String> = Vec::new();
+    for (new_slot, new_alternatives) in new_filter.iter().enumerate() {
+        let old_alternatives = change
+            .slots
+            .iter()
+            .position(|slot| *slot == new_slot)
+            .map(|old_slot| &old_filter[old_slot]);
+        match old_alternatives {
+            None => rendered.push("+[".to_string() + &new_alternatives.join(",") + "]"),
+            Some(old_alternatives) => {
+                let words: Vec<String> = new_alternatives
+                    .iter()
+                    .map(|word| {
+                        if old_alternatives.contains(word) {
+                            word.clone()
+                        } else {
+                            "+".to_string() + word
+                        }
+                    })
+                    .collect();
+                rendered.push("[".to_string() + &words.join(",") + "]");
+            }
+        }
+    }
+
+    rendered.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn learn(lines: &[&str]) -> LogFilters {
+        let mut log_filters = LogFilters::new();
+        log_filters.max_allowed_new_alternatives = 1;
+        log_filters.ignore_first_columns = 1;
+        for line in lines {
+            log_filters.learn_line(line);
+        }
+        log_filters
+    }
+
+    #[test]
+    fn diff() {
+        let old = learn(&[
+            "Sep 26 09:13:15 host systemd-logind[572]: Removed session c524.",
+            "Sep 26 09:13:15 host kernel: usb device connected",
+            "Sep 26 09:13:15 host cron[12]: job started",
+        ]);
+        assert!(super::diff(&old, &old).is_empty());
+
+        // new model is learned from scratch so filters come in different order
+        let new = learn(&[
+            "Sep 26 09:13:15 host kernel: usb device connected",
+            "Sep 26 09:13:15 host systemd-logind[572]: Removed session c524.",
+            "Sep 26 09:13:15 host systemd-logind[572]: Removed session c525.",
+            "Sep 26 09:13:15 host kernel: usb device connected hub",
+            "Sep 26 09:13:15 host sshd[1]: Accepted publickey for root",
+        ]);
+        let filters_diff = super::diff(&old, &new);
+        assert_eq!(filters_diff.settings, Vec::<String>::new());
+        assert_eq!(filters_diff.added, vec![2]);
+        assert_eq!(filters_diff.removed, vec![2]);
+        assert_eq!(
+            filters_diff.changed,
+            vec![
+                FilterChange {
+                    old_index: 1,
+                    new_index: 0,
+                    slots: vec![0, 1, 2, 3, 4],
+                },
+                FilterChange {
+                    old_index: 0,
+                    new_index: 1,
+                    slots: vec![0, 1, 2, 3, 4],
+                },
+            ]
+        );
+        assert_eq!(
+            filters_diff.to_text(&old, &new),
+            "- #2 [host],[cron],[job],[started]\n\
+             + #2 [host],[sshd],[Accepted],[publickey],[for],[root]\n\
+             ~ #1 -> #0 [host],[kernel],[usb],[device],[connected],+[hub,.]\n\
+             ~ #0 -> #1 [host],[systemd-logind],[Removed],[session],[c524,+c525]\n"
+        );
+
+        // settings are compared as well
+        let mut new = learn(&[]);
+        new.ignore_first_columns = 2;
+        let filters_diff = super::diff(&old, &new);
+        assert_eq!(
+            filters_diff.settings,
+            vec!["ignore_first_columns: 1 != 2".to_string()]
+        );
+        assert_eq!(filters_diff.removed, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn align() {
+        let old = vec![vec!["a".to_string()], vec!["b".to_string()]];
+        let new = vec![
+            vec!["x".to_string(), ".".to_string()],
+            vec!["a".to_string()],
+            vec!["b".to_string(), "c".to_string()],
+            vec!["y".to_string(), ".".to_string()],
+        ];
+        assert_eq!(super::align(&old, &new, "."), Some(vec![1, 2]));
+        // required slot cannot be added
+        let new = vec![
+            vec!["a".to_string()],
+            vec!["x".to_string()],
+            vec!["b".to_string()],
+        ];
+        assert_eq!(super::align(&old, &new, "."), None);
+        // alternatives cannot be lost
+        let new = vec![vec!["a".to_string()]];
+        assert_eq!(super::align(&old, &new, "."), None);
+        // words replaced by wildcard are not lost
+        let new = vec![vec!["a".to_string()], vec![".*".to_string()]];
+        assert_eq!(super::align(&old, &new, "."), Some(vec![0, 1]));
+        // words of the type are not lost either
+        let old = vec![vec!["a".to_string()], vec!["12".to_string()]];
+        let new = vec![vec!["a".to_string()], vec![".hex".to_string()]];
+        assert_eq!(super::align(&old, &new, "."), Some(vec![0, 1]));
+        let old = vec![vec!["a".to_string()], vec![".int".to_string()]];
+        assert_eq!(super::align(&old, &new, "."), Some(vec![0, 1]));
+        assert_eq!(super::align(&new, &old, "."), None);
+        // gap holds any number of old slots
+        let old = vec![
+            vec!["a".to_string()],
+            vec!["x".to_string()],
+            vec!["y".to_string()],
+            vec!["b".to_string()],
+        ];
+        let new = vec![
+            vec!["a".to_string()],
+            vec![".**".to_string()],
+            vec!["b".to_string()],
+        ];
+        assert_eq!(super::align(&old, &new, "."), Some(vec![0, 1, 1, 2]));
+        let new = vec![vec!["a".to_string()], vec![".**".to_string()]];
+        assert_eq!(super::align(&old, &new, "."), Some(vec![0, 1, 1, 1]));
+    }
+}
@@ -0,0 +1,352 @@
+//! Reading log lines for `LogFilters::learn_from_reader` and friends. Lines are
+//! read as bytes so a line which is not valid UTF-8 is reported on its own
+//! instead of ending the input.
+use std::fmt;
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug)]
+pub enum InputError {
+    /// Line of given number, counted from 1, is not valid UTF-8, its bytes follow
+    InvalidLine(usize, Vec<u8>),
+    /// Input could not be read any further
+    Read(io::Error),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::InvalidLine(line_number, _) => {
+                write!(f, "Line {} is not valid UTF-8", line_number)
+            }
+            InputError::Read(why) => write!(f, "Couldn't read input: {}", why),
+        }
+    }
+}
+
+/// Lines of `input` without their line endings, see `lines`
+pub struct Lines<R> {
+    input: R,
+    line_number: usize,
+    failed: bool,
+}
+
+/// Lines of `input` split the same way as by `BufRead::lines`, a line which is not
+/// valid UTF-8 is an error of its own and the following lines are read as usual.
+/// Lines end after the first error reading `input`.
+pub fn lines<R: BufRead>(input: R) -> Lines<R> {
+    Lines {
+        input,
+        line_number: 0,
+        failed: false,
+    }
+}
+
+impl<R: BufRead> Iterator for Lines<R> {
+    type Item = Result<String, InputError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let mut bytes = Vec::new();
+        match self.input.read_until(b'\n', &mut bytes) {
+            Ok(0) => return None,
+            Ok(_) => (),
+            Err(why) => {
+                self.failed = true;
+                return Some(Err(InputError::Read(why)));
+            }
+        }
+        self.line_number += 1;
+        if bytes.last() == Some(&b'\n') {
+            bytes.pop();
+            if bytes.last() == Some(&b'\r') {
+                bytes.pop();
+            }
+        }
+
+        Some(
+            String::from_utf8(bytes)
+                .map_err(|why| InputError::InvalidLine(self.line_number, why.into_bytes())),
+        )
+    }
+}
+
+/// What becomes of lines which are not valid UTF-8
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvalidLinePolicy {
+    /// Line is left out
+    Skip,
+    /// Invalid bytes are replaced by U+FFFD, see `String::from_utf8_lossy`
+    Lossy,
+    /// Invalid bytes are written as `\xNN`, so lines differing in them differ
+    Hex,
+}
+
+impl InvalidLinePolicy {
+    pub fn from_name(name: &str) -> Option<InvalidLinePolicy> {
+        match name {
+            "skip" => Some(InvalidLinePolicy::Skip),
+            "lossy" => Some(InvalidLinePolicy::Lossy),
+            "hex" => Some(InvalidLinePolicy::Hex),
+            _ => None,
+        }
+    }
+
+    /// Line to use in place of `bytes` which are not valid UTF-8, `None` to skip it
+    pub fn repair(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            InvalidLinePolicy::Skip => None,
+            InvalidLinePolicy::Lossy => Some(String::from_utf8_lossy(bytes).into_owned()),
+            InvalidLinePolicy::Hex => {
+                let mut repaired = String::new();
+                let mut rest = bytes;
+                loop {
+                    match ::std::str::from_utf8(rest) {
+                        Ok(valid) => {
+                            repaired += valid;
+                            return Some(repaired);
+                        }
+                        Err(why) => {
+                            let (valid, invalid) = rest.split_at(why.valid_up_to());
+                            // bytes up to `valid_up_to` are known to be valid
+                            repaired += &String::from_utf8_lossy(valid);
+                            let invalid_length = why.error_len().unwrap_or(invalid.len());
+                            for byte in &invalid[..invalid_length] {
+                                repaired += &format!("\\x{:02x}", byte);
+                            }
+                            rest = &invalid[invalid_length..];
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Line read by `lines` unless it is skipped, invalid lines are repaired and
+    /// counted by `report`. Panics if input could not be read.
+    pub fn apply(
+        self,
+        line: Result<String, InputError>,
+        report: &mut FeedReport,
+    ) -> Option<String> {
+        report.lines += 1;
+        match line {
+            Ok(log_line) => Some(log_line),
+            Err(InputError::InvalidLine(_, bytes)) => {
+                let repaired = self.repair(&bytes);
+                match repaired {
+                    None => report.skipped += 1,
+                    Some(_) => report.repaired += 1,
+                }
+                repaired
+            }
+            Err(why) => panic!("{}", why),
+        }
+    }
+}
+
+/// Lines fed to `LogFilters::learn_from_reader`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedReport {
+    /// Lines read, including skipped ones
+    pub lines: usize,
+    /// Invalid lines left out, see `Feed::invalid_lines`
+    pub skipped: usize,
+    /// Invalid lines replaced, see `Feed::invalid_lines`
+    pub repaired: usize,
+    /// Input was not read to its end, see `Feed::cancel`
+    pub cancelled: bool,
+}
+
+type Progress<'a> = Box<dyn FnMut(usize) + 'a>;
+type InvalidLines<'a> = Box<dyn FnMut(usize, Vec<u8>) -> Option<String> + 'a>;
+
+/// How lines are fed to `LogFilters::learn_from_reader`: by default every line is
+/// learned, nothing reports progress and the first invalid line is an error
+#[derive(Default)]
+pub struct Feed<'a> {
+    progress_lines: usize,
+    progress: Option<Progress<'a>>,
+    cancel: Option<&'a AtomicBool>,
+    invalid_lines: Option<InvalidLines<'a>>,
+}
+
+impl<'a> Feed<'a> {
+    pub fn new() -> Self {
+        Feed::default()
+    }
+
+    /// Call `progress` with the number of lines read so far after every `lines` lines
+    pub fn progress<F: FnMut(usize) + 'a>(mut self, lines: usize, progress: F) -> Self {
+        self.progress_lines = lines;
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stop before the next line once `cancel` is set, e.g. by another thread
+    pub fn cancel(mut self, cancel: &'a AtomicBool) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Pass each line which is not valid UTF-8, along with its number counted from 1,
+    /// to `invalid_lines` instead of failing. The line returned is used in place of
+    /// the invalid one, `None` skips it.
+    pub fn invalid_lines<F>(mut self, invalid_lines: F) -> Self
+    where
+        F: FnMut(usize, Vec<u8>) -> Option<String> + 'a,
+    {
+        self.invalid_lines = Some(Box::new(invalid_lines));
+        self
+    }
+
+    /// Pass each line of `lines` to `handle` until lines end, feeding is cancelled
+    /// or an error is not handled
+    pub(crate) fn run<I, S, F>(mut self, lines: I, mut handle: F) -> Result<FeedReport, InputError>
+    where
+        I: IntoIterator<Item = Result<S, InputError>>,
+        S: AsRef<str>,
+        F: FnMut(&str),
+    {
+        let mut report = FeedReport::default();
+        for line in lines {
+            if self
+                .cancel
+                .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+            {
+                report.cancelled = true;
+                break;
+            }
+            match (line, &mut self.invalid_lines) {
+                (Ok(log_line), _) => handle(log_line.as_ref()),
+                (Err(InputError::InvalidLine(line_number, bytes)), Some(invalid_lines)) => {
+                    match invalid_lines(line_number, bytes) {
+                        None => report.skipped += 1,
+                        Some(log_line) => {
+                            report.repaired += 1;
+                            handle(&log_line);
+                        }
+                    }
+                }
+                (Err(why), _) => return Err(why),
+            }
+            report.lines += 1;
+            if self.progress_lines > 0 && report.lines % self.progress_lines == 0 {
+                if let Some(progress) = &mut self.progress {
+                    progress(report.lines);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn lines() {
+        let input: &[u8] = b"first\r\nsecond \xff line\n\nlast";
+        let read: Vec<Result<String, String>> = super::lines(Cursor::new(input))
+            .map(|line| line.map_err(|why| why.to_string()))
+            .collect();
+        assert_eq!(
+            read,
+            vec![
+                Ok("first".to_string()),
+                Err("Line 2 is not valid UTF-8".to_string()),
+                Ok("".to_string()),
+                Ok("last".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_line_policy() {
+        let bytes = b"caf\xc3\xa9 \xff\xfe ok \xe2\x82";
+        assert_eq!(InvalidLinePolicy::Skip.repair(bytes), None);
+        assert_eq!(
+            InvalidLinePolicy::Lossy.repair(bytes),
+            Some("caf\u{e9} \u{fffd}\u{fffd} ok \u{fffd}".to_string())
+        );
+        assert_eq!(
+            InvalidLinePolicy::Hex.repair(bytes),
+            Some("caf\u{e9} \\xff\\xfe ok \\xe2\\x82".to_string())
+        );
+        assert_eq!(
+            InvalidLinePolicy::from_name("hex"),
+            Some(InvalidLinePolicy::Hex)
+        );
+        assert_eq!(InvalidLinePolicy::from_name("drop"), None);
+
+        let mut report = FeedReport::default();
+        let input: &[u8] = b"a\n\xff\nb\n";
+        let applied: Vec<Option<String>> = super::lines(Cursor::new(input))
+            .map(|line| InvalidLinePolicy::Skip.apply(line, &mut report))
+            .collect();
+        assert_eq!(
+            applied,
+            vec![Some("a".to_string()), None, Some("b".to_string())]
+        );
+        assert_eq!((report.lines, report.skipped, report.repaired), (3, 1, 0));
+    }
+
+    #[test]
+    fn feed() {
+        let input: &[u8] = b"a\nb \xff\nc\nd\n";
+        let mut handled = Vec::new();
+        let result = Feed::new().run(super::lines(Cursor::new(input)), |log_line| {
+            handled.push(log_line.to_string())
+        });
+        assert!(matches!(result, Err(InputError::InvalidLine(2, _))));
+        assert_eq!(handled, vec!["a"]);
+
+        let mut handled = Vec::new();
+        let mut progress = Vec::new();
+        let report = Feed::new()
+            .progress(2, |lines| progress.push(lines))
+            .invalid_lines(|_, bytes| Some(String::from_utf8_lossy(&bytes).into_owned()))
+            .run(super::lines(Cursor::new(input)), |log_line| {
+                handled.push(log_line.to_string())
+            })
+            .unwrap();
+        assert_eq!(handled, vec!["a", "b \u{fffd}", "c", "d"]);
+        assert_eq!(progress, vec![2, 4]);
+        assert_eq!(
+            report,
+            FeedReport {
+                lines: 4,
+                repaired: 1,
+                ..Default::default()
+            }
+        );
+
+        let cancel = AtomicBool::new(false);
+        let mut handled = Vec::new();
+        let report = Feed::new()
+            .cancel(&cancel)
+            .invalid_lines(|_, _| None)
+            .run(super::lines(Cursor::new(input)), |log_line| {
+                handled.push(log_line.to_string());
+                if log_line == "c" {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            })
+            .unwrap();
+        assert_eq!(handled, vec!["a", "c"]);
+        assert_eq!(
+            report,
+            FeedReport {
+                lines: 3,
+                skipped: 1,
+                cancelled: true,
+                ..Default::default()
+            }
+        );
+    }
+}
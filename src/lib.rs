@@ -1 +1,13 @@
+pub mod binary;
+pub mod cache;
+pub mod config;
+pub mod diff;
+pub mod input;
 pub mod logmap;
+pub mod parallel;
+pub mod rates;
+pub mod rules;
+pub mod severity;
+pub mod stats;
+pub mod symbols;
+pub mod transitions;
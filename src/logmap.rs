@@ -1,9 +1,381 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::BufRead;
+use std::mem;
+use std::net::IpAddr;
 use std::path::Path;
 
-#[derive(Default)]
+use binary::{is_binary, Reader, Writer};
+use config::{LogFiltersConfig, Precedence, SettingConflict};
+use input::{lines, Feed, FeedReport, InputError};
+use rates::{line_timestamp, skipped_windows};
+use severity::Severity;
+use symbols::{Symbols, WordId};
+
+/// Characters splitting log line into words unless configured otherwise
+pub const DEFAULT_DELIMITERS: &str = " /,.:\"'(){}[]";
+
+/// Words contained by more filters than that, and by more than
+/// 1/`FREQUENT_WORD_SHARE` of all filters, are frequent, see `count_filter_hits`
+const FREQUENT_WORD_MIN_FILTERS: usize = 32;
+const FREQUENT_WORD_SHARE: usize = 16;
+/// Filters scored by `count_consequent_matches` for a single line, those
+/// containing the most words of the line are preferred
+const MAX_SCORED_CANDIDATES: usize = 64;
+/// Filters whose leading columns combine into more keys are not keyed, see `PrefixIndex`
+const MAX_PREFIX_KEYS: usize = 16;
+/// Learning drops unused words once `symbols` holds twice as many words as after
+/// the last time, or as this many, see `compact_symbols`
+const MIN_COMPACTED_SYMBOLS: usize = 4096;
+
+/// Details of a filter which are not part of the filter notation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FilterMeta {
+    /// Number of lines matched by the filter while learning
+    pub hits: usize,
+    /// Filter comes from rules file and is never changed by learning, merging or pruning
+    pub manual: bool,
+    /// Name of the alert raised by lines matching the filter, alerts are never saved
+    pub alert: Option<String>,
+    /// Highest severity of lines matched while learning, see `Severity::of_line`
+    pub severity: Option<Severity>,
+    /// Number of `hits` carrying timestamp, see `LogFilters::baseline_rate`
+    pub bucket_hits: usize,
+    /// Filters matched by the next line of the same stream while learning, as
+    /// (filter index, count) ordered by filter index, see `LogFilters::learn_transitions`
+    pub next: Vec<(usize, usize)>,
+}
+
+impl FilterMeta {
+    /// Render as space separated `key=value` pairs, defaults are skipped
+    fn to_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if self.hits > 0 {
+            suffix += &format!(" hits={}", self.hits);
+        }
+        if self.manual {
+            suffix += " manual=true";
+        }
+        if let Some(severity) = self.severity {
+            suffix += &format!(" severity={}", severity);
+        }
+        if self.bucket_hits > 0 {
+            suffix += &format!(" bucket_hits={}", self.bucket_hits);
+        }
+        if !self.next.is_empty() {
+            let next: Vec<String> = self
+                .next
+                .iter()
+                .map(|(filter_index, count)| format!("{}:{}", filter_index, count))
+                .collect();
+            suffix += &format!(" next={}", next.join(","));
+        }
+
+        suffix
+    }
+
+    /// Account for lines matched by `other` filter folded into this one
+    fn absorb(&mut self, other: &FilterMeta) {
+        self.hits += other.hits;
+        self.bucket_hits += other.bucket_hits;
+        self.severity = self.severity.max(other.severity);
+        for (filter_index, count) in &other.next {
+            self.add_next(*filter_index, *count);
+        }
+    }
+
+    fn add_next(&mut self, filter_index: usize, count: usize) {
+        match self
+            .next
+            .binary_search_by_key(&filter_index, |(next_index, _)| *next_index)
+        {
+            Ok(position) => self.next[position].1 += count,
+            Err(position) => self.next.insert(position, (filter_index, count)),
+        }
+    }
+
+    fn from_suffix(suffix: &str) -> Self {
+        let mut filter_meta = FilterMeta::default();
+        for key_value in suffix.split_whitespace() {
+            let (key, value) = match key_value.find('=') {
+                None => (key_value, ""),
+                Some(position) => (&key_value[..position], &key_value[position + 1..]),
+            };
+            match key {
+                "hits" => {
+                    filter_meta.hits = match value.parse::<usize>() {
+                        Err(why) => panic!("Couldn't parse `hits` to `usize`: {}, {}", value, why),
+                        Ok(value) => value,
+                    }
+                }
+                "manual" => {
+                    filter_meta.manual = match value.parse::<bool>() {
+                        Err(why) => panic!("Couldn't parse `manual` to `bool`: {}, {}", value, why),
+                        Ok(value) => value,
+                    }
+                }
+                "bucket_hits" => {
+                    filter_meta.bucket_hits = match value.parse::<usize>() {
+                        Err(why) => panic!(
+                            "Couldn't parse `bucket_hits` to `usize`: {}, {}",
+                            value, why
+                        ),
+                        Ok(value) => value,
+                    }
+                }
+                "next" => {
+                    for next in value.split(',') {
+                        let pair = match next.find(':') {
+                            None => None,
+                            Some(position) => next[..position]
+                                .parse::<usize>()
+                                .ok()
+                                .zip(next[position + 1..].parse::<usize>().ok()),
+                        };
+                        match pair {
+                            None => {
+                                panic!("Couldn't parse `next`, `INDEX:COUNT` expected: {}", next)
+                            }
+                            Some((filter_index, count)) => {
+                                filter_meta.add_next(filter_index, count)
+                            }
+                        }
+                    }
+                }
+                "severity" => {
+                    filter_meta.severity = match Severity::from_name(value) {
+                        None => panic!("Couldn't parse `severity`: {}", value),
+                        severity => severity,
+                    }
+                }
+                _ => panic!("Unknown filter detail `{}`", key_value),
+            }
+        }
+
+        filter_meta
+    }
+}
+
+/// Kind of words accepted by a typed column, stored as `denote_optional` followed by type name
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlotType {
+    Int,
+    Hex,
+    Id,
+    Ip,
+}
+
+impl SlotType {
+    /// Ordered from the narrowest type
+    const ALL: [SlotType; 4] = [SlotType::Int, SlotType::Hex, SlotType::Id, SlotType::Ip];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SlotType::Int => "int",
+            SlotType::Hex => "hex",
+            SlotType::Id => "id",
+            SlotType::Ip => "ip",
+        }
+    }
+
+    /// Every `Int` word is `Hex` word and every `Hex` word is `Id` word as well.
+    /// Note `.` splits words by default so only IPv6 addresses can be `Ip` words
+    /// and only if `:` is not one of delimiters.
+    pub fn matches(self, word: &str) -> bool {
+        match self {
+            SlotType::Int => !word.is_empty() && word.chars().all(|c| c.is_ascii_digit()),
+            SlotType::Hex => {
+                let digits = word.trim_start_matches("0x");
+                !digits.is_empty()
+                    && digits.chars().all(|c| c.is_ascii_hexdigit())
+                    && (digits.len() < word.len() || digits.chars().any(|c| c.is_ascii_digit()))
+            }
+            SlotType::Id => {
+                word.chars().any(|c| c.is_ascii_digit())
+                    && word
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            }
+            SlotType::Ip => word.parse::<IpAddr>().is_ok(),
+        }
+    }
+
+    /// Narrowest type of `word`, `None` for words with no digits like `Removed`
+    pub fn of(word: &str) -> Option<SlotType> {
+        SlotType::ALL.iter().cloned().find(|t| t.matches(word))
+    }
+
+    /// Narrowest type accepting words of both types
+    pub fn join(self, other: SlotType) -> Option<SlotType> {
+        if self.covers(other) {
+            Some(self)
+        } else if other.covers(self) {
+            Some(other)
+        } else {
+            None
+        }
+    }
+
+    fn covers(self, other: SlotType) -> bool {
+        match (self, other) {
+            (SlotType::Hex, SlotType::Int) => true,
+            (SlotType::Id, SlotType::Int) | (SlotType::Id, SlotType::Hex) => true,
+            _ => self == other,
+        }
+    }
+
+    /// Whether `word` or every word of typed marker `word` is of this type
+    pub fn accepts(self, word: &str, denote_optional: &str) -> bool {
+        match SlotType::from_marker(word, denote_optional) {
+            None => self.matches(word),
+            Some(other) => self.covers(other),
+        }
+    }
+
+    pub fn marker(self, denote_optional: &str) -> String {
+        denote_optional.to_string() + self.name()
+    }
+
+    pub fn from_marker(word: &str, denote_optional: &str) -> Option<SlotType> {
+        if !word.starts_with(denote_optional) {
+            return None;
+        }
+        let name = &word[denote_optional.len()..];
+        SlotType::ALL.iter().cloned().find(|t| t.name() == name)
+    }
+}
+
+/// Word of a line being matched, `id` is `None` for words unknown when the line was split
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Word<'a> {
+    pub(crate) id: Option<WordId>,
+    pub(crate) text: &'a str,
+}
+
+/// Outcome of `LogFilters::check_line`
+#[derive(Clone, Debug, PartialEq)]
+pub enum LineCheck {
+    /// Line matches filter of given index
+    Known(usize),
+    /// Line matches no filter
+    Unknown,
+    /// Line matches alert filter of given index, see `FilterMeta::alert`
+    Alert(usize),
+    /// Line matches filter of given index but states higher severity than
+    /// any line the filter was learned from
+    Escalated(usize, Severity),
+    /// Line matches filter of given index thanks to `max_allowed_new_alternatives`,
+    /// holds (slot, word) of each word the filter has not seen, see `LogFilters::set_slot_varying`
+    NewValues(usize, Vec<(usize, String)>),
+}
+
+/// Outcome of `LogFilters::merge`
+#[derive(Debug, Default, PartialEq)]
+pub struct MergeReport {
+    /// Incoming filters folded into one of existing filters
+    pub unified: usize,
+    /// Incoming filters added as new filters
+    pub appended: usize,
+}
+
+/// Outcome of `LogFilters::prune`
+#[derive(Debug, Default, PartialEq)]
+pub struct PruneReport {
+    /// Filters merged together, as (filter index after pruning, filter indexes before pruning)
+    pub merged: Vec<(usize, Vec<usize>)>,
+    /// Indexes, before pruning, of filters dropped for too few hits
+    pub dropped: Vec<usize>,
+}
+
+/// Filters keyed by words of their first `tokens` columns along with the number
+/// of words each filter accepts, see `LogFilters::set_prefix_index_tokens`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PrefixIndex {
+    tokens: usize,
+    /// Filters under each combination of alternatives of their leading columns
+    by_prefix: HashMap<Vec<WordId>, Vec<usize>>,
+    /// Filters with optional, variable or gap column among leading columns or with
+    /// too many combinations, candidates for every line
+    unkeyed: Vec<usize>,
+    /// Keys each filter is stored under, empty for unkeyed filters
+    filter_keys: Vec<Vec<Vec<WordId>>>,
+    /// Number of words each filter requires and accepts, `None` if a gap accepts any
+    lengths: Vec<(usize, Option<usize>)>,
+}
+
+impl PrefixIndex {
+    fn new(tokens: usize) -> Self {
+        PrefixIndex {
+            tokens,
+            ..Default::default()
+        }
+    }
+
+    fn set_filter(
+        &mut self,
+        filter_index: usize,
+        keys: Vec<Vec<WordId>>,
+        lengths: (usize, Option<usize>),
+    ) {
+        if self.lengths.len() <= filter_index {
+            self.lengths.resize(filter_index + 1, (0, None));
+            self.filter_keys.resize(filter_index + 1, Vec::new());
+            // new filters start unkeyed
+            self.unkeyed.push(filter_index);
+        }
+        self.lengths[filter_index] = lengths;
+        if self.filter_keys[filter_index] == keys {
+            return;
+        }
+        for key in ::std::mem::take(&mut self.filter_keys[filter_index]) {
+            if let Some(filter_indexes) = self.by_prefix.get_mut(&key) {
+                filter_indexes.retain(|index| *index != filter_index);
+                if filter_indexes.is_empty() {
+                    self.by_prefix.remove(&key);
+                }
+            }
+        }
+        if let Ok(position) = self.unkeyed.binary_search(&filter_index) {
+            self.unkeyed.remove(position);
+        }
+        if keys.is_empty() {
+            if let Err(position) = self.unkeyed.binary_search(&filter_index) {
+                self.unkeyed.insert(position, filter_index);
+            }
+        }
+        for key in &keys {
+            self.by_prefix
+                .entry(key.clone())
+                .or_default()
+                .push(filter_index);
+        }
+        self.filter_keys[filter_index] = keys;
+    }
+
+    /// Filters stored under the key made of given leading words along with unkeyed
+    /// filters, ordered by filter index
+    fn candidates(&self, prefix: Option<Vec<WordId>>) -> Vec<usize> {
+        let mut candidates = self.unkeyed.clone();
+        if let Some(filter_indexes) = prefix.and_then(|prefix| self.by_prefix.get(&prefix)) {
+            candidates.extend(filter_indexes);
+            candidates.sort();
+            candidates.dedup();
+        }
+
+        candidates
+    }
+
+    /// Line of `length` words fits the filter given `max_new` words may differ
+    fn fits_length(&self, filter_index: usize, length: usize, max_new: usize) -> bool {
+        let (required, accepted) = self.lengths[filter_index];
+        length + max_new >= required
+            && accepted.map_or(true, |accepted| length <= accepted + max_new)
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct LogFilters {
     /// Each `filters` element stores a vector of individual words variations
     /// filters (Vec) - collection of all log lines
@@ -12,39 +384,183 @@ pub struct LogFilters {
     ///          |
     ///          |- word_variations (Vec) - collection of words within word variation
     ///                   |
-    ///                   |- word1 (String)
-    ///                   |- word2 (String)
-    filters: Vec<Vec<Vec<String>>>,
+    ///                   |- word1 (WordId)
+    ///                   |- word2 (WordId)
+    pub(crate) filters: Vec<Vec<Vec<WordId>>>,
+    /// Details of each `filters` element stored under the same index
+    pub(crate) filters_meta: Vec<FilterMeta>,
     /// Each unique word from `filters` gets its own key
     /// Each key stores references to lines containing the key
-    words_hash: HashMap<String, Vec<usize>>,
+    pub(crate) words_hash: HashMap<WordId, Vec<usize>>,
+    /// Words of `filters` and markers, each stored once
+    pub(crate) symbols: Symbols,
+    /// Identifiers of `denote_optional` and markers derived from it
+    optional_id: WordId,
+    wildcard_id: WordId,
+    gap_id: WordId,
+    vary_id: WordId,
     /// Maximum allowed new alternatives when analysing any new line
     pub max_allowed_new_alternatives: usize,
     /// If `denote_optional` is found within alternatives then column is treated as optional
-    denote_optional: String,
+    pub(crate) denote_optional: String,
+    /// Column holding `denote_wildcard` matches any word, derived from `denote_optional`
+    pub(crate) denote_wildcard: String,
+    /// Column holding `denote_gap` matches zero or more words, derived from `denote_optional`
+    pub(crate) denote_gap: String,
+    /// New words of column holding `denote_vary` are expected, derived from `denote_optional`
+    pub(crate) denote_vary: String,
+    /// Column gets replaced by wildcard once it has more alternatives, 0 means no limit
+    pub max_slot_alternatives: usize,
+    /// Replace alternatives sharing a `SlotType` with that type while learning
+    pub infer_slot_types: bool,
+    /// Columns get replaced by gap where learned line differs from the filter by at least
+    /// that many consecutive words, 0 disables gaps
+    pub min_gap_slots: usize,
+    /// Length of time windows in seconds learned rates of filters are counted in, 0 disables rates
+    pub rate_bucket_seconds: usize,
+    /// Number of distinct time windows seen while learning
+    pub(crate) rate_buckets: usize,
+    /// Time window of the last learned line carrying timestamp, not saved
+    last_rate_bucket: Option<usize>,
+    /// Count transitions between filters matched by consecutive lines while learning
+    pub learn_transitions: bool,
+    /// Column, before any column is dropped, telling apart streams of lines
+    /// (e.g. host or program) transitions are counted within, single stream if `None`
+    pub transition_stream_column: Option<usize>,
+    /// Filter matched by the last learned line of each stream, not saved
+    last_stream_filters: HashMap<String, usize>,
     /// Should words that contain only numbers be ignored
     pub ignore_numeric_words: bool,
     /// Drop first columns before analysing
     pub ignore_first_columns: usize,
+    /// Characters splitting log line into words
+    delimiters: Vec<char>,
+    /// Finds candidate filters instead of `words_hash` when set
+    prefix_index: Option<PrefixIndex>,
+    /// Changes whenever filters change, so outcomes of matching can be reused until then
+    generation: usize,
+    /// Indexes of filters with `FilterMeta::alert`, in order, see `check_words`
+    alert_filters: Vec<usize>,
+    /// Size of `symbols` once unused words were last dropped, see `compact_symbols`
+    compacted_symbols: usize,
+}
+
+impl fmt::Display for LogFilters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut filters_string: String = String::new();
+        for filter_index in 0..self.filters.len() {
+            filters_string += &self.filter_to_string(filter_index);
+            filters_string += ",\n";
+        }
+        filters_string.pop();
+        filters_string.pop();
+
+        write!(f, "{}", filters_string)
+    }
 }
 
 impl LogFilters {
     pub fn new() -> Self {
         let filters = Vec::new();
+        let filters_meta = Vec::new();
         let words_hash = HashMap::new();
 
         LogFilters {
             filters,
+            filters_meta,
             words_hash,
+            symbols: Symbols::default(),
+            optional_id: 0,
+            wildcard_id: 0,
+            gap_id: 0,
+            vary_id: 0,
             max_allowed_new_alternatives: 0,
             // below must never land as word alternative
             denote_optional: ".".to_string(),
+            denote_wildcard: ".*".to_string(),
+            denote_gap: ".**".to_string(),
+            denote_vary: ".~".to_string(),
+            max_slot_alternatives: 0,
+            infer_slot_types: false,
+            min_gap_slots: 0,
+            rate_bucket_seconds: 0,
+            rate_buckets: 0,
+            last_rate_bucket: None,
+            learn_transitions: false,
+            transition_stream_column: None,
+            last_stream_filters: HashMap::new(),
             ignore_numeric_words: true,
             ignore_first_columns: 2,
+            delimiters: DEFAULT_DELIMITERS.chars().collect(),
+            prefix_index: None,
+            generation: 0,
+            alert_filters: Vec::new(),
+            compacted_symbols: 0,
         }
+        .with_markers()
     }
 
-    pub fn save(&self, path: &Path) {
+    /// Intern `denote_optional` and markers derived from it
+    fn with_markers(mut self) -> Self {
+        self.optional_id = self.symbols.intern(&self.denote_optional);
+        self.wildcard_id = self.symbols.intern(&self.denote_wildcard);
+        self.gap_id = self.symbols.intern(&self.denote_gap);
+        self.vary_id = self.symbols.intern(&self.denote_vary);
+
+        self
+    }
+
+    pub fn delimiters(&self) -> String {
+        self.delimiters.iter().collect()
+    }
+
+    /// Words must never contain characters used by filters notation,
+    /// `denote_optional` included, so those have to stay delimiters
+    pub fn set_delimiters(&mut self, delimiters: &str) -> Result<(), String> {
+        LogFilters::check_delimiters(delimiters, &self.denote_optional)?;
+        self.delimiters = delimiters.chars().collect();
+
+        Ok(())
+    }
+
+    pub(crate) fn check_delimiters(delimiters: &str, denote_optional: &str) -> Result<(), String> {
+        if delimiters.contains('\n') {
+            return Err("Delimiters cannot contain new line character".to_string());
+        }
+        let required = "[],".to_string() + denote_optional;
+        for c in required.chars() {
+            if !delimiters.contains(c) {
+                return Err(format!(
+                    "Delimiters must contain `{}`, found `{}`",
+                    c, delimiters
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Word marking optional columns, markers of other columns are derived from it
+    pub fn denote_optional(&self) -> &str {
+        &self.denote_optional
+    }
+
+    /// Replace `denote_optional` of filters which have no filters yet, see
+    /// `LogFiltersConfig::validate`
+    pub(crate) fn set_denote_optional(&mut self, denote_optional: &str) {
+        if !self.filters.is_empty() {
+            panic!("Markers cannot change once filters are added");
+        }
+        self.denote_optional = denote_optional.to_string();
+        self.denote_wildcard = self.denote_optional.clone() + "*";
+        self.denote_gap = self.denote_optional.clone() + "**";
+        self.denote_vary = self.denote_optional.clone() + "~";
+        self.symbols = Symbols::default();
+        *self = mem::take(self).with_markers();
+    }
+
+    /// First four lines of a model followed by settings differing from defaults
+    fn settings_to_string(&self) -> String {
         let mut log_filters_str = String::new();
         log_filters_str += &self.max_allowed_new_alternatives.to_string();
         log_filters_str += "\n";
@@ -54,53 +570,389 @@ impl LogFilters {
         log_filters_str += "\n";
         log_filters_str += &self.ignore_first_columns.to_string();
         log_filters_str += "\n";
-        log_filters_str += &self.to_string();
+        // optional settings are only stored when they differ from defaults
+        if self.delimiters() != DEFAULT_DELIMITERS {
+            log_filters_str += &format!("delimiters={}\n", self.delimiters());
+        }
+        if self.max_slot_alternatives > 0 {
+            log_filters_str += &format!("max_slot_alternatives={}\n", self.max_slot_alternatives);
+        }
+        if self.infer_slot_types {
+            log_filters_str += "infer_slot_types=true\n";
+        }
+        if self.min_gap_slots > 0 {
+            log_filters_str += &format!("min_gap_slots={}\n", self.min_gap_slots);
+        }
+        if self.rate_bucket_seconds > 0 {
+            log_filters_str += &format!("rate_bucket_seconds={}\n", self.rate_bucket_seconds);
+            log_filters_str += &format!("rate_buckets={}\n", self.rate_buckets);
+        }
+        if self.learn_transitions {
+            log_filters_str += "learn_transitions=true\n";
+        }
+        if let Some(column) = self.transition_stream_column {
+            log_filters_str += &format!("transition_stream_column={}\n", column);
+        }
+        if self.prefix_index_tokens() > 0 {
+            log_filters_str += &format!("prefix_index_tokens={}\n", self.prefix_index_tokens());
+        }
+
+        log_filters_str
+    }
+
+    pub fn save(&self, path: &Path) {
+        let log_filters_str = self.to_text();
 
         let path_display = path.display();
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("Couldn't create {}: {}", path_display, why.to_string()),
+        let mut file = match File::create(path) {
+            Err(why) => panic!("Couldn't create {}: {}", path_display, why),
             Ok(file) => file,
         };
         match file.write_all(log_filters_str.as_bytes()) {
-            Err(why) => panic!("Couldn't write to {}: {}", path_display, why.to_string()),
+            Err(why) => panic!("Couldn't write to {}: {}", path_display, why),
             Ok(_) => println!("Successfully wrote to {}", path_display),
         }
     }
 
-    pub fn to_string(&self) -> String {
-        let mut filters_string: String = String::new();
-        for filter in &self.filters {
-            // Vec<Vec<String>> -> Vec<String>
-            let word_alternatives: Vec<String> = filter
+    /// Settings and filters as `save` writes them
+    pub(crate) fn to_text(&self) -> String {
+        let mut log_filters_str = self.settings_to_string();
+        let (new_indexes, _) = self.saved_indexes();
+        // filter details follow the last `]` of each filter
+        let mut filter_lines: Vec<String> = Vec::new();
+        for filter_index in 0..self.filters.len() {
+            if new_indexes[filter_index].is_none() {
+                continue;
+            }
+            let mut filter_meta = self.filter_meta(filter_index);
+            filter_meta.next = filter_meta
+                .next
                 .iter()
-                .map(|s| "[".to_string() + &s.join(",") + "]")
+                .filter_map(|(next_index, count)| {
+                    new_indexes[*next_index].map(|index| (index, *count))
+                })
                 .collect();
-            filters_string += &word_alternatives.join(",");
-            filters_string += ",\n";
+            filter_lines.push(self.filter_to_string(filter_index) + &filter_meta.to_suffix());
         }
-        filters_string.pop();
-        filters_string.pop();
+        log_filters_str += &filter_lines.join("\n");
+
+        log_filters_str
+    }
+
+    /// Index each filter is saved at, `None` for alerts which are not saved, along
+    /// with the number of saved filters
+    fn saved_indexes(&self) -> (Vec<Option<usize>>, usize) {
+        let mut new_indexes: Vec<Option<usize>> = Vec::with_capacity(self.filters.len());
+        let mut saved_filters = 0;
+        for filter_index in 0..self.filters.len() {
+            if self.is_alert(filter_index) {
+                new_indexes.push(None);
+            } else {
+                new_indexes.push(Some(saved_filters));
+                saved_filters += 1;
+            }
+        }
+
+        (new_indexes, saved_filters)
+    }
+
+    /// Save filters in the binary format read by `load` as well, see `to_binary`
+    pub fn save_binary(&self, path: &Path) {
+        let path_display = path.display();
+        let mut file = match File::create(path) {
+            Err(why) => panic!("Couldn't create {}: {}", path_display, why),
+            Ok(file) => file,
+        };
+        match file.write_all(&self.to_binary()) {
+            Err(why) => panic!("Couldn't write to {}: {}", path_display, why),
+            Ok(_) => println!("Successfully wrote to {}", path_display),
+        }
+    }
+
+    /// Settings as `save` writes them, markers and words of filters, filters as
+    /// word ids along with their details and `words_hash`, so loading needs no
+    /// parsing nor indexing of filters
+    pub(crate) fn to_binary(&self) -> Vec<u8> {
+        // alerts are not saved so filters following them move
+        let (new_indexes, saved_filters) = self.saved_indexes();
+        let new_index = |filter_index: &usize| new_indexes.get(*filter_index).cloned().flatten();
+        // only words of saved filters are saved, so words get ids in order they are saved
+        let mut new_ids: Vec<Option<WordId>> = vec![None; self.symbols.len()];
+        let mut saved_words: Vec<WordId> = Vec::new();
+        let markers = [
+            self.optional_id,
+            self.wildcard_id,
+            self.gap_id,
+            self.vary_id,
+        ];
+        let filter_words = self
+            .filters
+            .iter()
+            .enumerate()
+            .filter(|(filter_index, _)| new_indexes[*filter_index].is_some())
+            .flat_map(|(_, filter)| filter.iter().flatten());
+        for word in markers.iter().chain(filter_words) {
+            if new_ids[*word as usize].is_none() {
+                new_ids[*word as usize] = Some(saved_words.len() as WordId);
+                saved_words.push(*word);
+            }
+        }
+        let new_id = |word: &WordId| new_ids[*word as usize];
+
+        let mut writer = Writer::new();
+        writer.str(&self.settings_to_string());
+        writer.u32(saved_words.len());
+        for word in saved_words {
+            writer.str(self.word(word));
+        }
+        writer.u32(saved_filters);
+        for (filter_index, filter) in self.filters.iter().enumerate() {
+            if new_indexes[filter_index].is_none() {
+                continue;
+            }
+            writer.u32(filter.len());
+            for word_alternatives in filter {
+                writer.u32(word_alternatives.len());
+                for word in word_alternatives {
+                    writer.u32(new_id(word).unwrap() as usize);
+                }
+            }
+            let filter_meta = self.filter_meta(filter_index);
+            writer.u64(filter_meta.hits);
+            writer.u32(filter_meta.manual as usize);
+            writer.str(filter_meta.severity.map_or("", |severity| severity.name()));
+            writer.u64(filter_meta.bucket_hits);
+            let next: Vec<(usize, usize)> = filter_meta
+                .next
+                .iter()
+                .filter_map(|(next_index, count)| {
+                    new_index(next_index).map(|index| (index, *count))
+                })
+                .collect();
+            writer.u32(next.len());
+            for (next_index, count) in next {
+                writer.u32(next_index);
+                writer.u64(count);
+            }
+        }
+        let mut words_hash: Vec<(WordId, Vec<usize>)> = self
+            .words_hash
+            .iter()
+            .filter_map(|(word, filter_indexes)| {
+                new_id(word)
+                    .map(|word| (word, filter_indexes.iter().filter_map(&new_index).collect()))
+            })
+            .filter(|(_, filter_indexes): &(WordId, Vec<usize>)| !filter_indexes.is_empty())
+            .collect();
+        words_hash.sort();
+        writer.u32(words_hash.len());
+        for (word, filter_indexes) in words_hash {
+            writer.u32(word as usize);
+            writer.u32(filter_indexes.len());
+            for filter_index in filter_indexes {
+                writer.u32(filter_index);
+            }
+        }
+
+        writer.finish()
+    }
+
+    /// Filters stored by `save_binary`
+    pub(crate) fn from_binary(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes)?;
+        let settings = reader.str()?;
+        let settings_lines: Vec<&str> = settings.split('\n').collect();
+        // first four lines each end with a new line, see `load_parameters`
+        if settings_lines.len() < 5 {
+            return Err(format!("Settings are incomplete: {:?}", settings));
+        }
+        let mut log_filters = LogFilters::load_parameters(&settings_lines);
+        log_filters.load_settings(&settings_lines[4..]);
+
+        // ids of words stay as they were saved
+        log_filters.symbols = Symbols::default();
+        let words_count = reader.count(4)?;
+        for id in 0..words_count {
+            let word = reader.str()?;
+            if log_filters.symbols.intern(word) as usize != id {
+                return Err(format!("Word `{}` is stored twice", word));
+            }
+        }
+        let mut log_filters = log_filters.with_markers();
+        let word_id = |reader: &mut Reader| -> Result<WordId, String> {
+            match reader.u32()? {
+                id if id >= words_count => Err(format!("Unknown word id {}", id)),
+                id => Ok(id as WordId),
+            }
+        };
+
+        // hits, manual, severity, bucket hits and transitions follow slots
+        let filters_count = reader.count(32)?;
+        let filter_index = |reader: &mut Reader| -> Result<usize, String> {
+            match reader.u32()? {
+                index if index >= filters_count => Err(format!("Unknown filter #{}", index)),
+                index => Ok(index),
+            }
+        };
+        log_filters.filters = Vec::with_capacity(filters_count);
+        log_filters.filters_meta = Vec::with_capacity(filters_count);
+        for _ in 0..filters_count {
+            let slots = reader.count(4)?;
+            let mut filter = Vec::with_capacity(slots);
+            for _ in 0..slots {
+                let alternatives = reader.count(4)?;
+                let mut word_alternatives = Vec::with_capacity(alternatives);
+                for _ in 0..alternatives {
+                    word_alternatives.push(word_id(&mut reader)?);
+                }
+                filter.push(word_alternatives);
+            }
+            let mut filter_meta = FilterMeta {
+                hits: reader.u64()?,
+                manual: reader.u32()? != 0,
+                ..Default::default()
+            };
+            let severity = reader.str()?;
+            if !severity.is_empty() {
+                filter_meta.severity = match Severity::from_name(severity) {
+                    None => return Err(format!("Unknown severity `{}`", severity)),
+                    severity => severity,
+                };
+            }
+            filter_meta.bucket_hits = reader.u64()?;
+            for _ in 0..reader.count(12)? {
+                let next_index = filter_index(&mut reader)?;
+                filter_meta.next.push((next_index, reader.u64()?));
+            }
+            log_filters.filters.push(filter);
+            log_filters.filters_meta.push(filter_meta);
+        }
+
+        for _ in 0..reader.count(8)? {
+            let word = word_id(&mut reader)?;
+            let count = reader.count(4)?;
+            let mut filter_indexes = Vec::with_capacity(count);
+            for _ in 0..count {
+                filter_indexes.push(filter_index(&mut reader)?);
+            }
+            log_filters.words_hash.insert(word, filter_indexes);
+        }
+        if !reader.is_done() {
+            return Err("Unexpected data following filters".to_string());
+        }
+        // prefix index is not stored, it was built before filters were read
+        let prefix_index_tokens = log_filters.prefix_index_tokens();
+        if prefix_index_tokens > 0 {
+            log_filters.set_prefix_index_tokens(prefix_index_tokens);
+        }
+
+        Ok(log_filters)
+    }
+
+    /// Render single filter in the same notation as used by `save`
+    pub fn filter_to_string(&self, filter_index: usize) -> String {
+        // Vec<Vec<String>> -> Vec<String>
+        let word_alternatives: Vec<String> = self
+            .filter_words(filter_index)
+            .iter()
+            .map(|s| "[".to_string() + &s.join(",") + "]")
+            .collect();
+        word_alternatives.join(",")
+    }
+
+    /// Words of each column of the filter, empty if there is no such filter
+    pub fn filter_words(&self, filter_index: usize) -> Vec<Vec<String>> {
+        match self.filters.get(filter_index) {
+            None => Vec::new(),
+            Some(filter) => filter
+                .iter()
+                .map(|word_alternatives| self.words_of(word_alternatives))
+                .collect(),
+        }
+    }
+
+    fn words_of(&self, word_ids: &[WordId]) -> Vec<String> {
+        word_ids
+            .iter()
+            .map(|id| self.symbols.word(*id).to_string())
+            .collect()
+    }
+
+    /// Indexes of filters containing the word
+    pub fn word_filters(&self, word: &str) -> Option<&Vec<usize>> {
+        self.symbols
+            .get(word)
+            .and_then(|id| self.words_hash.get(&id))
+    }
 
-        filters_string
+    pub(crate) fn word(&self, id: WordId) -> &str {
+        self.symbols.word(id)
+    }
+
+    /// Changes each time filters change, see `cache::LineCache`
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn filters_count(&self) -> usize {
+        self.filters.len()
+    }
+
+    pub fn filter_meta(&self, filter_index: usize) -> FilterMeta {
+        match self.filters_meta.get(filter_index) {
+            None => FilterMeta::default(),
+            Some(filter_meta) => filter_meta.clone(),
+        }
     }
 
     pub fn load(path: &Path) -> Self {
         let path_display = path.display();
-        let mut file = match File::open(&path) {
-            Err(why) => panic!("Couldn't open {}: {}", path_display, why.to_string()),
+        let mut file = match File::open(path) {
+            Err(why) => panic!("Couldn't open {}: {}", path_display, why),
             Ok(file) => file,
         };
-        let mut log_filters_str = String::new();
-        file.read_to_string(&mut log_filters_str)
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
             .expect("Could not read from file!");
+        if is_binary(&bytes) {
+            return match LogFilters::from_binary(&bytes) {
+                Err(why) => panic!("Couldn't load {}: {}", path_display, why),
+                Ok(log_filters) => log_filters,
+            };
+        }
+        let log_filters_str = String::from_utf8(bytes).expect("Could not read from file!");
+
+        LogFilters::from_text(&log_filters_str)
+    }
+
+    /// Filters written by `to_text`
+    pub(crate) fn from_text(log_filters_str: &str) -> Self {
         let log_filters_lines: Vec<&str> = log_filters_str.split('\n').collect();
 
         let mut log_filters = LogFilters::load_parameters(&log_filters_lines);
-        log_filters.from_str_lines(&log_filters_lines);
+        log_filters.load_settings(&log_filters_lines[4..]);
+        log_filters.parse_str_lines(&log_filters_lines[4..]);
 
         log_filters
     }
 
+    /// Same as `load` along with `settings` given otherwise, e.g. on command line.
+    /// Settings stored in the model and given ones differing from them are returned
+    /// as conflicts, `precedence` tells which of them are kept.
+    pub fn load_with(
+        path: &Path,
+        settings: &LogFiltersConfig,
+        precedence: Precedence,
+    ) -> Result<(Self, Vec<SettingConflict>), String> {
+        let mut log_filters = LogFilters::load(path);
+        let conflicts = settings.conflicts(&log_filters);
+        if precedence == Precedence::Given {
+            settings.apply(&mut log_filters)?;
+        }
+
+        Ok((log_filters, conflicts))
+    }
+
     fn load_parameters(log_filters_lines: &[&str]) -> Self {
         if log_filters_lines.len() < 5 {
             panic!(
@@ -113,14 +965,12 @@ impl LogFilters {
             match log_filters_lines[0].to_string().parse::<usize>() {
                 Err(why) => panic!(
                     "Couldn't parse 1st line of input to `usize`: {}, {}",
-                    log_filters_lines[0],
-                    why.to_string()
+                    log_filters_lines[0], why
                 ),
                 Ok(value) => value,
             };
 
-        let denote_optional: String;
-        denote_optional = log_filters_lines[1].to_string();
+        let denote_optional: String = log_filters_lines[1].to_string();
         if denote_optional.is_empty() {
             panic!("2nd line of input cannot be empty!");
         }
@@ -128,8 +978,7 @@ impl LogFilters {
         let ignore_numeric_words: bool = match log_filters_lines[2].to_string().parse::<bool>() {
             Err(why) => panic!(
                 "Couldn't parse 3rd line of input to `bool`: {}, {}",
-                log_filters_lines[2],
-                why.to_string()
+                log_filters_lines[2], why
             ),
             Ok(value) => value,
         };
@@ -137,137 +986,550 @@ impl LogFilters {
         let ignore_first_columns: usize = match log_filters_lines[3].to_string().parse::<usize>() {
             Err(why) => panic!(
                 "Couldn't parse 4th line of input to `usize`: {}, {}",
-                log_filters_lines[3],
-                why.to_string()
+                log_filters_lines[3], why
             ),
             Ok(value) => value,
         };
 
         LogFilters {
             filters: Vec::new(),
+            filters_meta: Vec::new(),
             words_hash: HashMap::new(),
+            symbols: Symbols::default(),
+            optional_id: 0,
+            wildcard_id: 0,
+            gap_id: 0,
+            vary_id: 0,
             max_allowed_new_alternatives,
+            denote_wildcard: denote_optional.clone() + "*",
+            denote_gap: denote_optional.clone() + "**",
+            denote_vary: denote_optional.clone() + "~",
+            max_slot_alternatives: 0,
+            infer_slot_types: false,
+            min_gap_slots: 0,
+            rate_bucket_seconds: 0,
+            rate_buckets: 0,
+            last_rate_bucket: None,
+            learn_transitions: false,
+            transition_stream_column: None,
+            last_stream_filters: HashMap::new(),
             denote_optional,
             ignore_numeric_words,
             ignore_first_columns,
+            delimiters: DEFAULT_DELIMITERS.chars().collect(),
+            prefix_index: None,
+            generation: 0,
+            alert_filters: Vec::new(),
+            compacted_symbols: 0,
         }
+        .with_markers()
     }
 
-    fn from_str_lines(&mut self, log_filters_lines: &[&str]) {
+    /// Settings following the first four lines are stored as `key=value`
+    fn load_settings(&mut self, log_filters_lines: &[&str]) {
         for line in log_filters_lines {
-            if !line.contains('[') || !line.contains(']') {
-                continue;
+            if line.starts_with('[') {
+                break;
             }
-            let mut alternatives = Vec::new();
-            let mut include_in_hash = Vec::new();
-            let alts_iter = line
-                .split(|c| c == '[' || c == ']')
-                .map(|s| s.to_string())
-                .filter(|s| !s.is_empty() && s != ",");
-            for alternative in alts_iter {
-                let words: Vec<String> = alternative
-                    .split(',')
-                    .map(|s| s.to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-                include_in_hash.extend(words.clone());
-                alternatives.push(words);
+            if line.is_empty() {
+                continue;
             }
-            self.filters.push(alternatives);
-            let last_filter_index = self.filters.len() - 1;
-            for word in include_in_hash {
-                if word.is_empty() || word == self.denote_optional {
-                    continue;
+            let (key, value) = match line.find('=') {
+                None => panic!("Couldn't parse setting, `key=value` expected: {}", line),
+                Some(position) => (&line[..position], &line[position + 1..]),
+            };
+            match key {
+                "delimiters" => {
+                    if let Err(why) = self.set_delimiters(value) {
+                        panic!("Couldn't load delimiters: {}", why);
+                    }
                 }
-                self.update_hash(&word, last_filter_index)
-            }
-        }
-    }
-
-    pub fn print(&self) {
+                "max_slot_alternatives" => {
+                    self.max_slot_alternatives = match value.parse::<usize>() {
+                        Err(why) => panic!(
+                            "Couldn't parse `max_slot_alternatives` to `usize`: {}, {}",
+                            value, why
+                        ),
+                        Ok(value) => value,
+                    }
+                }
+                "infer_slot_types" => {
+                    self.infer_slot_types = match value.parse::<bool>() {
+                        Err(why) => panic!(
+                            "Couldn't parse `infer_slot_types` to `bool`: {}, {}",
+                            value, why
+                        ),
+                        Ok(value) => value,
+                    }
+                }
+                "min_gap_slots" => {
+                    self.min_gap_slots = match value.parse::<usize>() {
+                        Err(why) => panic!(
+                            "Couldn't parse `min_gap_slots` to `usize`: {}, {}",
+                            value, why
+                        ),
+                        Ok(value) => value,
+                    }
+                }
+                "rate_bucket_seconds" => {
+                    self.rate_bucket_seconds = match value.parse::<usize>() {
+                        Err(why) => panic!(
+                            "Couldn't parse `rate_bucket_seconds` to `usize`: {}, {}",
+                            value, why
+                        ),
+                        Ok(value) => value,
+                    }
+                }
+                "learn_transitions" => {
+                    self.learn_transitions = match value.parse::<bool>() {
+                        Err(why) => panic!(
+                            "Couldn't parse `learn_transitions` to `bool`: {}, {}",
+                            value, why
+                        ),
+                        Ok(value) => value,
+                    }
+                }
+                "transition_stream_column" => {
+                    self.transition_stream_column = match value.parse::<usize>() {
+                        Err(why) => panic!(
+                            "Couldn't parse `transition_stream_column` to `usize`: {}, {}",
+                            value, why
+                        ),
+                        Ok(value) => Some(value),
+                    }
+                }
+                "prefix_index_tokens" => match value.parse::<usize>() {
+                    Err(why) => panic!(
+                        "Couldn't parse `prefix_index_tokens` to `usize`: {}, {}",
+                        value, why
+                    ),
+                    Ok(value) => self.set_prefix_index_tokens(value),
+                },
+                "rate_buckets" => {
+                    self.rate_buckets = match value.parse::<usize>() {
+                        Err(why) => panic!(
+                            "Couldn't parse `rate_buckets` to `usize`: {}, {}",
+                            value, why
+                        ),
+                        Ok(value) => value,
+                    }
+                }
+                _ => panic!("Unknown setting `{}`", key),
+            }
+        }
+    }
+
+    fn parse_str_lines(&mut self, log_filters_lines: &[&str]) {
+        for line in log_filters_lines {
+            if !line.starts_with('[') || !line.contains(']') {
+                continue;
+            }
+            // words can never contain `]` so anything after the last one are filter details
+            let meta_position = line.rfind(']').unwrap() + 1;
+            let meta_suffix = line[meta_position..].trim_matches(',');
+            let line = &line[..meta_position];
+            let mut alternatives = Vec::new();
+            let mut include_in_hash = Vec::new();
+            let alts_iter = line
+                .split(['[', ']'])
+                .filter(|s| !s.is_empty() && *s != ",");
+            for alternative in alts_iter {
+                let words: Vec<WordId> = alternative
+                    .split(',')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| self.symbols.intern(s))
+                    .collect();
+                include_in_hash.extend(words.clone());
+                alternatives.push(words);
+            }
+            self.filters.push(alternatives);
+            self.filters_meta.push(FilterMeta::from_suffix(meta_suffix));
+            let last_filter_index = self.filters.len() - 1;
+            for word in include_in_hash {
+                if self.is_marker_id(word) {
+                    continue;
+                }
+                self.update_hash(word, last_filter_index)
+            }
+            self.filter_changed(last_filter_index);
+        }
+    }
+
+    pub fn print(&self) {
         if !self.filters.is_empty() {
-            for elem in &self.filters {
-                println!("{:?}", elem);
+            for filter_index in 0..self.filters.len() {
+                println!("{:?}", self.filter_words(filter_index));
             }
         } else {
             println!("No filters added yet");
         }
         println!();
         if !self.words_hash.is_empty() {
-            let keys: &Vec<&String> = &self.words_hash.keys().collect();
-            let mut keys = keys.clone();
+            let mut keys: Vec<(&str, WordId)> = self
+                .words_hash
+                .keys()
+                .map(|id| (self.word(*id), *id))
+                .collect();
             keys.sort();
-            for key in keys {
-                println!("{} : {:?}", key, &self.words_hash[key]);
+            for (key, id) in keys {
+                println!("{} : {:?}", key, &self.words_hash[&id]);
             }
         } else {
             println!("No words with references to filters added yet");
         }
     }
 
+    /// Each line of `input` checked with `check_line`, a line which is not valid
+    /// UTF-8 is an error of its own, see `input::lines`. Stop iterating to cancel.
+    pub fn check_reader<'a, R: BufRead + 'a>(
+        &'a self,
+        input: R,
+    ) -> impl Iterator<Item = Result<(String, LineCheck), InputError>> + 'a {
+        lines(input).map(move |line| {
+            line.map(|log_line| {
+                let line_check = self.check_line(&log_line);
+                (log_line, line_check)
+            })
+        })
+    }
+
+    /// Lines of `input` which are not known, see `is_line_known` and `check_reader`
+    pub fn unknown_lines<'a, R: BufRead + 'a>(
+        &'a self,
+        input: R,
+    ) -> impl Iterator<Item = Result<String, InputError>> + 'a {
+        self.check_reader(input)
+            .filter_map(|checked| match checked {
+                Ok((log_line, LineCheck::Unknown)) | Ok((log_line, LineCheck::Alert(_))) => {
+                    Some(Ok(log_line))
+                }
+                Ok(_) => None,
+                Err(why) => Some(Err(why)),
+            })
+    }
+
+    /// Some filter matches the line, even one `check_line` reports new values or
+    /// higher severity of. Lines matching alerts are never known.
     pub fn is_line_known(&self, log_line: &str) -> bool {
-        let words = self.line_to_words(&log_line);
-        if self.find_best_matching_filter_index(&words) == -1 {
-            return false;
+        !matches!(
+            self.check_line(log_line),
+            LineCheck::Unknown | LineCheck::Alert(_)
+        )
+    }
+
+    /// Alerts take priority over any other filter
+    pub fn check_line(&self, log_line: &str) -> LineCheck {
+        let words = self.line_words(log_line);
+        self.escalate(self.check_words(&words), log_line)
+    }
+
+    /// `check_line` outcome for words of a line, before severity of the line is considered
+    pub(crate) fn check_words(&self, words: &[Word]) -> LineCheck {
+        let alert_index = self
+            .alert_filters
+            .iter()
+            .cloned()
+            .find(|filter_index| self.is_exact_match(words, *filter_index));
+        if let Some(alert_index) = alert_index {
+            return LineCheck::Alert(alert_index);
+        }
+        let filter_index = self.find_best_matching_filter_index(words);
+        if filter_index < 0 {
+            return LineCheck::Unknown;
+        }
+        let filter_index = filter_index as usize;
+        let new_values = self.get_new_values(words, filter_index);
+        if !new_values.is_empty() {
+            return LineCheck::NewValues(filter_index, new_values);
         }
 
-        true
+        LineCheck::Known(filter_index)
     }
 
-    fn line_to_words(&self, log_line: &str) -> Vec<String> {
-        let raw_words = LogFilters::line_split(log_line);
+    /// Line stating higher severity than its filter was learned from is escalated
+    /// whether it holds new values or not
+    pub(crate) fn escalate(&self, line_check: LineCheck, log_line: &str) -> LineCheck {
+        let filter_index = match line_check {
+            LineCheck::Known(filter_index) | LineCheck::NewValues(filter_index, _) => filter_index,
+            _ => return line_check,
+        };
+        // filters learned from lines without severity cannot tell what is higher
+        if let Some(learned_severity) = self.filter_meta(filter_index).severity {
+            match Severity::of_line(log_line) {
+                Some(severity) if severity > learned_severity => {
+                    return LineCheck::Escalated(filter_index, severity)
+                }
+                _ => (),
+            }
+        }
+
+        line_check
+    }
+
+    /// Words matching no column as (slot the word falls into, word), columns holding
+    /// `denote_vary` are skipped. Slot equal to filter length means word follows the last column.
+    fn get_new_values(&self, words: &[Word], filter_index: usize) -> Vec<(usize, String)> {
+        let filter = &self.filters[filter_index];
+        let mut new_values = Vec::new();
+        let mut last_matching_index: isize = -1;
+        for word in words {
+            let matching_index = self.get_word_index_in_filter(
+                word,
+                filter_index,
+                (last_matching_index + 1) as usize,
+            );
+            if matching_index > last_matching_index {
+                last_matching_index = matching_index;
+                continue;
+            }
+            let slot = (last_matching_index + 1) as usize;
+            match filter.get(slot) {
+                Some(word_alternatives)
+                    if self.is_gap(word_alternatives)
+                        || word_alternatives.contains(&self.vary_id) => {}
+                _ => new_values.push((slot, word.text.to_string())),
+            }
+        }
+
+        new_values
+    }
+
+    /// Stop reporting new words of given column, see `LineCheck::NewValues`
+    pub fn set_slot_varying(&mut self, filter_index: usize, slot: usize) -> Result<(), String> {
+        let word_alternatives = match self
+            .filters
+            .get_mut(filter_index)
+            .and_then(|filter| filter.get_mut(slot))
+        {
+            None => return Err(format!("Filter #{} has no slot {}", filter_index, slot)),
+            Some(word_alternatives) => word_alternatives,
+        };
+        if !word_alternatives.contains(&self.vary_id) {
+            word_alternatives.push(self.vary_id);
+            self.filter_changed(filter_index);
+        }
+
+        Ok(())
+    }
+
+    fn is_alert(&self, filter_index: usize) -> bool {
+        self.filters_meta
+            .get(filter_index)
+            .is_some_and(|filter_meta| filter_meta.alert.is_some())
+    }
+
+    /// Index of the filter matching given line, `None` if line is unknown
+    pub fn find_line_filter(&self, log_line: &str) -> Option<usize> {
+        let words = self.line_words(log_line);
+        let filter_index = self.find_best_matching_filter_index(&words);
+        if filter_index < 0 {
+            return None;
+        }
+
+        Some(filter_index as usize)
+    }
+
+    pub fn line_to_words(&self, log_line: &str) -> Vec<String> {
+        self.line_words(log_line)
+            .iter()
+            .map(|word| word.text.to_string())
+            .collect()
+    }
+
+    /// Words of the line borrowed from it, with identifiers of words known already
+    pub(crate) fn line_words<'a>(&self, log_line: &'a str) -> Vec<Word<'a>> {
         let mut words = Vec::new();
 
         let mut i = 0;
-        for word in raw_words {
-            let word = word.to_string();
-            if self.ignore_numeric_words && self.is_word_only_numeric(&word) {
+        for word in LogFilters::split_words(log_line, &self.delimiters) {
+            if self.ignore_numeric_words && self.is_word_only_numeric(word) {
                 continue;
             }
             if i < self.ignore_first_columns {
                 i += 1;
                 continue;
             }
-            words.push(word);
+            words.push(self.word_of(word));
         }
 
         words
     }
 
+    pub(crate) fn word_of<'a>(&self, word: &'a str) -> Word<'a> {
+        Word {
+            id: self.symbols.get(word),
+            text: word,
+        }
+    }
+
+    /// Identifier of the word, interned if it was not yet
+    fn intern(&mut self, word: &Word) -> WordId {
+        match word.id {
+            None => self.symbols.intern(word.text),
+            Some(id) => id,
+        }
+    }
+
+    /// Identifier of the word, looked up again if it was not known when the line was split
+    fn word_id(&self, word: &Word) -> Option<WordId> {
+        word.id.or_else(|| self.symbols.get(word.text))
+    }
+
     pub fn line_split(log_line: &str) -> Vec<String> {
-        log_line
-            .split(|c| {
-                c == ' '
-                    || c == '/'
-                    || c == ','
-                    || c == '.'
-                    || c == ':'
-                    || c == '"'
-                    || c == '\''
-                    || c == '('
-                    || c == ')'
-                    || c == '{'
-                    || c == '}'
-                    || c == '['
-                    || c == ']'
-            })
+        let delimiters: Vec<char> = DEFAULT_DELIMITERS.chars().collect();
+        LogFilters::line_split_by(log_line, &delimiters)
+    }
+
+    pub fn line_split_by(log_line: &str, delimiters: &[char]) -> Vec<String> {
+        LogFilters::split_words(log_line, delimiters)
             .map(|s| s.to_string())
-            .filter(|s| !s.is_empty())
             .collect()
     }
 
+    fn split_words<'a: 'b, 'b>(
+        log_line: &'a str,
+        delimiters: &'b [char],
+    ) -> impl Iterator<Item = &'a str> + 'b {
+        log_line.split(delimiters).filter(|s| !s.is_empty())
+    }
+
+    /// Learn each line of `input` as `learn_line` does, `feed` reports progress,
+    /// cancels learning and handles lines which are not valid UTF-8. Lines learned
+    /// before an error are kept.
+    pub fn learn_from_reader<R: BufRead>(
+        &mut self,
+        input: R,
+        feed: Feed,
+    ) -> Result<FeedReport, InputError> {
+        feed.run(lines(input), |log_line| self.learn_line(log_line))
+    }
+
+    /// Learn each of `lines` as `learn_line` does, see `learn_from_reader`
+    pub fn learn_from_iter<I>(&mut self, lines: I, feed: Feed) -> FeedReport
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        match feed.run(lines.into_iter().map(Ok), |log_line| {
+            self.learn_line(log_line)
+        }) {
+            Err(why) => unreachable!("Lines given are valid: {}", why),
+            Ok(report) => report,
+        }
+    }
+
     pub fn learn_line(&mut self, log_line: &str) {
-        let words = self.line_to_words(&log_line);
+        let words = self.line_words(log_line);
 
-        let matched_filter_index = self.find_best_matching_filter_index(&words);
-        if matched_filter_index >= 0 {
-            self.update_filter(&words, matched_filter_index as usize);
+        let mut matched_filter_index = self.find_best_matching_filter_index(&words);
+        // rules are never extended so lines they do not match exactly get filters of their own
+        if matched_filter_index >= 0
+            && self.filter_meta(matched_filter_index as usize).manual
+            && !self.is_exact_match(&words, matched_filter_index as usize)
+        {
+            matched_filter_index = -1;
+        }
+        let filter_before = if matched_filter_index >= 0 {
+            self.filters.get(matched_filter_index as usize).cloned()
         } else {
+            None
+        };
+        let learned_filter_index = if matched_filter_index >= 0 {
+            let filter_index = matched_filter_index as usize;
+            if !self.filter_meta(filter_index).manual {
+                if self.min_gap_slots > 0 {
+                    self.introduce_gaps(&words, filter_index);
+                }
+                self.update_filter(&words, filter_index);
+            }
+            filter_index
+        } else {
+            // no filter is added for lines without words
+            let new_filter_index = self.filters.len();
             self.add_filter(words);
+            new_filter_index
+        };
+        // lines matching a filter exactly leave it as it was
+        if self.filters.get(learned_filter_index) != filter_before.as_ref() {
+            self.filter_changed(learned_filter_index);
+            if self.symbols.len() > 2 * self.compacted_symbols.max(MIN_COMPACTED_SYMBOLS) {
+                self.compact_symbols();
+            }
         }
+        let has_timestamp = self.count_rate_bucket(log_line);
+        if let Some(filter_meta) = self.filters_meta.get_mut(learned_filter_index) {
+            filter_meta.absorb(&FilterMeta {
+                hits: 1,
+                severity: Severity::of_line(log_line),
+                bucket_hits: has_timestamp as usize,
+                ..Default::default()
+            });
+            if self.learn_transitions {
+                let stream = self.stream_key(log_line);
+                if let Some(last_filter_index) = self
+                    .last_stream_filters
+                    .insert(stream, learned_filter_index)
+                {
+                    self.filters_meta[last_filter_index].add_next(learned_filter_index, 1);
+                }
+            }
+        }
+    }
+
+    /// Value of `transition_stream_column` of the line, empty if there is no such column
+    pub fn stream_key(&self, log_line: &str) -> String {
+        match self.transition_stream_column {
+            None => String::new(),
+            Some(column) => LogFilters::split_words(log_line, &self.delimiters)
+                .nth(column)
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+
+    /// How many times a line matching `to` followed a line matching `from` while learning
+    pub fn transition_count(&self, from: usize, to: usize) -> usize {
+        let filter_meta = self.filter_meta(from);
+        match filter_meta
+            .next
+            .binary_search_by_key(&to, |(next_index, _)| *next_index)
+        {
+            Err(_) => 0,
+            Ok(position) => filter_meta.next[position].1,
+        }
+    }
+
+    /// Count time window of the line if rates are enabled, returns `true` if it was counted.
+    /// Windows without lines since the previous one are counted as well, as `RateMonitor` does.
+    pub(crate) fn count_rate_bucket(&mut self, log_line: &str) -> bool {
+        if self.rate_bucket_seconds == 0 {
+            return false;
+        }
+        let bucket = match line_timestamp(log_line) {
+            None => return false,
+            Some(timestamp) => timestamp / self.rate_bucket_seconds,
+        };
+        if self.last_rate_bucket != Some(bucket) {
+            self.rate_buckets += 1 + self
+                .last_rate_bucket
+                .map_or(0, |last_bucket| skipped_windows(last_bucket, bucket));
+            self.last_rate_bucket = Some(bucket);
+        }
+
+        true
+    }
+
+    /// Average number of lines matched by the filter within a time window while
+    /// learning, `None` if rates were not learned
+    pub fn baseline_rate(&self, filter_index: usize) -> Option<f64> {
+        if self.rate_buckets == 0 || self.is_alert(filter_index) {
+            return None;
+        }
+
+        Some(self.filter_meta(filter_index).bucket_hits as f64 / self.rate_buckets as f64)
     }
 
-    fn is_word_only_numeric(&self, word: &str) -> bool {
+    pub(crate) fn is_word_only_numeric(&self, word: &str) -> bool {
         let chars_are_numeric: Vec<bool> = word
             .chars()
             .map(|c| c == '*' || c == '#' || c.is_numeric())
@@ -276,7 +1538,7 @@ impl LogFilters {
         !chars_are_numeric.contains(&false)
     }
 
-    fn find_best_matching_filter_index(&self, words: &[String]) -> isize {
+    fn find_best_matching_filter_index(&self, words: &[Word]) -> isize {
         if self.filters.is_empty() || words.is_empty() {
             return -1;
         }
@@ -300,8 +1562,9 @@ impl LogFilters {
             if max_consequent_matches_indexes.len() > 1 {
                 let mut matching_filters: String = String::new();
                 for filter_index in max_consequent_matches_indexes {
-                    matching_filters += &format!("{:?}, ", self.filters[filter_index]);
+                    matching_filters += &format!("{:?}, ", self.filter_words(filter_index));
                 }
+                let words: Vec<&str> = words.iter().map(|word| word.text).collect();
                 eprintln!(
                     "More than one matching filter found. Words: {:?}; Filters: {}",
                     &words, &matching_filters
@@ -314,59 +1577,173 @@ impl LogFilters {
     }
 
     // TODO: decompose below into smaller and simpler methods
-    fn get_filter_indexes_with_min_req_matches(&self, words: &[String]) -> Vec<usize> {
-        let mut filter_indexes_with_min_req_matches: Vec<usize> = Vec::new();
-        let filters_with_words = self.get_sorted_filter_indexes_containing_words(words);
-        let mut matches: usize = 0;
-        let mut optional_alternatives: usize = 0;
-        let mut prev_index: isize = -1;
-        let mut last_inserted_index: isize = -1;
-        for filter_index in filters_with_words {
-            if last_inserted_index == filter_index as isize {
+    fn get_filter_indexes_with_min_req_matches(&self, words: &[Word]) -> Vec<usize> {
+        let mut candidates = match self.prefix_index {
+            None => self.filters_with_min_req_matches(words, self.count_filter_hits(words, false)),
+            Some(ref prefix_index) => self.filters_with_min_req_matches(
+                words,
+                self.count_indexed_filter_hits(prefix_index, words),
+            ),
+        };
+        if candidates.is_empty() && self.prefix_index.is_none() && self.has_frequent_word(words) {
+            // filters sharing only frequent words with the line are candidates once no other is
+            candidates =
+                self.filters_with_min_req_matches(words, self.count_filter_hits(words, true));
+        }
+        if candidates.len() > MAX_SCORED_CANDIDATES {
+            candidates.sort_by(|(index, hits), (other_index, other_hits)| {
+                other_hits.cmp(hits).then(index.cmp(other_index))
+            });
+            candidates.truncate(MAX_SCORED_CANDIDATES);
+            candidates.sort();
+        }
+
+        candidates
+            .into_iter()
+            .map(|(filter_index, _)| filter_index)
+            .collect()
+    }
+
+    /// Filters out of (filter index, hits) which may match the line, ordered by filter index
+    fn filters_with_min_req_matches(
+        &self,
+        words: &[Word],
+        filter_hits: Vec<(usize, usize)>,
+    ) -> Vec<(usize, usize)> {
+        let mut filters_with_min_req_matches: Vec<(usize, usize)> = Vec::new();
+        for (filter_index, matches) in filter_hits {
+            // alerts only match exactly, see `check_line`
+            if self.is_alert(filter_index) {
                 continue;
             }
-            if prev_index != filter_index as isize {
-                matches = 1;
-                prev_index = filter_index as isize;
-                optional_alternatives = 0;
-                for word_alternatives in &self.filters[filter_index] {
-                    if word_alternatives.contains(&self.denote_optional) {
-                        optional_alternatives += 1;
-                    }
+            let mut optional_alternatives: usize = 0;
+            let mut variable_slots: usize = 0;
+            let mut has_gap = false;
+            for word_alternatives in &self.filters[filter_index] {
+                if word_alternatives.contains(&self.optional_id) {
+                    optional_alternatives += 1;
+                } else if self.is_gap(word_alternatives) {
+                    // gap may match no words at all while any number of words may fall into it
+                    optional_alternatives += 1;
+                    has_gap = true;
+                } else if self.is_variable(word_alternatives) {
+                    // wildcards and types match words which are not part of `words_hash`
+                    variable_slots += 1;
                 }
-            } else {
-                matches += 1;
             }
 
-            if matches as isize >= words.len() as isize - self.max_allowed_new_alternatives as isize
-                && matches as isize
+            if (has_gap
+                || (matches + variable_slots) as isize
+                    >= words.len() as isize - self.max_allowed_new_alternatives as isize)
+                && (matches + variable_slots) as isize
                     >= self.filters[filter_index].len() as isize
                         - self.max_allowed_new_alternatives as isize
                         - optional_alternatives as isize
             {
-                matches = 0;
-                filter_indexes_with_min_req_matches.push(filter_index);
-                last_inserted_index = filter_index as isize;
+                filters_with_min_req_matches.push((filter_index, matches));
             }
         }
 
-        filter_indexes_with_min_req_matches
+        filters_with_min_req_matches
     }
 
-    fn get_sorted_filter_indexes_containing_words(&self, words: &[String]) -> Vec<usize> {
-        let mut filters_with_words: Vec<usize> = Vec::new();
+    /// Number of words of the line each filter contains, as (filter index, hits) ordered
+    /// by filter index. Unless `seed_frequent` is set, frequent words only count towards
+    /// filters containing other words of the line.
+    fn count_filter_hits(&self, words: &[Word], seed_frequent: bool) -> Vec<(usize, usize)> {
+        let mut hits: HashMap<usize, usize> = HashMap::new();
+        let mut frequent_words: Vec<&Vec<usize>> = Vec::new();
         for word in words {
-            if self.words_hash.get(word).is_some() {
-                let vector_indexes = &self.words_hash[word];
-                filters_with_words.extend(vector_indexes);
+            let filter_indexes = match self.word_id(word).and_then(|id| self.words_hash.get(&id)) {
+                None => continue,
+                Some(filter_indexes) => filter_indexes,
+            };
+            if !seed_frequent && self.is_frequent(filter_indexes) {
+                frequent_words.push(filter_indexes);
+                continue;
+            }
+            for filter_index in filter_indexes {
+                *hits.entry(*filter_index).or_insert(0) += 1;
             }
         }
-        filters_with_words.sort();
+        // line made of frequent words only has no other words to find filters by
+        let seed_frequent = hits.is_empty();
+        for filter_indexes in frequent_words {
+            if seed_frequent {
+                for filter_index in filter_indexes {
+                    *hits.entry(*filter_index).or_insert(0) += 1;
+                }
+                continue;
+            }
+            for (filter_index, count) in hits.iter_mut() {
+                if filter_indexes.binary_search(filter_index).is_ok() {
+                    *count += 1;
+                }
+            }
+        }
+        let mut hits: Vec<(usize, usize)> = hits.into_iter().collect();
+        hits.sort();
 
-        filters_with_words
+        hits
     }
 
-    fn count_consequent_matches(&self, words: &[String], filter_index: usize) -> usize {
+    /// Number of words of the line each filter found by `prefix_index` contains, as
+    /// (filter index, hits) ordered by filter index. Filters sharing no word with
+    /// the line are left out like `count_filter_hits` does.
+    fn count_indexed_filter_hits(
+        &self,
+        prefix_index: &PrefixIndex,
+        words: &[Word],
+    ) -> Vec<(usize, usize)> {
+        let prefix: Option<Vec<WordId>> = if words.len() < prefix_index.tokens {
+            None
+        } else {
+            words
+                .iter()
+                .take(prefix_index.tokens)
+                .map(|word| self.word_id(word))
+                .collect()
+        };
+        let filter_indexes: Vec<&Vec<usize>> = words
+            .iter()
+            .filter_map(|word| self.word_id(word).and_then(|id| self.words_hash.get(&id)))
+            .collect();
+        prefix_index
+            .candidates(prefix)
+            .into_iter()
+            .filter(|filter_index| {
+                prefix_index.fits_length(
+                    *filter_index,
+                    words.len(),
+                    self.max_allowed_new_alternatives,
+                )
+            })
+            .map(|filter_index| {
+                let hits = filter_indexes
+                    .iter()
+                    .filter(|indexes| indexes.binary_search(&filter_index).is_ok())
+                    .count();
+                (filter_index, hits)
+            })
+            .filter(|(_, hits)| *hits > 0)
+            .collect()
+    }
+
+    /// Word contained by so many filters that it tells little about the line
+    fn is_frequent(&self, filter_indexes: &[usize]) -> bool {
+        filter_indexes.len() > FREQUENT_WORD_MIN_FILTERS
+            && filter_indexes.len() * FREQUENT_WORD_SHARE > self.filters.len()
+    }
+
+    fn has_frequent_word(&self, words: &[Word]) -> bool {
+        words.iter().any(|word| {
+            self.word_id(word)
+                .and_then(|id| self.words_hash.get(&id))
+                .is_some_and(|filter_indexes| self.is_frequent(filter_indexes))
+        })
+    }
+
+    fn count_consequent_matches(&self, words: &[Word], filter_index: usize) -> usize {
         if self.filters.len() <= filter_index || words.is_empty() {
             return 0;
         }
@@ -393,6 +1770,15 @@ impl LogFilters {
                 if consequent_matches > max_consequent_matches {
                     max_consequent_matches = consequent_matches;
                 }
+            } else if self.filters[filter_index]
+                .get((last_matching_index + 1) as usize)
+                .is_some_and(|word_alternatives| self.is_gap(word_alternatives))
+            {
+                // word falls into the gap following last matching word
+                consequent_matches += 1;
+                if consequent_matches > max_consequent_matches {
+                    max_consequent_matches = consequent_matches;
+                }
             } else {
                 new_alternatives += 1;
                 if new_alternatives
@@ -408,17 +1794,11 @@ impl LogFilters {
 
     fn get_word_index_in_filter(
         &self,
-        word: &str,
+        word: &Word,
         filter_index: usize,
         start_from_word: usize,
     ) -> isize {
-        if word.is_empty() {
-            return -1;
-        }
-        if self.words_hash.get(word).is_none() {
-            return -1;
-        }
-        if !&self.words_hash[word].contains(&filter_index) {
+        if word.text.is_empty() {
             return -1;
         }
         let filter = self.filters.get(filter_index);
@@ -429,203 +1809,951 @@ impl LogFilters {
         if filter.is_empty() || filter.len() - 1 < start_from_word {
             return -1;
         }
+        let word_id = self.word_id(word).filter(|id| {
+            self.words_hash
+                .get(id)
+                .is_some_and(|filter_indexes| filter_indexes.contains(&filter_index))
+        });
+        let is_word_in_filter = word_id.is_some();
+        if !is_word_in_filter && !filter.iter().any(|s| self.is_variable(s)) {
+            return -1;
+        }
 
         for (word_alternative_index, word_alternative) in
             filter.iter().enumerate().skip(start_from_word)
         {
-            if word_alternative.contains(&word.to_owned()) {
+            if word_id.is_some_and(|id| word_alternative.contains(&id))
+                || self.is_wildcard(word_alternative)
+                || self
+                    .slot_type(word_alternative)
+                    .is_some_and(|slot_type| slot_type.matches(word.text))
+            {
                 return word_alternative_index as isize;
             }
         }
-
-        -1
+
+        -1
+    }
+
+    /// Every word matches a column, in order, and every column left is optional or gap
+    fn is_exact_match(&self, words: &[Word], filter_index: usize) -> bool {
+        let filter = match self.filters.get(filter_index) {
+            None => return false,
+            Some(filter) => filter,
+        };
+        let mut matched_slots = vec![false; filter.len()];
+        let mut last_matching_index: isize = -1;
+        for word in words {
+            let matching_index = self.get_word_index_in_filter(
+                word,
+                filter_index,
+                (last_matching_index + 1) as usize,
+            );
+            if matching_index > last_matching_index {
+                last_matching_index = matching_index;
+                matched_slots[matching_index as usize] = true;
+            } else if !filter
+                .get((last_matching_index + 1) as usize)
+                .is_some_and(|word_alternatives| self.is_gap(word_alternatives))
+            {
+                return false;
+            }
+        }
+
+        filter
+            .iter()
+            .zip(matched_slots)
+            .all(|(word_alternatives, matched)| {
+                matched
+                    || word_alternatives.contains(&self.optional_id)
+                    || self.is_gap(word_alternatives)
+            })
+    }
+
+    /// Replace columns between words matching the filter by gap where either the words
+    /// or the columns in between count at least `min_gap_slots`
+    fn introduce_gaps(&mut self, words: &[Word], filter_index: usize) {
+        let mut anchors: Vec<(usize, usize)> = Vec::new();
+        let mut last_matching_index: isize = -1;
+        for (word_index, word) in words.iter().enumerate() {
+            let matching_index = self.get_word_index_in_filter(
+                word,
+                filter_index,
+                (last_matching_index + 1) as usize,
+            );
+            if matching_index > last_matching_index {
+                last_matching_index = matching_index;
+                anchors.push((word_index, matching_index as usize));
+            }
+        }
+        if anchors.is_empty() {
+            return;
+        }
+        anchors.push((words.len(), self.filters[filter_index].len()));
+
+        let mut gaps: Vec<(usize, usize)> = Vec::new();
+        let (mut first_word, mut first_slot) = (0, 0);
+        for (word_index, slot_index) in anchors {
+            let differing = (word_index - first_word).max(slot_index - first_slot);
+            let has_gap = self.filters[filter_index][first_slot..slot_index]
+                .iter()
+                .any(|word_alternatives| self.is_gap(word_alternatives));
+            if differing >= self.min_gap_slots && !has_gap {
+                gaps.push((first_slot, slot_index));
+            }
+            first_word = word_index + 1;
+            first_slot = slot_index + 1;
+        }
+
+        for (first_slot, last_slot) in gaps.into_iter().rev() {
+            let gap = vec![self.gap_id];
+            let removed: Vec<Vec<WordId>> = self.filters[filter_index]
+                .splice(first_slot..last_slot, vec![gap])
+                .collect();
+            for word in removed.into_iter().flatten() {
+                if !self.is_marker_id(word) {
+                    self.remove_from_hash(word, filter_index);
+                }
+            }
+        }
+    }
+
+    // TODO: decompose below into smaller and simpler methods
+    fn update_filter(&mut self, words: &[Word], filter_index: usize) {
+        let mut indexes = self.normalise_lengths_before_first_match(words, filter_index, 0, 0);
+        while indexes.0 >= 0 && indexes.1 >= 0 && words.len() > indexes.0 as usize {
+            let new_indexes = self.normalise_lengths_before_first_match(
+                words,
+                filter_index,
+                indexes.0 as usize,
+                indexes.1 as usize,
+            );
+            if new_indexes.0 == -1 || new_indexes.1 == -1 {
+                break;
+            }
+            if new_indexes.0 != indexes.0 || new_indexes.1 != indexes.1 {
+                indexes = new_indexes;
+            } else {
+                if indexes.0 == words.len() as isize - 1 {
+                    break;
+                }
+                if indexes.1 == self.filters[filter_index].len() as isize - 1 {
+                    break;
+                }
+                indexes.0 += 1;
+                indexes.1 += 1;
+            }
+        }
+        if indexes.0 >= 0 && indexes.1 >= 0 {
+            let filter_length = { self.filters[filter_index].len() };
+            if words.len() > filter_length && indexes.1 == filter_length as isize - 1 {
+                for extra_word in 0..words.len() - filter_length {
+                    let word = self.intern(&words[filter_length + extra_word]);
+                    self.filters[filter_index].push(vec![word, self.optional_id]);
+                    self.update_hash(word, filter_index);
+                }
+            } else if indexes.0 < words.len() as isize {
+                let mut reversed_words = words.to_owned();
+                reversed_words.reverse();
+                self.filters[filter_index].reverse();
+                self.normalise_lengths_before_first_match(&reversed_words, filter_index, 0, 0);
+                self.filters[filter_index].reverse();
+            }
+        }
+        self.generalise_slots(filter_index);
+    }
+
+    // TODO: decompose below into smaller and simpler methods
+    fn normalise_lengths_before_first_match(
+        &mut self,
+        words: &[Word],
+        filter_index: usize,
+        word_start_index: usize,
+        filter_start_index: usize,
+    ) -> (isize, isize) {
+        // returns first index after normalised filter slice
+        let (first_word, first_filter) = self.get_indexes_of_earliest_matching_word(
+            words,
+            filter_index,
+            word_start_index,
+            filter_start_index,
+        );
+        if first_word < 0 || first_filter < 0 {
+            return (-1, -1);
+        }
+        let filters_offset = filter_start_index as isize - word_start_index as isize;
+        if first_word + filters_offset > first_filter {
+            // only words written into the filter are interned
+            let word_ids: Vec<WordId> = words[word_start_index..first_word as usize]
+                .iter()
+                .map(|word| self.intern(word))
+                .collect();
+            let mut front_words = Vec::new();
+            let mut updates: isize = 0;
+            for word in &word_ids {
+                front_words.push(vec![*word, self.optional_id]);
+                updates += 1;
+            }
+            // TODO: check if below can be done in more elegant way
+            {
+                let first_filter = first_filter as usize;
+                let filter = &mut self.filters[filter_index];
+                filter.splice(first_filter..first_filter, front_words);
+            }
+            for word in word_ids {
+                self.update_hash(word, filter_index);
+            }
+
+            (first_word, first_filter + updates)
+        } else {
+            let mut word_ids = Vec::new();
+            {
+                // Mark first filter columns as optional alternatives
+                let filter = &mut self.filters[filter_index];
+                for word_alternatives in filter
+                    .iter_mut()
+                    .take(
+                        (filter_start_index as isize + first_filter - first_word - filters_offset)
+                            as usize,
+                    )
+                    .skip(filter_start_index)
+                {
+                    if !word_alternatives.contains(&self.optional_id) {
+                        word_alternatives.push(self.optional_id);
+                    }
+                }
+                // Add new alternatives if filter length before first match was longer than words index
+                for (word_index, word_alternatives) in
+                    (word_start_index..).zip(filter.iter_mut().take(first_filter as usize).skip(
+                        (filter_start_index as isize + first_filter - first_word - filters_offset)
+                            as usize,
+                    ))
+                {
+                    let word = &words[word_index];
+                    let symbols = &mut self.symbols;
+                    let known_id = word.id.or_else(|| symbols.get(word.text));
+                    let is_contained = known_id.is_some_and(|id| word_alternatives.contains(&id));
+                    // words neither contained nor added stay out of `symbols`
+                    if !is_contained && word_alternatives.contains(&self.wildcard_id) {
+                        continue;
+                    }
+                    let word_id = match known_id {
+                        Some(id) => id,
+                        None => symbols.intern(word.text),
+                    };
+                    if !is_contained {
+                        word_alternatives.push(word_id);
+                    }
+                    word_ids.push(word_id);
+                }
+            }
+            for word in word_ids {
+                self.update_hash(word, filter_index);
+            }
+
+            (first_word, first_filter)
+        }
+    }
+
+    fn get_indexes_of_earliest_matching_word(
+        &self,
+        words: &[Word],
+        filter_index: usize,
+        word_start_index: usize,
+        filter_start_index: usize,
+    ) -> (isize, isize) {
+        if words.len() as isize - 1 < word_start_index as isize
+            || self.filters.get(filter_index).is_none()
+        {
+            return (-1, -1);
+        }
+        if self.filters[filter_index].len() as isize - 1 < filter_start_index as isize {
+            return (-1, -1);
+        }
+
+        let filters_offset = filter_start_index as isize - word_start_index as isize;
+        let mut first_matching_word: isize = -1;
+        let mut first_matching_filter: isize = -1;
+        for (word_index, word) in words.iter().enumerate().skip(word_start_index) {
+            let matching_filter_index = self.get_word_index_in_filter(
+                word,
+                filter_index,
+                (word_start_index as isize + filters_offset) as usize,
+            );
+            if matching_filter_index >= 0
+                && (first_matching_filter == -1 || matching_filter_index < first_matching_filter)
+            {
+                first_matching_filter = matching_filter_index;
+                first_matching_word = word_index as isize;
+            }
+        }
+
+        (first_matching_word, first_matching_filter)
+    }
+
+    fn add_filter(&mut self, words: Vec<Word>) {
+        let mut new_filter = Vec::new();
+        let expected_index: usize = self.filters.len();
+
+        for word in words {
+            if !word.text.is_empty() {
+                new_filter.push(vec![self.intern(&word)]);
+            }
+        }
+        if !new_filter.is_empty() {
+            self.filters.push(new_filter.clone());
+            self.filters_meta.push(FilterMeta::default());
+            for word_alternatives in new_filter {
+                self.update_hash(word_alternatives[0], expected_index);
+            }
+        }
+    }
+
+    /// Settings which make words of `other` incomparable with words of `self`
+    pub fn settings_conflicts(&self, other: &LogFilters) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        if self.ignore_first_columns != other.ignore_first_columns {
+            conflicts.push(format!(
+                "ignore_first_columns: {} != {}",
+                self.ignore_first_columns, other.ignore_first_columns
+            ));
+        }
+        if self.ignore_numeric_words != other.ignore_numeric_words {
+            conflicts.push(format!(
+                "ignore_numeric_words: {} != {}",
+                self.ignore_numeric_words, other.ignore_numeric_words
+            ));
+        }
+        if self.denote_optional != other.denote_optional {
+            conflicts.push(format!(
+                "denote_optional: {} != {}",
+                self.denote_optional, other.denote_optional
+            ));
+        }
+        if self.delimiters != other.delimiters {
+            conflicts.push(format!(
+                "delimiters: {} != {}",
+                self.delimiters(),
+                other.delimiters()
+            ));
+        }
+        if self.learn_transitions
+            && other.learn_transitions
+            && self.transition_stream_column != other.transition_stream_column
+        {
+            conflicts.push(format!(
+                "transition_stream_column: {:?} != {:?}",
+                self.transition_stream_column, other.transition_stream_column
+            ));
+        }
+        // models without rates can be merged with any other
+        if self.rate_bucket_seconds > 0
+            && other.rate_bucket_seconds > 0
+            && self.rate_bucket_seconds != other.rate_bucket_seconds
+        {
+            conflicts.push(format!(
+                "rate_bucket_seconds: {} != {}",
+                self.rate_bucket_seconds, other.rate_bucket_seconds
+            ));
+        }
+
+        conflicts
+    }
+
+    /// Fold filters of `other` into `self`, nothing is merged if settings conflict
+    pub fn merge(&mut self, other: &LogFilters) -> Result<MergeReport, String> {
+        let conflicts = self.settings_conflicts(other);
+        if !conflicts.is_empty() {
+            return Err(format!("Settings conflict: {}", conflicts.join(", ")));
+        }
+
+        // models usually cover the same period on different hosts
+        if self.rate_bucket_seconds == 0 {
+            self.rate_bucket_seconds = other.rate_bucket_seconds;
+        }
+        self.rate_buckets = self.rate_buckets.max(other.rate_buckets);
+        if !self.learn_transitions {
+            self.learn_transitions = other.learn_transitions;
+            self.transition_stream_column = other.transition_stream_column;
+        }
+        let mut report = MergeReport::default();
+        // transitions refer to indexes of `other` so are added once all filters are merged
+        let mut merged_indexes: Vec<Option<usize>> = Vec::new();
+        let mut transitions: Vec<Vec<(usize, usize)>> = Vec::new();
+        // words are interned by each model on its own so filters are merged as words
+        for filter_index in 0..other.filters.len() {
+            let mut filter_meta = other.filter_meta(filter_index);
+            transitions.push(::std::mem::take(&mut filter_meta.next));
+            let filter = other.filter_words(filter_index);
+            let (merged_index, is_unified) = self.merge_filter(&filter, filter_meta);
+            merged_indexes.push(Some(merged_index));
+            if is_unified {
+                report.unified += 1;
+            } else {
+                report.appended += 1;
+            }
+        }
+        for (filter_index, next) in transitions.into_iter().enumerate() {
+            if let Some(merged_index) = merged_indexes[filter_index] {
+                self.add_transitions(merged_index, next, &merged_indexes);
+            }
+        }
+        // unified filters may have replaced alternatives by a wildcard or a type
+        self.compact_symbols();
+
+        Ok(report)
+    }
+
+    /// Model sharing settings, filters and words with `self` but none of what
+    /// learning counted, lines learned into it are added by `merge_shard`
+    pub fn shard(&self) -> LogFilters {
+        let mut shard = self.clone();
+        for filter_meta in &mut shard.filters_meta {
+            filter_meta.hits = 0;
+            filter_meta.bucket_hits = 0;
+            filter_meta.next.clear();
+        }
+        shard.rate_buckets = 0;
+        shard.break_sequence();
+
+        shard
+    }
+
+    /// Add what `shard` learned, see `shard`. Time windows of the shard are not
+    /// counted, lines of all shards have to be passed to `count_rate_bucket` instead.
+    pub fn merge_shard(&mut self, shard: &LogFilters) {
+        let rate_buckets = self.rate_buckets;
+        if let Err(why) = self.merge(shard) {
+            panic!("Couldn't merge shard: {}", why);
+        }
+        self.rate_buckets = rate_buckets;
+    }
+
+    /// Next learned line does not follow the last one, so no transition is counted between them
+    pub fn break_sequence(&mut self) {
+        self.last_stream_filters.clear();
+    }
+
+    /// Index of the filter `filter` ended up in and `true` if it was unified with one of existing filters
+    fn merge_filter(&mut self, filter: &[Vec<String>], filter_meta: FilterMeta) -> (usize, bool) {
+        // rules are kept as written, only the same rule is unified
+        if filter_meta.manual {
+            let same_rule = (0..self.filters.len()).find(|index| {
+                self.filter_meta(*index).manual && self.filter_words(*index) == filter
+            });
+            return match same_rule {
+                None => {
+                    self.append_filter(filter.to_vec(), filter_meta);
+                    (self.filters.len() - 1, false)
+                }
+                Some(filter_index) => {
+                    self.filters_meta[filter_index].absorb(&filter_meta);
+                    (filter_index, true)
+                }
+            };
+        }
+
+        // each slot is represented by a word, preferably one known already
+        let mut words: Vec<String> = Vec::new();
+        let mut required_words: Vec<String> = Vec::new();
+        for word_alternatives in filter {
+            let mut candidates = word_alternatives
+                .iter()
+                .filter(|word| !self.is_marker(word));
+            let first_word = candidates.clone().next();
+            match candidates
+                .find(|word| self.word_filters(word).is_some())
+                .or(first_word)
+            {
+                None => continue,
+                Some(word) => {
+                    if !word_alternatives.contains(&self.denote_optional) {
+                        required_words.push(word.clone());
+                    }
+                    words.push(word.clone())
+                }
+            }
+        }
+
+        // optional slots must not prevent the match, `update_filter` adds them afterwards
+        if required_words.is_empty() {
+            required_words = words.clone();
+        }
+        let required_words: Vec<Word> = required_words
+            .iter()
+            .map(|word| self.word_of(word))
+            .collect();
+        let matched_filter_index = self.find_best_matching_filter_index(&required_words);
+        if matched_filter_index < 0 || self.filter_meta(matched_filter_index as usize).manual {
+            self.append_filter(filter.to_vec(), filter_meta);
+            return (self.filters.len() - 1, false);
+        }
+
+        let filter_index = matched_filter_index as usize;
+        let line_words: Vec<Word> = words.iter().map(|word| self.word_of(word)).collect();
+        self.update_filter(&line_words, filter_index);
+        let mut last_matching_index: isize = -1;
+        for word_alternatives in filter {
+            let word = match word_alternatives
+                .iter()
+                .find(|word| words.contains(word) && !self.is_marker(word))
+            {
+                None => continue,
+                Some(word) => word,
+            };
+            let matching_index = self.get_word_index_in_filter(
+                &self.word_of(word),
+                filter_index,
+                (last_matching_index + 1) as usize,
+            );
+            if matching_index < 0 {
+                continue;
+            }
+            last_matching_index = matching_index;
+            for alternative in word_alternatives {
+                let alternative_id = self.symbols.intern(alternative);
+                let existing_alternatives =
+                    &mut self.filters[filter_index][matching_index as usize];
+                if !existing_alternatives.contains(&alternative_id) {
+                    existing_alternatives.push(alternative_id);
+                }
+                if !self.is_marker(alternative) {
+                    self.update_hash(alternative_id, filter_index);
+                }
+            }
+        }
+        if let Some(existing_meta) = self.filters_meta.get_mut(filter_index) {
+            existing_meta.absorb(&filter_meta);
+        }
+        self.generalise_slots(filter_index);
+        self.filter_changed(filter_index);
+
+        (filter_index, true)
+    }
+
+    /// Add transitions `next` of a filter now stored under `filter_index`, `new_indexes`
+    /// holds current index of each filter `next` refers to, `None` if it was dropped
+    fn add_transitions(
+        &mut self,
+        filter_index: usize,
+        next: Vec<(usize, usize)>,
+        new_indexes: &[Option<usize>],
+    ) {
+        for (next_index, count) in next {
+            if let Some(Some(next_index)) = new_indexes.get(next_index) {
+                self.filters_meta[filter_index].add_next(*next_index, count);
+            }
+        }
+    }
+
+    pub(crate) fn append_filter(&mut self, filter: Vec<Vec<String>>, filter_meta: FilterMeta) {
+        let filter_index = self.filters.len();
+        let filter: Vec<Vec<WordId>> = filter
+            .iter()
+            .map(|word_alternatives| {
+                word_alternatives
+                    .iter()
+                    .map(|word| self.symbols.intern(word))
+                    .collect()
+            })
+            .collect();
+        if filter_meta.alert.is_some() {
+            self.alert_filters.push(filter_index);
+        }
+        self.filters.push(filter.clone());
+        self.filters_meta.push(filter_meta);
+        for word in filter.into_iter().flatten() {
+            if !self.is_marker_id(word) {
+                self.update_hash(word, filter_index);
+            }
+        }
+        self.generalise_slots(filter_index);
+        self.filter_changed(filter_index);
     }
 
-    // TODO: decompose below into smaller and simpler methods
-    fn update_filter(&mut self, words: &[String], filter_index: usize) {
-        let mut indexes = self.normalise_lengths_before_first_match(&words, filter_index, 0, 0);
-        while indexes.0 >= 0 && indexes.1 >= 0 && words.len() > indexes.0 as usize {
-            let new_indexes = self.normalise_lengths_before_first_match(
-                &words,
-                filter_index,
-                indexes.0 as usize,
-                indexes.1 as usize,
-            );
-            if new_indexes.0 == -1 || new_indexes.1 == -1 {
-                break;
+    /// Merge filters of the same length whose slots differ in at most
+    /// `max_differing_slots` slots, then drop filters with less than `min_hits` hits.
+    /// Rules are neither merged nor dropped.
+    pub fn prune(
+        &mut self,
+        max_differing_slots: usize,
+        min_hits: usize,
+    ) -> Result<PruneReport, String> {
+        if min_hits > 0
+            && self
+                .filters_meta
+                .iter()
+                .all(|filter_meta| filter_meta.hits == 0)
+        {
+            return Err("Filters carry no hits, cannot drop filters by hits".to_string());
+        }
+
+        // (filter, details, indexes of source filters)
+        let mut compacted: Vec<(Vec<Vec<WordId>>, FilterMeta, Vec<usize>)> = Vec::new();
+        let mut compacted_by_length: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (filter_index, filter) in self.filters.iter().enumerate() {
+            let filter_meta = self.filter_meta(filter_index);
+            if filter_meta.manual {
+                compacted.push((filter.clone(), filter_meta, vec![filter_index]));
+                continue;
             }
-            if new_indexes.0 != indexes.0 || new_indexes.1 != indexes.1 {
-                indexes = new_indexes;
-            } else {
-                if indexes.0 == words.len() as isize - 1 {
-                    break;
+            let similar = compacted_by_length
+                .get(&filter.len())
+                .and_then(|candidates| {
+                    candidates.iter().cloned().find(|candidate| {
+                        LogFilters::count_differing_slots(
+                            &compacted[*candidate].0,
+                            filter,
+                            max_differing_slots,
+                        ) <= max_differing_slots
+                    })
+                });
+            match similar {
+                None => {
+                    compacted_by_length
+                        .entry(filter.len())
+                        .or_default()
+                        .push(compacted.len());
+                    compacted.push((filter.clone(), filter_meta, vec![filter_index]));
                 }
-                if indexes.1 == self.filters[filter_index].len() as isize - 1 {
-                    break;
+                Some(similar) => {
+                    let (ref mut similar_filter, ref mut similar_meta, ref mut sources) =
+                        compacted[similar];
+                    for (slot, word_alternatives) in filter.iter().enumerate() {
+                        for word in word_alternatives {
+                            if !similar_filter[slot].contains(word) {
+                                similar_filter[slot].push(*word);
+                            }
+                        }
+                    }
+                    similar_meta.absorb(&filter_meta);
+                    sources.push(filter_index);
                 }
-                indexes.0 += 1;
-                indexes.1 += 1;
             }
         }
-        if indexes.0 >= 0 && indexes.1 >= 0 {
-            let filter_length = { self.filters[filter_index].len() };
-            if words.len() > filter_length && indexes.1 == filter_length as isize - 1 {
-                for extra_word in 0..words.len() - filter_length {
-                    {
-                        let filter = &mut self.filters[filter_index];
-                        filter.push(vec![
-                            words[filter_length + extra_word].clone(),
-                            self.denote_optional.clone(),
-                        ]);
-                    }
-                    self.update_hash(&words[filter_length + extra_word].clone(), filter_index);
-                }
-            } else if indexes.0 < words.len() as isize {
-                let mut reversed_words = words.to_owned();
-                reversed_words.reverse();
-                self.filters[filter_index].reverse();
-                self.normalise_lengths_before_first_match(&reversed_words, filter_index, 0, 0);
-                self.filters[filter_index].reverse();
+
+        let mut report = PruneReport::default();
+        let mut new_indexes: Vec<Option<usize>> = vec![None; self.filters.len()];
+        self.filters = Vec::new();
+        self.filters_meta = Vec::new();
+        for (filter, filter_meta, sources) in compacted {
+            if filter_meta.hits < min_hits && !filter_meta.manual {
+                report.dropped.extend(sources);
+                continue;
+            }
+            for source in &sources {
+                new_indexes[*source] = Some(self.filters.len());
+            }
+            if sources.len() > 1 {
+                report.merged.push((self.filters.len(), sources));
             }
+            self.filters.push(filter);
+            self.filters_meta.push(filter_meta);
+            self.generalise_slots(self.filters.len() - 1);
         }
+        report.dropped.sort();
+        self.alert_filters = (0..self.filters.len())
+            .filter(|filter_index| self.is_alert(*filter_index))
+            .collect();
+        self.rebuild_hash();
+        // words of merged and dropped filters go, `prefix_index` is rebuilt as well
+        self.compact_symbols();
+        for filter_index in 0..self.filters_meta.len() {
+            let next = ::std::mem::take(&mut self.filters_meta[filter_index].next);
+            self.add_transitions(filter_index, next, &new_indexes);
+        }
+
+        Ok(report)
     }
 
-    // TODO: decompose below into smaller and simpler methods
-    fn normalise_lengths_before_first_match(
-        &mut self,
-        words: &[String],
-        filter_index: usize,
-        word_start_index: usize,
-        filter_start_index: usize,
-    ) -> (isize, isize) {
-        // returns first index after normalised filter slice
-        let (first_word, first_filter) = self.get_indexes_of_earliest_matching_word(
-            &words,
-            filter_index,
-            word_start_index,
-            filter_start_index,
-        );
-        if first_word < 0 || first_filter < 0 {
-            return (-1, -1);
-        }
-        let filters_offset = filter_start_index as isize - word_start_index as isize;
-        if first_word + filters_offset > first_filter {
-            let mut front_words = Vec::new();
-            let mut updates: isize = 0;
-            for word in &words[word_start_index..first_word as usize] {
-                front_words.push(vec![word.clone(), self.denote_optional.clone()]);
-                updates += 1;
-            }
-            // TODO: check if below can be done in more elegant way
+    /// Slots are equal if alternatives of one include all alternatives of the other,
+    /// counting stops once `limit` is exceeded
+    fn count_differing_slots(filter: &[Vec<WordId>], other: &[Vec<WordId>], limit: usize) -> usize {
+        let mut differing_slots = 0;
+        for (word_alternatives, other_alternatives) in filter.iter().zip(other) {
+            if word_alternatives
+                .iter()
+                .all(|w| other_alternatives.contains(w))
+                || other_alternatives
+                    .iter()
+                    .all(|w| word_alternatives.contains(w))
             {
-                let first_filter = first_filter as usize;
-                let filter = &mut self.filters[filter_index];
-                filter.splice(first_filter..first_filter, front_words);
+                continue;
             }
-            for word in &words[word_start_index..first_word as usize] {
-                self.update_hash(&word, filter_index);
+            differing_slots += 1;
+            if differing_slots > limit {
+                break;
             }
+        }
 
-            (first_word, first_filter + updates)
+        differing_slots
+    }
+
+    pub fn prefix_index_tokens(&self) -> usize {
+        self.prefix_index
+            .as_ref()
+            .map_or(0, |prefix_index| prefix_index.tokens)
+    }
+
+    /// Find candidate filters by the first `tokens` words of the line and by its
+    /// number of words instead of by every word, 0 goes back to `words_hash`.
+    /// Filters whose leading columns are optional, variable or gaps are candidates
+    /// for every line. Lines which differ from a filter within the first `tokens`
+    /// words never match it, see Readme for the trade-offs.
+    pub fn set_prefix_index_tokens(&mut self, tokens: usize) {
+        self.prefix_index = if tokens == 0 {
+            None
         } else {
+            Some(PrefixIndex::new(tokens))
+        };
+        // lines may match other filters than before
+        self.generation += 1;
+        for filter_index in 0..self.filters.len() {
+            self.filter_changed(filter_index);
+        }
+    }
+
+    /// Keep `generation` and `prefix_index` up to date once the filter changed
+    fn filter_changed(&mut self, filter_index: usize) {
+        self.generation += 1;
+        let tokens = match self.prefix_index {
+            None => return,
+            Some(_) if filter_index >= self.filters.len() => return,
+            Some(ref prefix_index) => prefix_index.tokens,
+        };
+        let keys = self.prefix_keys(filter_index, tokens);
+        let lengths = self.filter_lengths(filter_index);
+        if let Some(ref mut prefix_index) = self.prefix_index {
+            prefix_index.set_filter(filter_index, keys, lengths);
+        }
+    }
+
+    /// Each combination of alternatives of the first `tokens` columns, none if any
+    /// of those columns accepts words it does not list or may be missing
+    fn prefix_keys(&self, filter_index: usize, tokens: usize) -> Vec<Vec<WordId>> {
+        let filter = &self.filters[filter_index];
+        if filter.len() < tokens {
+            return Vec::new();
+        }
+        let mut keys: Vec<Vec<WordId>> = vec![Vec::new()];
+        for word_alternatives in filter.iter().take(tokens) {
+            if self.is_optional(word_alternatives)
+                || self.is_gap(word_alternatives)
+                || self.is_variable(word_alternatives)
             {
-                // Mark first filter columns as optional alternatives
-                let filter = &mut self.filters[filter_index];
-                for word_alternatives in filter
-                    .iter_mut()
-                    .take(
-                        (filter_start_index as isize + first_filter - first_word - filters_offset)
-                            as usize,
-                    )
-                    .skip(filter_start_index)
-                {
-                    if !word_alternatives.contains(&self.denote_optional) {
-                        word_alternatives.push(self.denote_optional.clone());
-                    }
-                }
-                // Add new alternatives if filter length before first match was longer than words index
-                let mut word_index: usize = word_start_index;
-                for word_alternatives in filter.iter_mut().take(first_filter as usize).skip(
-                    (filter_start_index as isize + first_filter - first_word - filters_offset)
-                        as usize,
-                ) {
-                    if !word_alternatives.contains(&words[word_index]) {
-                        word_alternatives.push(words[word_index].clone());
-                    }
-                    word_index += 1;
-                }
+                return Vec::new();
             }
-            for word in words
+            let words: Vec<WordId> = word_alternatives
                 .iter()
-                .take(first_word as usize)
-                .skip(word_start_index)
-            {
-                self.update_hash(&word, filter_index);
+                .filter(|word| !self.is_marker_id(**word))
+                .cloned()
+                .collect();
+            if words.is_empty() || keys.len() * words.len() > MAX_PREFIX_KEYS {
+                return Vec::new();
             }
-
-            (first_word, first_filter)
+            keys = keys
+                .iter()
+                .flat_map(|key| {
+                    words.iter().map(move |word| {
+                        let mut key = key.clone();
+                        key.push(*word);
+                        key
+                    })
+                })
+                .collect();
         }
+
+        keys
     }
 
-    fn get_indexes_of_earliest_matching_word(
-        &self,
-        words: &[String],
-        filter_index: usize,
-        word_start_index: usize,
-        filter_start_index: usize,
-    ) -> (isize, isize) {
-        if words.len() as isize - 1 < word_start_index as isize
-            || self.filters.get(filter_index).is_none()
+    /// Number of words the filter requires and accepts, `None` if a gap accepts any
+    fn filter_lengths(&self, filter_index: usize) -> (usize, Option<usize>) {
+        let filter = &self.filters[filter_index];
+        let required = filter
+            .iter()
+            .filter(|word_alternatives| {
+                !self.is_optional(word_alternatives) && !self.is_gap(word_alternatives)
+            })
+            .count();
+        if filter
+            .iter()
+            .any(|word_alternatives| self.is_gap(word_alternatives))
         {
-            return (-1, -1);
+            (required, None)
+        } else {
+            (required, Some(filter.len()))
         }
-        if self.filters[filter_index].len() as isize - 1 < filter_start_index as isize {
-            return (-1, -1);
+    }
+
+    fn rebuild_hash(&mut self) {
+        self.words_hash = HashMap::new();
+        for filter_index in 0..self.filters.len() {
+            let words: Vec<WordId> = self.filters[filter_index]
+                .iter()
+                .flatten()
+                .filter(|word| !self.is_marker_id(**word))
+                .cloned()
+                .collect();
+            for word in words {
+                self.update_hash(word, filter_index);
+            }
         }
+    }
 
-        let filters_offset = filter_start_index as isize - word_start_index as isize;
-        let mut first_matching_word: isize = -1;
-        let mut first_matching_filter: isize = -1;
-        for (word_index, word) in words.iter().enumerate().skip(word_start_index) {
-            let matching_filter_index = self.get_word_index_in_filter(
-                &word,
-                filter_index,
-                (word_start_index as isize + filters_offset) as usize,
-            );
-            if matching_filter_index >= 0
-                && (first_matching_filter == -1 || matching_filter_index < first_matching_filter)
-            {
-                first_matching_filter = matching_filter_index;
-                first_matching_word = word_index as isize;
+    /// Intern anew only markers and words `filters` hold, so words left behind by
+    /// alternatives replaced with a wildcard or a type, by pruning or by merging
+    /// are freed. Identifiers of the words kept change.
+    fn compact_symbols(&mut self) {
+        let mut symbols = Symbols::default();
+        let mut new_ids: Vec<Option<WordId>> = vec![None; self.symbols.len()];
+        for id in &[
+            self.optional_id,
+            self.wildcard_id,
+            self.gap_id,
+            self.vary_id,
+        ] {
+            new_ids[*id as usize] = Some(symbols.intern(self.symbols.word(*id)));
+        }
+        for filter in &mut self.filters {
+            for word in filter.iter_mut().flatten() {
+                let new_id = match new_ids[*word as usize] {
+                    Some(new_id) => new_id,
+                    None => symbols.intern(self.symbols.word(*word)),
+                };
+                new_ids[*word as usize] = Some(new_id);
+                *word = new_id;
             }
         }
+        self.words_hash = mem::take(&mut self.words_hash)
+            .into_iter()
+            .filter_map(|(word, filter_indexes)| {
+                new_ids[word as usize].map(|new_id| (new_id, filter_indexes))
+            })
+            .collect();
+        self.symbols = symbols;
+        self.compacted_symbols = self.symbols.len();
+        *self = mem::take(self).with_markers();
+        // keys of `prefix_index` are word identifiers
+        let prefix_index_tokens = self.prefix_index_tokens();
+        self.set_prefix_index_tokens(prefix_index_tokens);
+    }
 
-        (first_matching_word, first_matching_filter)
+    /// `denote_optional` and every marker derived from it can never be a word
+    pub(crate) fn is_marker(&self, word: &str) -> bool {
+        word.starts_with(&self.denote_optional)
     }
 
-    fn add_filter(&mut self, words: Vec<String>) {
-        let mut new_filter = Vec::new();
-        let expected_index: usize = self.filters.len();
+    pub(crate) fn is_marker_id(&self, word: WordId) -> bool {
+        self.is_marker(self.word(word))
+    }
 
-        for word in words {
-            if !word.is_empty() {
-                new_filter.push(vec![word]);
+    pub(crate) fn is_optional(&self, word_alternatives: &[WordId]) -> bool {
+        word_alternatives.contains(&self.optional_id)
+    }
+
+    pub(crate) fn is_wildcard(&self, word_alternatives: &[WordId]) -> bool {
+        word_alternatives.contains(&self.wildcard_id)
+    }
+
+    pub(crate) fn is_gap(&self, word_alternatives: &[WordId]) -> bool {
+        word_alternatives.contains(&self.gap_id)
+    }
+
+    /// Column accepts words not listed among its alternatives
+    pub(crate) fn is_variable(&self, word_alternatives: &[WordId]) -> bool {
+        self.is_wildcard(word_alternatives) || self.slot_type(word_alternatives).is_some()
+    }
+
+    pub(crate) fn slot_type(&self, word_alternatives: &[WordId]) -> Option<SlotType> {
+        word_alternatives
+            .iter()
+            .filter_map(|word| SlotType::from_marker(self.word(*word), &self.denote_optional))
+            .next()
+    }
+
+    /// Replace alternatives of columns with wildcard once there are more than
+    /// `max_slot_alternatives` of them, or with their common type if `infer_slot_types` is set
+    fn generalise_slots(&mut self, filter_index: usize) {
+        if self.filter_meta(filter_index).manual {
+            return;
+        }
+        let mut removed_words: Vec<WordId> = Vec::new();
+        let symbols = &mut self.symbols;
+        let denote_optional = &self.denote_optional;
+        let max_slot_alternatives = self.max_slot_alternatives;
+        let infer_slot_types = self.infer_slot_types;
+        let optional_id = self.optional_id;
+        let wildcard_id = self.wildcard_id;
+        let gap_id = self.gap_id;
+        let vary_id = self.vary_id;
+        if let Some(filter) = self.filters.get_mut(filter_index) {
+            for word_alternatives in filter.iter_mut() {
+                let words_count = word_alternatives
+                    .iter()
+                    .filter(|word| !symbols.word(**word).starts_with(denote_optional))
+                    .count();
+                if words_count == 0 {
+                    continue;
+                }
+                let marker = if word_alternatives.contains(&wildcard_id)
+                    || (max_slot_alternatives > 0 && words_count > max_slot_alternatives)
+                {
+                    wildcard_id
+                } else if !infer_slot_types {
+                    continue;
+                } else {
+                    let words: Vec<&str> = word_alternatives
+                        .iter()
+                        .map(|word| symbols.word(*word))
+                        .collect();
+                    match common_slot_type(&words, denote_optional) {
+                        None => continue,
+                        Some(slot_type) => symbols.intern(&slot_type.marker(denote_optional)),
+                    }
+                };
+                let is_optional = word_alternatives.contains(&optional_id);
+                let is_varying = word_alternatives.contains(&vary_id);
+                removed_words.extend(
+                    word_alternatives
+                        .drain(..)
+                        .filter(|word| !symbols.word(*word).starts_with(denote_optional)),
+                );
+                word_alternatives.push(marker);
+                if is_varying {
+                    word_alternatives.push(vary_id);
+                }
+                if is_optional {
+                    word_alternatives.push(optional_id);
+                }
             }
+            removed_words.extend(collapse_gaps(filter, optional_id, gap_id));
         }
-        if !new_filter.is_empty() {
-            self.filters.push(new_filter.clone());
-            for word_alternatives in new_filter {
-                self.update_hash(&word_alternatives[0], expected_index);
+        for word in removed_words {
+            if !self.is_marker_id(word) {
+                self.remove_from_hash(word, filter_index);
             }
         }
     }
 
-    fn update_hash(&mut self, word: &str, filter_index: usize) {
+    fn remove_from_hash(&mut self, word: WordId, filter_index: usize) {
         if self.is_word_in_filter(word, filter_index) {
-            self.words_hash
-                .entry(word.to_owned())
-                .or_insert(vec![filter_index]);
-            let vector_indexes = self.words_hash.get_mut(word).unwrap();
+            return;
+        }
+        let is_referenced = match self.words_hash.get_mut(&word) {
+            None => return,
+            Some(vector_indexes) => {
+                vector_indexes.retain(|index| *index != filter_index);
+                !vector_indexes.is_empty()
+            }
+        };
+        if !is_referenced {
+            self.words_hash.remove(&word);
+        }
+    }
+
+    fn update_hash(&mut self, word: WordId, filter_index: usize) {
+        if self.is_word_in_filter(word, filter_index) {
+            let vector_indexes = self.words_hash.entry(word).or_default();
             if !vector_indexes.contains(&filter_index) {
                 vector_indexes.push(filter_index);
                 vector_indexes.sort();
@@ -633,29 +2761,84 @@ impl LogFilters {
         }
     }
 
-    fn is_word_in_filter(&self, word: &str, filter_index: usize) -> bool {
-        let filter = self.filters.get(filter_index);
-        if filter.is_none() {
-            return false;
+    fn is_word_in_filter(&self, word: WordId, filter_index: usize) -> bool {
+        match self.filters.get(filter_index) {
+            None => false,
+            Some(filter) => filter
+                .iter()
+                .any(|word_alternatives| word_alternatives.contains(&word)),
         }
+    }
+}
 
-        let filter = filter.unwrap();
-        for word_alternatives in filter {
-            if word_alternatives.contains(&word.to_owned()) {
-                return true;
+/// Drop optional columns and gaps next to a gap, returns words removed from the filter
+fn collapse_gaps(
+    filter: &mut Vec<Vec<WordId>>,
+    optional_id: WordId,
+    gap_id: WordId,
+) -> Vec<WordId> {
+    let mut removed_words: Vec<WordId> = Vec::new();
+    let is_gap = |word_alternatives: &Vec<WordId>| word_alternatives.contains(&gap_id);
+
+    // gap matches any words which optional columns and other gaps next to it would
+    let mut slot_index = 0;
+    while slot_index < filter.len() {
+        if is_gap(&filter[slot_index]) {
+            if slot_index + 1 < filter.len()
+                && (is_gap(&filter[slot_index + 1])
+                    || filter[slot_index + 1].contains(&optional_id))
+            {
+                removed_words.extend(filter.remove(slot_index + 1));
+                continue;
+            }
+            if slot_index > 0 && filter[slot_index - 1].contains(&optional_id) {
+                removed_words.extend(filter.remove(slot_index - 1));
+                slot_index -= 1;
+                continue;
             }
         }
+        slot_index += 1;
+    }
+
+    removed_words
+}
 
-        false
+/// Narrowest type of all words and typed markers of the column, single word
+/// is a constant rather than a type so at least two values are required
+fn common_slot_type(word_alternatives: &[&str], denote_optional: &str) -> Option<SlotType> {
+    let denote_vary = denote_optional.to_string() + "~";
+    let mut slot_type: Option<SlotType> = None;
+    let mut values: usize = 0;
+    for word in word_alternatives
+        .iter()
+        .filter(|word| **word != denote_optional && **word != denote_vary)
+    {
+        let word_type = match SlotType::from_marker(word, denote_optional) {
+            None => SlotType::of(word)?,
+            Some(marker_type) => marker_type,
+        };
+        slot_type = match slot_type {
+            None => Some(word_type),
+            Some(slot_type) => Some(slot_type.join(word_type)?),
+        };
+        values += 1;
+    }
+    if values < 2 {
+        return None;
     }
+
+    slot_type
 }
 
-#[cfg(feature = "tst_utils")]
+#[cfg(any(test, feature = "tst_utils"))]
 pub mod tst_utils {
     use super::*;
 
-    pub fn _words_vector_from_string(words: &str) -> Vec<String> {
-        LogFilters::line_split(words)
+    pub(crate) fn _words_vector_from_string<'a>(words: &'a str) -> Vec<Word<'a>> {
+        let delimiters: Vec<char> = DEFAULT_DELIMITERS.chars().collect();
+        LogFilters::split_words(words, &delimiters)
+            .map(|text| Word { id: None, text })
+            .collect()
     }
 
     pub fn _simple_filter_from_string(words: &str) -> Vec<Vec<String>> {
@@ -665,7 +2848,7 @@ pub mod tst_utils {
         for word in words_vec {
             filter.push(vec![word.to_string()]);
         }
-        return filter;
+        filter
     }
 
     pub fn _add_word_alternative(
@@ -675,7 +2858,7 @@ pub mod tst_utils {
     ) -> Vec<Vec<String>> {
         if filter.get(index).is_some() {
             filter.get_mut(index).unwrap().push(word.to_string());
-            return filter;
+            filter
         } else {
             panic!(
                 "Failed to create test data! Extending {:?} at {}",
@@ -686,21 +2869,23 @@ pub mod tst_utils {
 
     pub fn _add_test_filter(test_filters: &mut LogFilters, filter: Vec<Vec<String>>) {
         let next_filter_index = test_filters.filters.len();
-        for word_alternatives in &filter {
-            for word in word_alternatives {
-                if test_filters.words_hash.get(word).is_some() {
-                    let filter_indexes = test_filters.words_hash.get_mut(word).unwrap();
-                    if !filter_indexes.contains(&next_filter_index) {
-                        filter_indexes.push(next_filter_index);
-                    }
-                } else {
-                    test_filters
-                        .words_hash
-                        .insert(word.clone(), vec![next_filter_index]);
-                }
+        let filter: Vec<Vec<WordId>> = filter
+            .iter()
+            .map(|word_alternatives| {
+                word_alternatives
+                    .iter()
+                    .map(|word| test_filters.symbols.intern(word))
+                    .collect()
+            })
+            .collect();
+        for word in filter.iter().flatten() {
+            let filter_indexes = test_filters.words_hash.entry(*word).or_default();
+            if !filter_indexes.contains(&next_filter_index) {
+                filter_indexes.push(next_filter_index);
             }
         }
         test_filters.filters.push(filter);
+        test_filters.filters_meta.push(FilterMeta::default());
     }
 
     pub fn _init_test_data() -> LogFilters {
@@ -729,13 +2914,23 @@ pub mod tst_utils {
             &mut log_filters,
             _simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv"),
         );
-        return log_filters;
+        log_filters
     }
 }
 
 #[cfg(test)]
+// tests of the first release are kept as they were written
+#[allow(
+    clippy::bool_assert_comparison,
+    clippy::needless_borrow,
+    clippy::unnecessary_cast,
+    clippy::unnecessary_get_then_check,
+    clippy::unnecessary_to_owned,
+    clippy::useless_vec
+)]
 mod tests {
     use super::*;
+    use input::InvalidLinePolicy;
 
     #[test]
     fn line_split() {
@@ -892,7 +3087,255 @@ mod tests {
     }
 
     #[test]
-    fn from_str_lines() {
+    fn load_settings() {
+        let mut log_filters = LogFilters::new();
+        log_filters.load_settings(&[
+            "",
+            "delimiters= ,.[]",
+            "max_slot_alternatives=20",
+            "infer_slot_types=true",
+            "rate_bucket_seconds=3600",
+            "rate_buckets=24",
+            "prefix_index_tokens=2",
+            "[a],[b]",
+        ]);
+        assert_eq!(log_filters.rate_bucket_seconds, 3600);
+        assert_eq!(log_filters.rate_buckets, 24);
+        assert!(log_filters.infer_slot_types);
+        assert_eq!(log_filters.delimiters(), " ,.[]");
+        assert_eq!(log_filters.max_slot_alternatives, 20);
+        assert_eq!(log_filters.prefix_index_tokens(), 2);
+        // Filters are not settings
+        let mut log_filters = LogFilters::new();
+        log_filters.load_settings(&["[a],[b]", "delimiters= ,.[]"]);
+        assert_eq!(log_filters.delimiters(), DEFAULT_DELIMITERS);
+    }
+
+    #[test]
+    fn text_with_alerts() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 3;
+        log_filters.learn_transitions = true;
+        log_filters.learn_line("Sep 26 09:13:15 host systemd: Starting backup");
+        log_filters.append_filter(
+            vec![
+                vec![".**".to_string()],
+                vec!["segfault".to_string()],
+                vec![".**".to_string()],
+            ],
+            FilterMeta {
+                manual: true,
+                alert: Some("segfault".to_string()),
+                ..Default::default()
+            },
+        );
+        log_filters.learn_line("Sep 26 09:13:16 host systemd: Started backup");
+        log_filters.learn_line("Sep 26 09:13:17 host systemd: Starting backup");
+        assert_eq!(log_filters.transition_count(0, 2), 1);
+        assert_eq!(log_filters.transition_count(2, 0), 1);
+
+        // alerts are not saved so transitions refer to filters following them as saved
+        let loaded = LogFilters::from_text(&log_filters.to_text());
+        assert_eq!(loaded.filters_count(), 2);
+        assert_eq!(loaded.filter_to_string(1), log_filters.filter_to_string(2));
+        assert_eq!(loaded.transition_count(0, 1), 1);
+        assert_eq!(loaded.transition_count(1, 0), 1);
+        assert_eq!(loaded.to_text(), log_filters.to_text());
+    }
+
+    #[test]
+    fn binary() {
+        let mut log_filters = LogFilters::new();
+        log_filters.max_allowed_new_alternatives = 1;
+        log_filters.set_prefix_index_tokens(1);
+        log_filters.learn_line("Sep 26 09:13:15 host sshd[1]: Accepted publickey for alice");
+        log_filters.learn_line("Sep 26 09:13:16 host sshd[2]: Accepted publickey for bob");
+        log_filters.learn_line("Sep 26 09:13:17 host cron[3]: job run");
+        log_filters.filters_meta[1].severity = Some(Severity::Warning);
+        log_filters.set_slot_varying(0, 4).unwrap();
+        // alerts are dropped, filters following them move
+        log_filters.append_filter(
+            tst_utils::_simple_filter_from_string("kernel segfault"),
+            FilterMeta {
+                alert: Some("segfault".to_string()),
+                ..Default::default()
+            },
+        );
+        log_filters.learn_line("Sep 26 09:13:18 host kernel: usb connected");
+        // transitions to alerts are dropped as well
+        log_filters.filters_meta[1].next = vec![(2, 5), (3, 1)];
+        log_filters.filters_meta[3].next = vec![(0, 2)];
+        // words no saved filter holds are not saved
+        log_filters.symbols.intern("unused");
+
+        let loaded = LogFilters::from_binary(&log_filters.to_binary()).unwrap();
+        assert_eq!(loaded.symbols.get("unused"), None);
+        assert_eq!(loaded.symbols.get("segfault"), None);
+        assert_eq!(loaded.symbols.len(), log_filters.symbols.len() - 2);
+        assert_eq!(
+            loaded.settings_to_string(),
+            log_filters.settings_to_string()
+        );
+        assert_eq!(loaded.prefix_index_tokens(), 1);
+        assert_eq!(loaded.filters_count(), 3);
+        for (loaded_index, filter_index) in [(0, 0), (1, 1), (2, 3)] {
+            assert_eq!(
+                loaded.filter_to_string(loaded_index),
+                log_filters.filter_to_string(filter_index)
+            );
+        }
+        assert_eq!(loaded.filters_meta[0], log_filters.filters_meta[0]);
+        assert_eq!(loaded.filters_meta[1].severity, Some(Severity::Warning));
+        assert_eq!(loaded.filters_meta[1].next, vec![(2, 1)]);
+        assert_eq!(loaded.filters_meta[2], log_filters.filters_meta[3]);
+        let line = "Sep 27 09:13:15 host sshd[4]: Accepted publickey for carol";
+        assert_eq!(loaded.check_line(line), LineCheck::Known(0));
+        let line = "Sep 27 09:13:15 host kernel: usb disconnected";
+        assert_eq!(
+            loaded.check_line(line),
+            LineCheck::NewValues(2, vec![(2, "disconnected".to_string())])
+        );
+        // nothing is lost once alerts are gone
+        let bytes = loaded.to_binary();
+        assert_eq!(LogFilters::from_binary(&bytes).unwrap().to_binary(), bytes);
+
+        let mut corrupted = log_filters.to_binary();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert!(LogFilters::from_binary(&corrupted).is_err());
+        let mut writer = Writer::new();
+        writer.str("1\n.\ntrue\n2");
+        assert!(LogFilters::from_binary(&writer.finish()).is_err());
+    }
+
+    #[test]
+    fn learn_from_reader() {
+        let mut log_filters = LogFilters::new();
+        let report = log_filters.learn_from_iter(
+            vec![
+                "Sep 26 09:13:15 host sshd[1]: Accepted publickey for alice",
+                "Sep 26 09:13:16 host cron[2]: job run",
+            ],
+            Feed::new(),
+        );
+        assert_eq!(report.lines, 2);
+        assert_eq!(log_filters.filters_count(), 2);
+
+        let input: &[u8] = b"Sep 26 09:13:17 host cron[3]: job done\n\
+                              Sep 26 09:13:18 host kernel: \xff\n\
+                              Sep 26 09:13:19 host kernel: usb connected\n";
+        let mut progress = Vec::new();
+        let result = log_filters
+            .learn_from_reader(input, Feed::new().progress(1, |lines| progress.push(lines)));
+        assert!(matches!(result, Err(InputError::InvalidLine(2, _))));
+        // lines preceding the invalid one are learned
+        assert_eq!(progress, vec![1]);
+        assert_eq!(log_filters.filters_count(), 3);
+        let report = log_filters
+            .learn_from_reader(input, Feed::new().invalid_lines(|_, _| None))
+            .unwrap();
+        assert_eq!((report.lines, report.skipped), (3, 1));
+        assert_eq!(log_filters.filters_count(), 4);
+
+        let input: &[u8] = b"Sep 27 09:13:15 host sshd[4]: Accepted publickey for alice\n\
+                              Sep 27 09:13:16 host kernel: \xff\n\
+                              Sep 27 09:13:17 host cron[5]: job failed\n";
+        let checked: Vec<Option<LineCheck>> = log_filters
+            .check_reader(input)
+            .map(|checked| checked.ok().map(|(_, line_check)| line_check))
+            .collect();
+        assert_eq!(
+            checked,
+            vec![Some(LineCheck::Known(0)), None, Some(LineCheck::Unknown)]
+        );
+        let unknown: Vec<Result<String, String>> = log_filters
+            .unknown_lines(input)
+            .map(|line| line.map_err(|why| why.to_string()))
+            .collect();
+        assert_eq!(
+            unknown,
+            vec![
+                Err("Line 2 is not valid UTF-8".to_string()),
+                Ok("Sep 27 09:13:17 host cron[5]: job failed".to_string()),
+            ]
+        );
+        // lines with new words are known
+        log_filters.max_allowed_new_alternatives = 1;
+        let input: &[u8] = b"Sep 27 09:13:18 host kernel: usb disconnected\n\
+                              Sep 27 09:13:19 host ntpd[6]: clock stepped\n";
+        assert_eq!(
+            log_filters.check_line("Sep 27 09:13:18 host kernel: usb disconnected"),
+            LineCheck::NewValues(3, vec![(2, "disconnected".to_string())])
+        );
+        let unknown: Vec<String> = log_filters
+            .unknown_lines(input)
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(unknown, vec!["Sep 27 09:13:19 host ntpd[6]: clock stepped"]);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let mut log_filters = LogFilters::new();
+        for bytes in [
+            &b"Sep 26 09:13:15 host kernel: usb \xff\xfe[1] attached"[..],
+            b"Sep 26 09:13:16 host kernel: \xc3 \xa9",
+        ] {
+            for policy in [InvalidLinePolicy::Lossy, InvalidLinePolicy::Hex] {
+                log_filters.learn_line(&policy.repair(bytes).unwrap());
+            }
+        }
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[kernel],[usb],[\u{fffd}\u{fffd}],[attached]"
+        );
+        assert_eq!(
+            log_filters.filter_to_string(1),
+            "[kernel],[usb],[\\xff\\xfe],[attached]"
+        );
+        assert_eq!(log_filters.filter_to_string(3), "[kernel],[\\xc3],[\\xa9]");
+
+        // repaired words are saved and loaded as any other
+        let mut loaded = LogFilters::new();
+        let lines: Vec<String> = (0..log_filters.filters_count())
+            .map(|filter_index| log_filters.filter_to_string(filter_index))
+            .collect();
+        let lines: Vec<&str> = lines.iter().map(|line| line.as_str()).collect();
+        loaded.parse_str_lines(&lines);
+        let binary = LogFilters::from_binary(&log_filters.to_binary()).unwrap();
+        for filter_index in 0..log_filters.filters_count() {
+            assert_eq!(
+                loaded.filter_to_string(filter_index),
+                log_filters.filter_to_string(filter_index)
+            );
+            assert_eq!(
+                binary.filter_to_string(filter_index),
+                log_filters.filter_to_string(filter_index)
+            );
+        }
+        let line =
+            InvalidLinePolicy::Hex.repair(b"Sep 27 09:13:15 host kernel: usb \xff\xfe[2] attached");
+        assert_eq!(loaded.check_line(&line.unwrap()), LineCheck::Known(1));
+    }
+
+    #[test]
+    fn set_delimiters() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 0;
+        assert!(log_filters.set_delimiters(" ,[]").is_err());
+        assert!(log_filters.set_delimiters(" .[]").is_err());
+        assert!(log_filters.set_delimiters(" ,.[]\n").is_err());
+        assert_eq!(log_filters.delimiters(), DEFAULT_DELIMITERS);
+
+        assert!(log_filters.set_delimiters(" ,.[]").is_ok());
+        assert_eq!(
+            log_filters.line_to_words("GET /request/url:80 (client) [done]"),
+            vec!["GET", "/request/url:80", "(client)", "done"]
+        );
+    }
+
+    #[test]
+    fn parse_str_lines() {
         // TODO: cover incorrect input
         // Filter with no alternatives
         let log_filters_lines = vec!["[a],[b],[c],[d],[e]"];
@@ -900,30 +3343,15 @@ mod tests {
         log_filters.max_allowed_new_alternatives = 1;
         log_filters.ignore_numeric_words = true;
         log_filters.ignore_first_columns = 2;
-        log_filters.from_str_lines(&log_filters_lines);
+        log_filters.parse_str_lines(&log_filters_lines);
         assert_eq!(log_filters.filters.len(), 1);
         let expected = tst_utils::_simple_filter_from_string("a b c d e");
-        assert_eq!(log_filters.filters[0], expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"a".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"b".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"c".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"d".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"e".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
+        assert_eq!(log_filters.filter_words(0), expected);
+        assert_eq!(log_filters.word_filters("a").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("b").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("c").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("d").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("e").unwrap(), &vec![0 as usize]);
 
         // Filter with alternatives
         let log_filters_lines = vec!["[a,b],[c],[d,e]"];
@@ -931,32 +3359,38 @@ mod tests {
         log_filters.max_allowed_new_alternatives = 1;
         log_filters.ignore_numeric_words = true;
         log_filters.ignore_first_columns = 2;
-        log_filters.from_str_lines(&log_filters_lines);
+        log_filters.parse_str_lines(&log_filters_lines);
         assert_eq!(log_filters.filters.len(), 1);
         let mut expected = tst_utils::_simple_filter_from_string("a c d");
         expected = tst_utils::_add_word_alternative(expected, 0, "b");
         expected = tst_utils::_add_word_alternative(expected, 2, "e");
-        assert_eq!(log_filters.filters[0], expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"a".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"b".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"c".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"d".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
+        assert_eq!(log_filters.filter_words(0), expected);
+        assert_eq!(log_filters.word_filters("a").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("b").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("c").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("d").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("e").unwrap(), &vec![0 as usize]);
+
+        // Filter details following the filter
+        let log_filters_lines = vec![
+            "[a],[b,.] hits=7 severity=err next=1:2,0:1,",
+            "[c] manual=true",
+        ];
+        let mut log_filters = LogFilters::new();
+        log_filters.parse_str_lines(&log_filters_lines);
+        assert_eq!(log_filters.filters.len(), 2);
+        let mut expected = tst_utils::_simple_filter_from_string("a b");
+        expected = tst_utils::_add_word_alternative(expected, 1, ".");
+        assert_eq!(log_filters.filter_words(0), expected);
+        assert_eq!(log_filters.filter_meta(0).hits, 7);
+        assert_eq!(log_filters.filter_meta(1).hits, 0);
+        assert_eq!(log_filters.filter_meta(0).severity, Some(Severity::Err));
         assert_eq!(
-            log_filters.words_hash.get(&"e".to_string()).unwrap(),
-            &vec![0 as usize]
+            log_filters.filter_meta(0).to_suffix(),
+            " hits=7 severity=err next=0:1,1:2"
         );
+        assert!(log_filters.filter_meta(1).manual);
+        assert_eq!(log_filters.filter_meta(1).to_suffix(), " manual=true");
 
         // Two filters
         let log_filters_lines = vec!["[a],[b],[c],[d],[e,f]", "[a,b],[c],[d,e,g]"];
@@ -964,7 +3398,7 @@ mod tests {
         log_filters.max_allowed_new_alternatives = 1;
         log_filters.ignore_numeric_words = true;
         log_filters.ignore_first_columns = 2;
-        log_filters.from_str_lines(&log_filters_lines);
+        log_filters.parse_str_lines(&log_filters_lines);
         assert_eq!(log_filters.filters.len(), 2);
         let mut expected_1 = tst_utils::_simple_filter_from_string("a b c d e");
         expected_1 = tst_utils::_add_word_alternative(expected_1, 4, "f");
@@ -972,36 +3406,30 @@ mod tests {
         expected_2 = tst_utils::_add_word_alternative(expected_2, 0, "b");
         expected_2 = tst_utils::_add_word_alternative(expected_2, 2, "e");
         expected_2 = tst_utils::_add_word_alternative(expected_2, 2, "g");
-        assert_eq!(log_filters.filters[0], expected_1);
-        assert_eq!(log_filters.filters[1], expected_2);
+        assert_eq!(log_filters.filter_words(0), expected_1);
+        assert_eq!(log_filters.filter_words(1), expected_2);
         assert_eq!(
-            log_filters.words_hash.get(&"a".to_string()).unwrap(),
+            log_filters.word_filters("a").unwrap(),
             &vec![0 as usize, 1 as usize]
         );
         assert_eq!(
-            log_filters.words_hash.get(&"b".to_string()).unwrap(),
+            log_filters.word_filters("b").unwrap(),
             &vec![0 as usize, 1 as usize]
         );
         assert_eq!(
-            log_filters.words_hash.get(&"c".to_string()).unwrap(),
+            log_filters.word_filters("c").unwrap(),
             &vec![0 as usize, 1 as usize]
         );
         assert_eq!(
-            log_filters.words_hash.get(&"d".to_string()).unwrap(),
+            log_filters.word_filters("d").unwrap(),
             &vec![0 as usize, 1 as usize]
         );
         assert_eq!(
-            log_filters.words_hash.get(&"e".to_string()).unwrap(),
+            log_filters.word_filters("e").unwrap(),
             &vec![0 as usize, 1 as usize]
         );
-        assert_eq!(
-            log_filters.words_hash.get(&"f".to_string()).unwrap(),
-            &vec![0 as usize]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"g".to_string()).unwrap(),
-            &vec![1 as usize]
-        );
+        assert_eq!(log_filters.word_filters("f").unwrap(), &vec![0 as usize]);
+        assert_eq!(log_filters.word_filters("g").unwrap(), &vec![1 as usize]);
     }
 
     #[test]
@@ -1144,10 +3572,7 @@ mod tests {
 
         let mut log_filters = tst_utils::_init_test_data();
         log_filters.max_allowed_new_alternatives = 1;
-        assert_eq!(
-            log_filters.get_sorted_filter_indexes_containing_words(&vec![]),
-            vec![]
-        );
+        assert_eq!(log_filters.count_filter_hits(&[], false), vec![]);
         let words = tst_utils::_words_vector_from_string("aaa bbb ccc ddd");
         assert_eq!(
             log_filters.get_filter_indexes_with_min_req_matches(&words),
@@ -1301,39 +3726,89 @@ mod tests {
     }
 
     #[test]
-    fn get_sorted_filter_indexes_containing_words() {
+    fn count_filter_hits() {
         let log_filters = LogFilters::new();
         let words = tst_utils::_words_vector_from_string("aaa bbb ccc ddd");
+        assert_eq!(log_filters.count_filter_hits(&words, false), vec![]);
+        assert_eq!(log_filters.count_filter_hits(&[], false), vec![]);
+
+        let mut log_filters = tst_utils::_init_test_data();
+        log_filters.max_allowed_new_alternatives = 1;
+        assert_eq!(log_filters.count_filter_hits(&[], false), vec![]);
+        let words = tst_utils::_words_vector_from_string("aaa bbb ccc ddd");
         assert_eq!(
-            log_filters.get_sorted_filter_indexes_containing_words(&words),
-            vec![]
+            log_filters.count_filter_hits(&words, false),
+            vec![(0, 4), (4, 1), (5, 4)]
         );
+        let words = tst_utils::_words_vector_from_string("aaa xxx");
         assert_eq!(
-            log_filters.get_sorted_filter_indexes_containing_words(&vec![]),
-            vec![]
+            log_filters.count_filter_hits(&words, false),
+            vec![(0, 1), (4, 1), (5, 1)]
         );
+        let words = tst_utils::_words_vector_from_string("xxx");
+        assert_eq!(log_filters.count_filter_hits(&words, false), vec![]);
+
+        // frequent word only counts towards filters found by other words
+        let mut log_filters = LogFilters::new();
+        for index in 0..40 {
+            log_filters.learn_line(&format!("Jan 1 web01 host event{} started", index));
+        }
+        let words = tst_utils::_words_vector_from_string("host event7 started");
+        assert_eq!(log_filters.count_filter_hits(&words, false), vec![(7, 3)]);
+        assert_eq!(log_filters.count_filter_hits(&words, true).len(), 40);
+        let words = tst_utils::_words_vector_from_string("host started");
+        assert_eq!(log_filters.count_filter_hits(&words, false).len(), 40);
+    }
 
+    #[test]
+    fn prefix_index() {
         let mut log_filters = tst_utils::_init_test_data();
         log_filters.max_allowed_new_alternatives = 1;
+        let id = |word: &str| log_filters.symbols.get(word).unwrap();
         assert_eq!(
-            log_filters.get_sorted_filter_indexes_containing_words(&vec![]),
-            vec![]
+            log_filters.prefix_keys(0, 2),
+            vec![vec![id("aaa"), id("qqq")], vec![id("aaa"), id("bbb")]]
         );
+        assert_eq!(log_filters.filter_lengths(1), (7, Some(7)));
+
+        log_filters.set_prefix_index_tokens(1);
+        assert_eq!(log_filters.prefix_index_tokens(), 1);
         let words = tst_utils::_words_vector_from_string("aaa bbb ccc ddd");
         assert_eq!(
-            log_filters.get_sorted_filter_indexes_containing_words(&words),
-            vec![0, 0, 0, 0, 4, 5, 5, 5, 5]
+            log_filters.get_filter_indexes_with_min_req_matches(&words),
+            vec![0]
         );
-        let words = tst_utils::_words_vector_from_string("aaa xxx");
+        // Filter is no candidate once the first word differs
+        let words = tst_utils::_words_vector_from_string("xxx aaa bbb ccc ddd");
+        assert_eq!(log_filters.count_filter_hits(&words, false)[0], (0, 4));
         assert_eq!(
-            log_filters.get_sorted_filter_indexes_containing_words(&words),
-            vec![0, 4, 5]
+            log_filters.get_filter_indexes_with_min_req_matches(&words),
+            vec![]
         );
-        let words = tst_utils::_words_vector_from_string("xxx");
+        // Filter with optional first column is a candidate for any line
+        log_filters.append_filter(
+            tst_utils::_simple_filter_from_string("www yyy zzz"),
+            FilterMeta::default(),
+        );
+        log_filters.filters[6][0].push(log_filters.optional_id);
+        log_filters.filter_changed(6);
+        assert_eq!(log_filters.filter_lengths(6), (2, Some(3)));
+        let words = tst_utils::_words_vector_from_string("yyy zzz");
         assert_eq!(
-            log_filters.get_sorted_filter_indexes_containing_words(&words),
-            vec![]
+            log_filters.get_filter_indexes_with_min_req_matches(&words),
+            vec![6]
+        );
+
+        // Learned filters are indexed
+        log_filters.learn_line("Sep 26 host_name kernel usb connected");
+        let words = log_filters.line_words("Sep 27 host_name kernel usb disconnected");
+        assert_eq!(
+            log_filters.get_filter_indexes_with_min_req_matches(&words),
+            vec![7]
         );
+
+        log_filters.set_prefix_index_tokens(0);
+        assert!(log_filters.prefix_index.is_none());
     }
 
     #[test]
@@ -1399,6 +3874,25 @@ mod tests {
         // Test if words vector and filter vector must contain words in the same order
         let words = tst_utils::_words_vector_from_string("ddd ccc bbb aaa");
         assert_eq!(log_filters.count_consequent_matches(&words, 0), 0);
+
+        // Test if gap matches zero or more words between anchors
+        let mut log_filters = LogFilters::new();
+        log_filters.parse_str_lines(&["[aaa],[.**],[bbb]", "[.**],[ccc]"]);
+        for (line, matches) in &[
+            ("aaa bbb", 2),
+            ("aaa xxx bbb", 3),
+            ("aaa xxx yyy zzz bbb", 5),
+            ("aaa xxx yyy", 3),
+            ("xxx aaa bbb", 0),
+        ] {
+            let words = tst_utils::_words_vector_from_string(line);
+            assert_eq!(log_filters.count_consequent_matches(&words, 0), *matches);
+        }
+        let words = tst_utils::_words_vector_from_string("xxx yyy ccc");
+        assert_eq!(log_filters.count_consequent_matches(&words, 1), 3);
+        assert_eq!(log_filters.find_best_matching_filter_index(&words), 1);
+        let words = tst_utils::_words_vector_from_string("aaa xxx yyy zzz bbb");
+        assert_eq!(log_filters.find_best_matching_filter_index(&words), 0);
     }
 
     #[test]
@@ -1406,63 +3900,289 @@ mod tests {
         // Test what happens if method was used on empty data structure
         let log_filters = LogFilters::new();
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"aaa".to_string(), 0, 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("aaa"), 0, 0),
             -1
         );
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"aaa".to_string(), 0, 100),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("aaa"), 0, 100),
             -1
         );
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"aaa".to_string(), 100, 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("aaa"), 100, 0),
             -1
         );
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"".to_string(), 0, 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of(""), 0, 0),
             -1
         );
 
         let log_filters = tst_utils::_init_test_data();
         // Test if word will be matched when it should be
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"aaa".to_string(), 0, 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("aaa"), 0, 0),
             0
         );
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"aaa".to_string(), 4, 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("aaa"), 4, 0),
             3
         );
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"qqq".to_string(), 0, 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("qqq"), 0, 0),
             1
         );
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"sss".to_string(), 0, 3),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("sss"), 0, 3),
             3
         );
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"ddd".to_string(), 0, 3),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("ddd"), 0, 3),
             3
         );
         // Test if word will not be matched if starting index is higher than word index in filter
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"aaa".to_string(), 0, 1),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("aaa"), 0, 1),
             -1
         );
         // Empty string should not be matched
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"".to_string(), 4, 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of(""), 4, 0),
             -1
         );
         // Test when word does not exist in filter or filter does not exist
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"aaa".to_string(), 1, 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("aaa"), 1, 0),
+            -1
+        );
+        assert_eq!(
+            log_filters.get_word_index_in_filter(
+                &log_filters.word_of("aaa"),
+                log_filters.filters.len(),
+                0
+            ),
             -1
         );
+
+        // Wildcard matches any word, including words unknown to `words_hash`
+        let mut log_filters = LogFilters::new();
+        log_filters.parse_str_lines(&["[aaa],[.*],[bbb],[.*,.]"]);
+        assert_eq!(
+            log_filters.get_word_index_in_filter(&log_filters.word_of("xxx"), 0, 0),
+            1
+        );
+        assert_eq!(
+            log_filters.get_word_index_in_filter(&log_filters.word_of("bbb"), 0, 0),
+            1
+        );
+        assert_eq!(
+            log_filters.get_word_index_in_filter(&log_filters.word_of("bbb"), 0, 2),
+            2
+        );
+        assert_eq!(
+            log_filters.get_word_index_in_filter(&log_filters.word_of("xxx"), 0, 2),
+            3
+        );
+        assert_eq!(
+            log_filters.get_word_index_in_filter(&log_filters.word_of("aaa"), 0, 0),
+            0
+        );
+
+        // Typed column matches only words of its type
+        let mut log_filters = LogFilters::new();
+        log_filters.parse_str_lines(&["[aaa],[.int],[bbb]"]);
+        assert_eq!(
+            log_filters.get_word_index_in_filter(&log_filters.word_of("123"), 0, 0),
+            1
+        );
         assert_eq!(
-            log_filters.get_word_index_in_filter(&"aaa".to_string(), log_filters.filters.len(), 0),
+            log_filters.get_word_index_in_filter(&log_filters.word_of("12a"), 0, 0),
             -1
         );
+        assert_eq!(
+            log_filters.get_word_index_in_filter(&log_filters.word_of("bbb"), 0, 0),
+            2
+        );
+    }
+
+    #[test]
+    fn generalise_slots() {
+        let mut log_filters = LogFilters::new();
+        log_filters.parse_str_lines(&["[aaa],[bbb,ccc,.],[ddd,eee,fff],[ggg]"]);
+        // No limit by default
+        log_filters.generalise_slots(0);
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[aaa],[bbb,ccc,.],[ddd,eee,fff],[ggg]"
+        );
+
+        log_filters.max_slot_alternatives = 2;
+        log_filters.generalise_slots(0);
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[aaa],[bbb,ccc,.],[.*],[ggg]"
+        );
+        assert!(log_filters.word_filters("ddd").is_none());
+        assert!(log_filters.word_filters("bbb").is_some());
+
+        log_filters.max_slot_alternatives = 1;
+        log_filters.generalise_slots(0);
+        assert_eq!(log_filters.filter_to_string(0), "[aaa],[.*,.],[.*],[ggg]");
+        assert!(log_filters.word_filters("ccc").is_none());
+
+        // Words pushed into wildcard column are dropped
+        log_filters.max_slot_alternatives = 0;
+        let word = log_filters.symbols.intern("hhh");
+        log_filters.filters[0][2].push(word);
+        log_filters.generalise_slots(0);
+        assert_eq!(log_filters.filter_to_string(0), "[aaa],[.*,.],[.*],[ggg]");
+
+        // Word still present in other column is kept in hash
+        let mut log_filters = LogFilters::new();
+        log_filters.max_slot_alternatives = 1;
+        log_filters.parse_str_lines(&["[aaa,bbb],[aaa]"]);
+        log_filters.generalise_slots(0);
+        assert_eq!(log_filters.filter_to_string(0), "[.*],[aaa]");
+        assert_eq!(log_filters.word_filters("aaa"), Some(&vec![0]));
+        assert!(log_filters.word_filters("bbb").is_none());
+
+        // Common type of alternatives replaces them
+        let mut log_filters = LogFilters::new();
+        log_filters.infer_slot_types = true;
+        log_filters
+            .parse_str_lines(&["[aaa],[12,34],[12,f1,.],[c524],[c524,0x1f],[c524,user_1],[ab,12]"]);
+        log_filters.generalise_slots(0);
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[aaa],[.int],[.hex,.],[c524],[.hex],[.id],[ab,12]"
+        );
+        assert!(log_filters.word_filters("34").is_none());
+        assert_eq!(log_filters.word_filters("12"), Some(&vec![0]));
+        // Slot expected to vary stays so
+        log_filters.parse_str_lines(&["[aaa],[12,34,.~]"]);
+        log_filters.generalise_slots(1);
+        assert_eq!(log_filters.filter_to_string(1), "[aaa],[.int,.~]");
+        // Type is widened by words of other type
+        let word = log_filters.symbols.intern("1a");
+        log_filters.filters[0][1].push(word);
+        log_filters.generalise_slots(0);
+        assert_eq!(
+            log_filters.filter_to_string(0).split(',').nth(1),
+            Some("[.hex]")
+        );
+        // Wildcard takes precedence over type
+        log_filters.max_slot_alternatives = 1;
+        let word = log_filters.symbols.intern("2b");
+        log_filters.filters[0][1].push(word);
+        let word = log_filters.symbols.intern("xyz");
+        log_filters.filters[0][1].push(word);
+        log_filters.generalise_slots(0);
+        assert_eq!(
+            log_filters.filter_to_string(0).split(',').nth(1),
+            Some("[.*]")
+        );
+    }
+
+    #[test]
+    fn compact_symbols() {
+        let mut log_filters = LogFilters::new();
+        log_filters.max_slot_alternatives = 2;
+        log_filters.max_allowed_new_alternatives = 1;
+        log_filters.set_prefix_index_tokens(1);
+        for user in &["alice", "bob", "carol"] {
+            log_filters.learn_line(&format!("Sep 26 sshd session opened for {}", user));
+        }
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[session],[opened],[for],[.*]"
+        );
+        assert!(log_filters.symbols.get("alice").is_some());
+
+        log_filters.compact_symbols();
+        // markers and the three words left
+        assert_eq!(log_filters.symbols.len(), 7);
+        assert!(log_filters.symbols.get("alice").is_none());
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[session],[opened],[for],[.*]"
+        );
+        assert_eq!(log_filters.word_filters("opened"), Some(&vec![0]));
+        assert_eq!(
+            log_filters.check_line("Sep 26 sshd session opened for dave"),
+            LineCheck::Known(0)
+        );
+        log_filters.learn_line("Sep 26 sshd session opened for erin");
+        log_filters.learn_line("Sep 26 sshd session closed for dave");
+        assert_eq!(log_filters.filters.len(), 2);
+        assert_eq!(
+            log_filters.check_line("Sep 26 sshd session closed for dave"),
+            LineCheck::Known(1)
+        );
+    }
+
+    #[test]
+    fn introduce_gaps() {
+        let mut log_filters = LogFilters::new();
+        log_filters.min_gap_slots = 2;
+        log_filters.parse_str_lines(&["[aaa],[bbb],[ccc],[ddd],[eee],[fff]"]);
+        // Single differing word does not make a gap
+        let words = tst_utils::_words_vector_from_string("aaa xxx ccc ddd eee fff");
+        log_filters.introduce_gaps(&words, 0);
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[aaa],[bbb],[ccc],[ddd],[eee],[fff]"
+        );
+        // Differing run of words or of columns does
+        let words = tst_utils::_words_vector_from_string("aaa xxx yyy zzz ccc ddd fff");
+        log_filters.introduce_gaps(&words, 0);
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[aaa],[.**],[ccc],[ddd],[eee],[fff]"
+        );
+        assert!(log_filters.word_filters("bbb").is_none());
+        let words = tst_utils::_words_vector_from_string("aaa ccc");
+        log_filters.introduce_gaps(&words, 0);
+        assert_eq!(log_filters.filter_to_string(0), "[aaa],[.**],[ccc],[.**]");
+        // Words falling into existing gap are not differing
+        let words = tst_utils::_words_vector_from_string("aaa xxx yyy ccc");
+        log_filters.introduce_gaps(&words, 0);
+        assert_eq!(log_filters.filter_to_string(0), "[aaa],[.**],[ccc],[.**]");
+    }
+
+    #[test]
+    fn collapse_gaps() {
+        let mut log_filters = LogFilters::new();
+        log_filters.parse_str_lines(&["[aaa],[bbb,.],[.**],[.**],[ccc,.],[ddd,eee],[fff,.]"]);
+        let removed = super::collapse_gaps(
+            &mut log_filters.filters[0],
+            log_filters.optional_id,
+            log_filters.gap_id,
+        );
+        let removed: Vec<&str> = removed
+            .into_iter()
+            .filter(|word| !log_filters.is_marker_id(*word))
+            .map(|word| log_filters.word(word))
+            .collect();
+        assert_eq!(removed, vec!["ccc", "bbb"]);
+        assert_eq!(
+            log_filters.filter_to_string(0),
+            "[aaa],[.**],[ddd,eee],[fff,.]"
+        );
+    }
+
+    #[test]
+    fn slot_type() {
+        assert_eq!(SlotType::of("1234"), Some(SlotType::Int));
+        assert_eq!(SlotType::of("c524"), Some(SlotType::Hex));
+        assert_eq!(SlotType::of("0xff"), Some(SlotType::Hex));
+        assert_eq!(SlotType::of("ff"), None);
+        assert_eq!(SlotType::of("user_12"), Some(SlotType::Id));
+        assert_eq!(SlotType::of("fe80::1"), Some(SlotType::Ip));
+        assert_eq!(SlotType::of("Removed"), None);
+        assert_eq!(SlotType::Int.join(SlotType::Id), Some(SlotType::Id));
+        assert_eq!(SlotType::Ip.join(SlotType::Int), None);
+        assert_eq!(SlotType::from_marker(".hex", "."), Some(SlotType::Hex));
+        assert_eq!(SlotType::from_marker(".*", "."), None);
+        assert_eq!(SlotType::from_marker("hex", "."), None);
+        assert_eq!(SlotType::Id.marker("."), ".id");
     }
 
     #[test]
@@ -1487,7 +4207,7 @@ mod tests {
         let words = tst_utils::_words_vector_from_string("mmm nnn ooo ppp");
         log_filters.update_filter(&words, 3);
         let expected = tst_utils::_simple_filter_from_string("mmm nnn ooo ppp");
-        assert_eq!(log_filters.filters.get(3).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(3), &expected);
 
         // One new (hence optional) word alternative added at the front of filter
         let words = tst_utils::_words_vector_from_string("foo qqq rrr sss ttt");
@@ -1495,39 +4215,30 @@ mod tests {
         let mut expected = tst_utils::_simple_filter_from_string("foo qqq rrr sss ttt");
         expected = tst_utils::_add_word_alternative(expected, 0, ".");
         expected = tst_utils::_add_word_alternative(expected, 4, "aaa");
-        assert_eq!(log_filters.filters.get(4).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"foo".to_string()).unwrap(),
-            &vec![4]
-        );
+        assert_eq!(&log_filters.filter_words(4), &expected);
+        assert_eq!(log_filters.word_filters("foo").unwrap(), &vec![4]);
         // Two new (hence optional) word alternatives added at the front of filter
         let words = tst_utils::_words_vector_from_string("xyz qwe mmm nnn ooo ppp");
         log_filters.update_filter(&words, 3);
         let mut expected = tst_utils::_simple_filter_from_string("xyz qwe mmm nnn ooo ppp");
         expected = tst_utils::_add_word_alternative(expected, 0, ".");
         expected = tst_utils::_add_word_alternative(expected, 1, ".");
-        assert_eq!(log_filters.filters.get(3).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"xyz".to_string()).unwrap(),
-            &vec![3]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"qwe".to_string()).unwrap(),
-            &vec![3]
-        );
+        assert_eq!(&log_filters.filter_words(3), &expected);
+        assert_eq!(log_filters.word_filters("xyz").unwrap(), &vec![3]);
+        assert_eq!(log_filters.word_filters("qwe").unwrap(), &vec![3]);
         // One word turned to (optional) alternative as a result of words vector shorter than filter
         let words = tst_utils::_words_vector_from_string("fff ggg hhh x y z");
         log_filters.update_filter(&words, 1);
         let mut expected = tst_utils::_simple_filter_from_string("eee fff ggg hhh x y z");
         expected = tst_utils::_add_word_alternative(expected, 0, ".");
-        assert_eq!(log_filters.filters.get(1).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(1), &expected);
         // Two words turned to (optional) alternatives as a result of words vector shorter than filter
         let words = tst_utils::_words_vector_from_string("kkk lll");
         log_filters.update_filter(&words, 2);
         let mut expected = tst_utils::_simple_filter_from_string("iii jjj kkk lll");
         expected = tst_utils::_add_word_alternative(expected, 0, ".");
         expected = tst_utils::_add_word_alternative(expected, 1, ".");
-        assert_eq!(log_filters.filters.get(2).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(2), &expected);
         // One word turned to optional alternative and one new alternative added
         let words = tst_utils::_words_vector_from_string("bar ccc sss");
         log_filters.update_filter(&words, 0);
@@ -1537,7 +4248,7 @@ mod tests {
         expected = tst_utils::_add_word_alternative(expected, 1, "bar");
         expected = tst_utils::_add_word_alternative(expected, 2, "rrr");
         expected = tst_utils::_add_word_alternative(expected, 3, "ddd");
-        assert_eq!(log_filters.filters.get(0).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(0), &expected);
 
         let mut log_filters = tst_utils::_init_test_data();
         log_filters.max_allowed_new_alternatives = 1;
@@ -1547,41 +4258,26 @@ mod tests {
         log_filters.update_filter(&words, 2);
         let mut expected = tst_utils::_simple_filter_from_string("iii jjj kkk lll");
         expected = tst_utils::_add_word_alternative(expected, 2, "foo");
-        assert_eq!(log_filters.filters.get(2).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"foo".to_string()).unwrap(),
-            &vec![2]
-        );
+        assert_eq!(&log_filters.filter_words(2), &expected);
+        assert_eq!(log_filters.word_filters("foo").unwrap(), &vec![2]);
         // Add alternatives to consequent two words in the middle
         let words = tst_utils::_words_vector_from_string("ttt aaa xyz qwe ccc ddd vvv");
         log_filters.update_filter(&words, 5);
         let mut expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
         expected = tst_utils::_add_word_alternative(expected, 2, "xyz");
         expected = tst_utils::_add_word_alternative(expected, 3, "qwe");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"xyz".to_string()).unwrap(),
-            &vec![5]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"qwe".to_string()).unwrap(),
-            &vec![5]
-        );
+        assert_eq!(&log_filters.filter_words(5), &expected);
+        assert_eq!(log_filters.word_filters("xyz").unwrap(), &vec![5]);
+        assert_eq!(log_filters.word_filters("qwe").unwrap(), &vec![5]);
         // Add alternatives to two non-consequent words in the middle
         let words = tst_utils::_words_vector_from_string("eee fff bar hhh x baz z");
         log_filters.update_filter(&words, 1);
         let mut expected = tst_utils::_simple_filter_from_string("eee fff ggg hhh x y z");
         expected = tst_utils::_add_word_alternative(expected, 2, "bar");
         expected = tst_utils::_add_word_alternative(expected, 5, "baz");
-        assert_eq!(log_filters.filters.get(1).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"bar".to_string()).unwrap(),
-            &vec![1]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"baz".to_string()).unwrap(),
-            &vec![1]
-        );
+        assert_eq!(&log_filters.filter_words(1), &expected);
+        assert_eq!(log_filters.word_filters("bar").unwrap(), &vec![1]);
+        assert_eq!(log_filters.word_filters("baz").unwrap(), &vec![1]);
 
         let mut log_filters = tst_utils::_init_test_data();
         log_filters.max_allowed_new_alternatives = 1;
@@ -1591,20 +4287,20 @@ mod tests {
         log_filters.update_filter(&words, 5);
         let mut expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
         expected = tst_utils::_add_word_alternative(expected, 2, ".");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(5), &expected);
         // Turn two non-consequent words in the middle to optional alternatives
         let words = tst_utils::_words_vector_from_string("eee ggg x y z");
         log_filters.update_filter(&words, 1);
         let mut expected = tst_utils::_simple_filter_from_string("eee fff ggg hhh x y z");
         expected = tst_utils::_add_word_alternative(expected, 1, ".");
         expected = tst_utils::_add_word_alternative(expected, 3, ".");
-        assert_eq!(log_filters.filters.get(1).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(1), &expected);
         // Turn one word in the middle to optional alternative
         let words = tst_utils::_words_vector_from_string("iii jjj lll");
         log_filters.update_filter(&words, 2);
         let mut expected = tst_utils::_simple_filter_from_string("iii jjj kkk lll");
         expected = tst_utils::_add_word_alternative(expected, 2, ".");
-        assert_eq!(log_filters.filters.get(2).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(2), &expected);
 
         let mut log_filters = tst_utils::_init_test_data();
         log_filters.max_allowed_new_alternatives = 1;
@@ -1614,11 +4310,8 @@ mod tests {
         log_filters.update_filter(&words, 5);
         let mut expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
         expected = tst_utils::_add_word_alternative(expected, 6, "xyz");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"xyz".to_string()).unwrap(),
-            &vec![5]
-        );
+        assert_eq!(&log_filters.filter_words(5), &expected);
+        assert_eq!(log_filters.word_filters("xyz").unwrap(), &vec![5]);
 
         let mut log_filters = tst_utils::_init_test_data();
         log_filters.max_allowed_new_alternatives = 1;
@@ -1628,7 +4321,7 @@ mod tests {
         log_filters.update_filter(&words, 5);
         let mut expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
         expected = tst_utils::_add_word_alternative(expected, 6, ".");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(5), &expected);
 
         let mut log_filters = tst_utils::_init_test_data();
         log_filters.max_allowed_new_alternatives = 1;
@@ -1638,11 +4331,8 @@ mod tests {
         log_filters.update_filter(&words, 5);
         let mut expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv xyz");
         expected = tst_utils::_add_word_alternative(expected, 7, ".");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"xyz".to_string()).unwrap(),
-            &vec![5]
-        );
+        assert_eq!(&log_filters.filter_words(5), &expected);
+        assert_eq!(log_filters.word_filters("xyz").unwrap(), &vec![5]);
     }
 
     #[test]
@@ -1692,7 +4382,7 @@ mod tests {
             (0, 0)
         );
         let expected = tst_utils::_simple_filter_from_string("mmm nnn ooo ppp");
-        assert_eq!(log_filters.filters.get(3).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(3), &expected);
 
         // One new (hence optional) word alternative added at the front of filter
         let words = tst_utils::_words_vector_from_string("foo qqq rrr sss ttt");
@@ -1703,11 +4393,8 @@ mod tests {
         let mut expected = tst_utils::_simple_filter_from_string("foo qqq rrr sss ttt");
         expected = tst_utils::_add_word_alternative(expected, 0, ".");
         expected = tst_utils::_add_word_alternative(expected, 4, "aaa");
-        assert_eq!(log_filters.filters.get(4).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"foo".to_string()).unwrap(),
-            &vec![4]
-        );
+        assert_eq!(&log_filters.filter_words(4), &expected);
+        assert_eq!(log_filters.word_filters("foo").unwrap(), &vec![4]);
         // Two new (hence optional) word alternatives resulting from passed word vector
         let words = tst_utils::_words_vector_from_string("xyz qwe mmm nnn ooo ppp");
         assert_eq!(
@@ -1717,15 +4404,9 @@ mod tests {
         let mut expected = tst_utils::_simple_filter_from_string("xyz qwe mmm nnn ooo ppp");
         expected = tst_utils::_add_word_alternative(expected, 0, ".");
         expected = tst_utils::_add_word_alternative(expected, 1, ".");
-        assert_eq!(log_filters.filters.get(3).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"xyz".to_string()).unwrap(),
-            &vec![3]
-        );
-        assert_eq!(
-            log_filters.words_hash.get(&"qwe".to_string()).unwrap(),
-            &vec![3]
-        );
+        assert_eq!(&log_filters.filter_words(3), &expected);
+        assert_eq!(log_filters.word_filters("xyz").unwrap(), &vec![3]);
+        assert_eq!(log_filters.word_filters("qwe").unwrap(), &vec![3]);
         // One word turned to (optional) alternative as a result of words vector shorter than filter
         let words = tst_utils::_words_vector_from_string("fff ggg hhh x y z");
         assert_eq!(
@@ -1734,7 +4415,7 @@ mod tests {
         );
         let mut expected = tst_utils::_simple_filter_from_string("eee fff ggg hhh x y z");
         expected = tst_utils::_add_word_alternative(expected, 0, ".");
-        assert_eq!(log_filters.filters.get(1).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(1), &expected);
         // Two words turned to (optional) alternatives as a resulting of words vector shorter than filter
         let words = tst_utils::_words_vector_from_string("kkk lll");
         assert_eq!(
@@ -1744,7 +4425,7 @@ mod tests {
         let mut expected = tst_utils::_simple_filter_from_string("iii jjj kkk lll");
         expected = tst_utils::_add_word_alternative(expected, 0, ".");
         expected = tst_utils::_add_word_alternative(expected, 1, ".");
-        assert_eq!(log_filters.filters.get(2).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(2), &expected);
         // One word turned to optional alternative and one new alternative added to second word
         let words = tst_utils::_words_vector_from_string("bar ccc sss");
         assert_eq!(
@@ -1757,7 +4438,7 @@ mod tests {
         expected = tst_utils::_add_word_alternative(expected, 1, "bar");
         expected = tst_utils::_add_word_alternative(expected, 2, "rrr");
         expected = tst_utils::_add_word_alternative(expected, 3, "ddd");
-        assert_eq!(log_filters.filters.get(0).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(0), &expected);
 
         // Tests covering when both filter and words vector do not start from column 0 and both are different indexes
         let mut log_filters = tst_utils::_init_test_data();
@@ -1768,7 +4449,7 @@ mod tests {
             (-1, -1)
         );
         let expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(5), &expected);
 
         // both filter and words vector match first word
         let mut log_filters = tst_utils::_init_test_data();
@@ -1785,7 +4466,7 @@ mod tests {
             (3, 2)
         );
         let expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(5), &expected);
 
         // first filter's alternative matches second word
         let mut log_filters = tst_utils::_init_test_data();
@@ -1804,11 +4485,8 @@ mod tests {
         );
         let mut expected = tst_utils::_simple_filter_from_string("ttt aaa xyz uuu bbb ccc ddd vvv");
         expected = tst_utils::_add_word_alternative(expected, 2, ".");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"xyz".to_string()).unwrap(),
-            &vec![5]
-        );
+        assert_eq!(&log_filters.filter_words(5), &expected);
+        assert_eq!(log_filters.word_filters("xyz").unwrap(), &vec![5]);
 
         // second filter's alternative matches second word
         let mut log_filters = tst_utils::_init_test_data();
@@ -1827,11 +4505,8 @@ mod tests {
         );
         let mut expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
         expected = tst_utils::_add_word_alternative(expected, 2, "xyz");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"xyz".to_string()).unwrap(),
-            &vec![5]
-        );
+        assert_eq!(&log_filters.filter_words(5), &expected);
+        assert_eq!(log_filters.word_filters("xyz").unwrap(), &vec![5]);
 
         // words missing first alternative and second alternative with new option
         let mut log_filters = tst_utils::_init_test_data();
@@ -1851,11 +4526,8 @@ mod tests {
         let mut expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
         expected = tst_utils::_add_word_alternative(expected, 2, ".");
         expected = tst_utils::_add_word_alternative(expected, 3, "xyz");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
-        assert_eq!(
-            log_filters.words_hash.get(&"xyz".to_string()).unwrap(),
-            &vec![5]
-        );
+        assert_eq!(&log_filters.filter_words(5), &expected);
+        assert_eq!(log_filters.word_filters("xyz").unwrap(), &vec![5]);
 
         // no matches
         let mut log_filters = tst_utils::_init_test_data();
@@ -1867,7 +4539,7 @@ mod tests {
             (-1, -1)
         );
         let expected = tst_utils::_simple_filter_from_string("ttt aaa uuu bbb ccc ddd vvv");
-        assert_eq!(log_filters.filters.get(5).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(5), &expected);
 
         // first word matching last filter alternative with earlier match available
         let mut log_filters = tst_utils::_init_test_data();
@@ -1891,7 +4563,7 @@ mod tests {
         let mut expected = tst_utils::_simple_filter_from_string("aaa bbb ccc ddd eee fff ggg hhh");
         expected = tst_utils::_add_word_alternative(expected, 2, "lll");
         expected = tst_utils::_add_word_alternative(expected, 7, "lll");
-        assert_eq!(log_filters.filters.get(6).unwrap(), &expected);
+        assert_eq!(&log_filters.filter_words(6), &expected);
     }
 
     #[test]
@@ -2093,92 +4765,349 @@ mod tests {
         // Test what happens if method was used on empty data structure
         let mut log_filters = LogFilters::new();
         log_filters.add_filter(tst_utils::_words_vector_from_string("aaa bbb ccc"));
+        assert_eq!(log_filters.word_filters("aaa").unwrap(), &vec![0]);
+        assert_eq!(log_filters.word_filters("bbb").unwrap(), &vec![0]);
+        assert_eq!(log_filters.word_filters("ccc").unwrap(), &vec![0]);
+        assert_eq!(
+            &log_filters.filter_words(0),
+            &tst_utils::_simple_filter_from_string("aaa bbb ccc")
+        );
+        // add_filter does not check if filter already exists
+        log_filters.add_filter(tst_utils::_words_vector_from_string("aaa bbb ccc"));
+        assert_eq!(log_filters.word_filters("aaa").unwrap(), &vec![0, 1]);
+        assert_eq!(log_filters.word_filters("bbb").unwrap(), &vec![0, 1]);
+        assert_eq!(log_filters.word_filters("ccc").unwrap(), &vec![0, 1]);
+        assert_eq!(
+            &log_filters.filter_words(1),
+            &tst_utils::_simple_filter_from_string("aaa bbb ccc")
+        );
+    }
+
+    #[test]
+    fn merge() {
+        // Settings conflicts are reported and nothing gets merged
+        let mut log_filters = tst_utils::_init_test_data();
+        let mut other = LogFilters::new();
+        other.ignore_first_columns = 0;
+        other.ignore_numeric_words = false;
+        tst_utils::_add_test_filter(&mut other, tst_utils::_simple_filter_from_string("xxx"));
+        assert_eq!(
+            log_filters.merge(&other),
+            Err(
+                "Settings conflict: ignore_first_columns: 2 != 0, ignore_numeric_words: true != false"
+                    .to_string()
+            )
+        );
+        assert_eq!(log_filters.filters.len(), 6);
+
+        // Learned models from different hosts
+        let mut log_filters = LogFilters::new();
+        log_filters.max_allowed_new_alternatives = 1;
+        log_filters.ignore_first_columns = 1;
+        log_filters.learn_line("Sep 26 09:13:15 host systemd-logind[572]: Removed session c524.");
+        log_filters.learn_line("Sep 27 19:27:53 host systemd-logind[572]: Removed session c525.");
+        let mut other = LogFilters::new();
+        other.max_allowed_new_alternatives = 1;
+        other.ignore_first_columns = 1;
+        other.learn_line("Sep 28 13:41:26 host systemd-logind[572]: Removed session c526.");
+        other.learn_line("Sep 28 13:41:26 host");
+        assert_eq!(
+            log_filters.merge(&other),
+            Ok(MergeReport {
+                unified: 1,
+                appended: 1
+            })
+        );
         assert_eq!(
-            log_filters.words_hash.get(&"aaa".to_string()).unwrap(),
-            &vec![0]
+            log_filters.to_string(),
+            "[host],[systemd-logind],[Removed],[session],[c524,c525,c526],\n[host]"
+        );
+        assert_eq!(log_filters.filter_meta(0).hits, 3);
+        assert_eq!(log_filters.filter_meta(1).hits, 1);
+        assert_eq!(log_filters.word_filters("c526").unwrap(), &vec![0]);
+        assert_eq!(log_filters.word_filters("host").unwrap(), &vec![0, 1]);
+
+        // Alternatives and optional markers are carried over even if no new alternatives are allowed
+        let mut log_filters = LogFilters::new();
+        log_filters.max_allowed_new_alternatives = 0;
+        tst_utils::_add_test_filter(
+            &mut log_filters,
+            tst_utils::_simple_filter_from_string("aaa bbb ccc"),
         );
+        let mut other = LogFilters::new();
+        let mut complex_filter = tst_utils::_simple_filter_from_string("aaa xxx ccc ddd");
+        complex_filter = tst_utils::_add_word_alternative(complex_filter, 1, "bbb");
+        complex_filter = tst_utils::_add_word_alternative(complex_filter, 3, ".");
+        tst_utils::_add_test_filter(&mut other, complex_filter);
         assert_eq!(
-            log_filters.words_hash.get(&"bbb".to_string()).unwrap(),
-            &vec![0]
+            log_filters.merge(&other),
+            Ok(MergeReport {
+                unified: 1,
+                appended: 0
+            })
+        );
+        assert_eq!(log_filters.to_string(), "[aaa],[bbb,xxx],[ccc],[ddd,.]");
+        assert_eq!(log_filters.word_filters("xxx").unwrap(), &vec![0]);
+        assert_eq!(log_filters.word_filters("ddd").unwrap(), &vec![0]);
+        assert!(log_filters.word_filters(".").is_none());
+    }
+
+    #[test]
+    fn prune() {
+        let mut log_filters = LogFilters::new();
+        log_filters.max_allowed_new_alternatives = 0;
+        log_filters.ignore_first_columns = 1;
+        log_filters.learn_transitions = true;
+        log_filters.learn_line("Sep 26 09:13:15 host systemd-logind[572]: Removed session c524.");
+        log_filters.learn_line("Sep 27 19:27:53 host systemd-logind[572]: Removed session c525.");
+        log_filters.learn_line("Sep 27 19:27:53 host systemd-logind[572]: Removed session c525.");
+        log_filters.learn_line("Sep 27 19:27:53 host kernel: usb device connected");
+        log_filters.learn_line("Sep 28 13:41:26 host systemd-logind[572]: Removed session c526.");
+        log_filters.learn_line("Sep 28 13:41:26 host systemd-logind[572]: New session c527.");
+        assert_eq!(log_filters.filters.len(), 5);
+
+        // Nothing to drop if filters carry no hits
+        let mut no_hits = LogFilters::new();
+        tst_utils::_add_test_filter(&mut no_hits, tst_utils::_simple_filter_from_string("aaa"));
+        assert!(no_hits.prune(1, 1).is_err());
+        assert_eq!(no_hits.prune(1, 0), Ok(PruneReport::default()));
+
+        // Filters differing in more than one slot are kept apart
+        assert_eq!(
+            log_filters.prune(1, 0),
+            Ok(PruneReport {
+                merged: vec![(0, vec![0, 1, 3])],
+                dropped: vec![],
+            })
+        );
+        let mut expected =
+            "[host],[systemd-logind],[Removed],[session],[c524,c525,c526],\n".to_string();
+        expected += "[host],[kernel],[usb],[device],[connected],\n";
+        expected += "[host],[systemd-logind],[New],[session],[c527]";
+        assert_eq!(log_filters.to_string(), expected);
+        assert_eq!(log_filters.filter_meta(0).hits, 4);
+        // transitions follow merged filters
+        assert_eq!(
+            log_filters.filter_meta(0).next,
+            vec![(0, 2), (1, 1), (2, 1)]
+        );
+        assert_eq!(log_filters.filter_meta(1).next, vec![(0, 1)]);
+        assert_eq!(log_filters.word_filters("c526").unwrap(), &vec![0]);
+        assert_eq!(log_filters.word_filters("c527").unwrap(), &vec![2]);
+        assert_eq!(log_filters.word_filters("kernel").unwrap(), &vec![1]);
+        assert_eq!(log_filters.word_filters("New").unwrap(), &vec![2]);
+
+        // Second slot allowed to differ, rare filters dropped
+        assert_eq!(
+            log_filters.prune(2, 2),
+            Ok(PruneReport {
+                merged: vec![(0, vec![0, 2])],
+                dropped: vec![1],
+            })
         );
         assert_eq!(
-            log_filters.words_hash.get(&"ccc".to_string()).unwrap(),
-            &vec![0]
+            log_filters.to_string(),
+            "[host],[systemd-logind],[Removed,New],[session],[c524,c525,c526,c527]"
+        );
+        assert_eq!(log_filters.filter_meta(0).hits, 5);
+        assert_eq!(log_filters.filter_meta(0).next, vec![(0, 3)]);
+        assert!(log_filters.word_filters("kernel").is_none());
+        assert_eq!(log_filters.word_filters("New").unwrap(), &vec![0]);
+
+        // Rules are neither merged nor dropped
+        let manual = FilterMeta {
+            manual: true,
+            ..Default::default()
+        };
+        log_filters.append_filter(
+            tst_utils::_simple_filter_from_string("host systemd-logind Removed session c999"),
+            manual.clone(),
         );
         assert_eq!(
-            log_filters.filters.get(0).unwrap(),
-            &tst_utils::_simple_filter_from_string("aaa bbb ccc")
+            log_filters.prune(2, 2),
+            Ok(PruneReport {
+                merged: vec![],
+                dropped: vec![],
+            })
         );
-        // add_filter does not check if filter already exists
-        log_filters.add_filter(tst_utils::_words_vector_from_string("aaa bbb ccc"));
+        assert_eq!(log_filters.filters.len(), 2);
+        assert_eq!(log_filters.filter_meta(1), manual);
+    }
+
+    #[test]
+    fn learn_line_with_rules() {
+        let mut log_filters = LogFilters::new();
+        log_filters.max_allowed_new_alternatives = 1;
+        log_filters.ignore_first_columns = 1;
+        log_filters.append_filter(
+            vec![
+                vec!["host".to_string()],
+                vec!["sshd".to_string()],
+                vec!["Accepted".to_string()],
+                vec![".*".to_string()],
+            ],
+            FilterMeta {
+                manual: true,
+                ..Default::default()
+            },
+        );
+        // Line matching the rule counts as its hit
+        log_filters.learn_line("Sep 26 09:13:15 host sshd[1]: Accepted publickey");
+        assert_eq!(log_filters.filters.len(), 1);
+        assert_eq!(log_filters.filter_meta(0).hits, 1);
+        // Line which would extend the rule gets its own filter
+        log_filters.learn_line("Sep 26 09:13:15 host sshd[1]: Accepted publickey again");
+        assert_eq!(
+            log_filters.to_string(),
+            "[host],[sshd],[Accepted],[.*],\n[host],[sshd],[Accepted],[publickey],[again]"
+        );
+        assert_eq!(log_filters.filter_meta(0).hits, 1);
+    }
+
+    #[test]
+    fn check_line() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 1;
+        log_filters.learn_line("Sep 26 09:13:15 host kernel: app segfault at 0");
+        log_filters.append_filter(
+            vec![
+                vec![".**".to_string()],
+                vec!["segfault".to_string()],
+                vec![".**".to_string()],
+            ],
+            FilterMeta {
+                manual: true,
+                alert: Some("segfault".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(log_filters.alert_filters, vec![1]);
+        // Alert wins over learned filter and is never matched approximately
+        assert_eq!(
+            log_filters.check_line("Sep 26 09:13:15 host kernel: app segfault at 0"),
+            LineCheck::Alert(1)
+        );
+        assert!(!log_filters.is_line_known("Sep 26 09:13:15 host kernel: app segfault at 0"));
+        log_filters.max_allowed_new_alternatives = 2;
         assert_eq!(
-            log_filters.words_hash.get(&"aaa".to_string()).unwrap(),
-            &vec![0, 1]
+            log_filters.check_line("Sep 26 09:13:15 host segfaults"),
+            LineCheck::Unknown
         );
+        // New words are reported unless slot is expected to vary
         assert_eq!(
-            log_filters.words_hash.get(&"bbb".to_string()).unwrap(),
-            &vec![0, 1]
+            log_filters.check_line("Sep 26 09:13:15 host kernel: app crashed at 0"),
+            LineCheck::NewValues(0, vec![(3, "crashed".to_string())])
         );
+        assert!(log_filters.is_line_known("Sep 26 09:13:15 host kernel: app crashed at 0"));
         assert_eq!(
-            log_filters.words_hash.get(&"ccc".to_string()).unwrap(),
-            &vec![0, 1]
+            log_filters.check_line("Sep 26 09:13:15 host kernel: app crashed at 0 now"),
+            LineCheck::NewValues(0, vec![(3, "crashed".to_string()), (5, "now".to_string())])
         );
+        assert!(log_filters.set_slot_varying(0, 5).is_err());
+        log_filters.set_slot_varying(0, 3).unwrap();
         assert_eq!(
-            log_filters.filters.get(1).unwrap(),
-            &tst_utils::_simple_filter_from_string("aaa bbb ccc")
+            log_filters.filter_to_string(0),
+            "[host],[kernel],[app],[segfault,.~],[at]"
+        );
+        assert_eq!(
+            log_filters.check_line("Sep 26 09:13:15 host kernel: app crashed at 0"),
+            LineCheck::Known(0)
+        );
+        assert_eq!(
+            log_filters.find_line_filter("Sep 26 09:13:15 segfault"),
+            None
+        );
+
+        // Severity higher than learned one is reported
+        log_filters.learn_line("<14>Sep 26 09:13:15 host disk check WARN: usage 80%");
+        log_filters.learn_line("<12>Sep 26 09:13:15 host disk check WARN: usage 90%");
+        assert_eq!(log_filters.filter_meta(2).hits, 2);
+        assert_eq!(log_filters.filter_meta(2).severity, Some(Severity::Warning));
+        assert_eq!(
+            log_filters.check_line("<11>Sep 26 09:13:15 host disk check WARN: usage 99%"),
+            LineCheck::Escalated(2, Severity::Err)
+        );
+        assert_eq!(
+            log_filters.check_line("<14>Sep 26 09:13:15 host disk check WARN: usage 80%"),
+            LineCheck::Known(2)
         );
+        // filters learned without severity accept any severity
+        assert_eq!(
+            log_filters.check_line("<8>Sep 26 09:13:15 host kernel: app crashed at 0"),
+            LineCheck::Known(0)
+        );
+    }
+
+    #[test]
+    fn is_exact_match() {
+        let mut log_filters = LogFilters::new();
+        log_filters.parse_str_lines(&["[aaa],[bbb,.],[.*],[.**],[ccc]"]);
+        for (line, is_exact) in &[
+            ("aaa bbb xxx ccc", true),
+            ("aaa xxx ccc", true),
+            ("aaa xxx yyy zzz ccc", true),
+            ("aaa bbb xxx", false),
+            ("aaa ccc", false),
+            ("aaa bbb xxx ccc ddd", false),
+        ] {
+            let words = tst_utils::_words_vector_from_string(line);
+            assert_eq!(log_filters.is_exact_match(&words, 0), *is_exact, "{}", line);
+        }
+        assert!(!log_filters.is_exact_match(&[], 1));
     }
 
     #[test]
     fn update_hash() {
         // Test what happens if method was used on empty data structure
         let mut log_filters = LogFilters::new();
-        let word = "xxx".to_string();
-        log_filters.update_hash(&word, 0);
+        let word = log_filters.symbols.intern("xxx");
+        log_filters.update_hash(word, 0);
         assert_eq!(log_filters.words_hash.get(&word).is_some(), false);
 
         let mut log_filters = tst_utils::_init_test_data();
         // Trying to add a word not found in any filter
-        let word = "xyz".to_string();
-        log_filters.update_hash(&word, 0);
+        let word = log_filters.symbols.intern("xyz");
+        log_filters.update_hash(word, 0);
         assert_eq!(log_filters.words_hash.get(&word).is_some(), false);
         // Trying to add already existing word should change nothing
-        let word = "aaa".to_string();
+        let word = log_filters.symbols.intern("aaa");
         assert_eq!(log_filters.words_hash.get(&word).unwrap(), &vec![0, 4, 5]);
-        log_filters.update_hash(&word, 0);
+        log_filters.update_hash(word, 0);
         assert_eq!(log_filters.words_hash.get(&word).unwrap(), &vec![0, 4, 5]);
         // Adding new word to hash just after new filter was added
-        let word = "xyz".to_string();
-        log_filters
-            .filters
-            .push(tst_utils::_simple_filter_from_string(&word));
+        let word = log_filters.symbols.intern("xyz");
+        log_filters.filters.push(vec![vec![word]]);
         let last_index: usize = log_filters.filters.len() - 1;
         assert_eq!(log_filters.words_hash.get(&word).is_some(), false);
-        log_filters.update_hash(&word, last_index);
+        log_filters.update_hash(word, last_index);
         assert_eq!(
             log_filters.words_hash.get(&word).unwrap(),
             &vec![last_index]
         );
         // Adding new word to hash when extending existing filter
-        let word = "iii".to_string();
-        log_filters.filters[0].push(vec![word.clone()]);
+        let word = log_filters.symbols.intern("iii");
+        log_filters.filters[0].push(vec![word]);
         assert_eq!(log_filters.words_hash.get(&word).unwrap(), &vec![2]);
-        log_filters.update_hash(&word, 0);
+        log_filters.update_hash(word, 0);
         assert_eq!(log_filters.words_hash.get(&word).unwrap(), &vec![0, 2]);
     }
 
     #[test]
     fn is_word_in_filter() {
-        let log_filters = tst_utils::_init_test_data();
-        assert_eq!(log_filters.is_word_in_filter(&"aaa".to_string(), 0), true);
-        assert_eq!(log_filters.is_word_in_filter(&"aaa".to_string(), 4), true);
-        assert_eq!(log_filters.is_word_in_filter(&"hhh".to_string(), 1), true);
-        assert_eq!(log_filters.is_word_in_filter(&"aaa".to_string(), 1), false);
-        assert_eq!(log_filters.is_word_in_filter(&"xxx".to_string(), 2), false);
-        assert_eq!(
-            log_filters.is_word_in_filter(&"xxx".to_string(), log_filters.filters.len()),
+        let mut log_filters = tst_utils::_init_test_data();
+        let aaa = log_filters.symbols.intern("aaa");
+        let hhh = log_filters.symbols.intern("hhh");
+        let xxx = log_filters.symbols.intern("xxx");
+        let empty = log_filters.symbols.intern("");
+        assert_eq!(log_filters.is_word_in_filter(aaa, 0), true);
+        assert_eq!(log_filters.is_word_in_filter(aaa, 4), true);
+        assert_eq!(log_filters.is_word_in_filter(hhh, 1), true);
+        assert_eq!(log_filters.is_word_in_filter(aaa, 1), false);
+        assert_eq!(log_filters.is_word_in_filter(xxx, 2), false);
+        assert_eq!(
+            log_filters.is_word_in_filter(xxx, log_filters.filters.len()),
             false
         );
-        assert_eq!(log_filters.is_word_in_filter(&"".to_string(), 0), false);
+        assert_eq!(log_filters.is_word_in_filter(empty, 0), false);
     }
 }
@@ -1,16 +1,567 @@
 extern crate getopts;
+extern crate logmap;
 
 use std::env;
-use std::io::{self, BufRead};
+use std::io;
 use std::path::Path;
 use std::process::exit;
 
-mod logmap;
+use logmap::cache::LineCache;
+use logmap::config::{Config, LogFiltersConfig, Precedence, Profile, SettingConflict};
+use logmap::diff::diff;
+use logmap::input::{lines, FeedReport, InvalidLinePolicy};
+use logmap::logmap::{LineCheck, LogFilters};
+use logmap::parallel::{check_lines, learn_lines};
+use logmap::rates::{RateAnomaly, RateMonitor};
+use logmap::rules::{add_alerts, load_alerts, load_rules};
+use logmap::severity::Severity;
+use logmap::stats::Stats;
+use logmap::transitions::TransitionMonitor;
+
+const USAGE: &str = "Usage: logmap [--config PATH] [--profile NAME] COMMAND [OPTIONS]
+
+Commands:
+    learn      Learn filters from standard input and save them to a file
+    check      Print lines from standard input not matching any loaded filter
+    explain    Print the filter matching each line from standard input
+    show       Print internal data structure of loaded filters
+    stats      Print summary of loaded filters
+    merge      Merge several filters files into one
+    diff       Compare two filters files
+    prune      Merge near-duplicate filters and drop rarely matched ones
+    vary       Stop reporting new words of given slots
+    convert    Convert filters file between text and binary format
+    help       Print this help menu
+
+Global options:
+    --config PATH    Read profiles from given file
+                     default: $LOGMAP_CONFIG or ~/.config/logmap/config.toml
+    --profile NAME   Use settings and model file of given profile,
+                     command line options override profile values
+
+Run `logmap COMMAND --help` for options of given command.
+
+Deprecated flags (-m, -p, -l, -s, -d) are still accepted, see `logmap --legacy-help`.";
 
 pub fn main() {
     let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("{}", USAGE);
+        exit(1);
+    }
+
+    let (profile, command_position) = parse_global_options(&args);
+    if command_position >= args.len() {
+        fail(USAGE);
+    }
+    let command = args[command_position].as_str();
+    let command_args = &args[command_position + 1..];
+    match command {
+        "learn" => learn(command_args, &profile),
+        "check" => check(command_args, &profile),
+        "explain" => explain(command_args, &profile),
+        "show" => show(command_args, &profile),
+        "stats" => stats(command_args, &profile),
+        "merge" => merge(command_args, &profile),
+        "diff" => diff_files(command_args),
+        "prune" => prune(command_args, &profile),
+        "vary" => vary(command_args, &profile),
+        "convert" => convert(command_args),
+        "help" | "-h" | "--help" => println!("{}", USAGE),
+        "--legacy-help" => println!("{}", legacy_options().usage("Usage: logmap [OPTIONS]")),
+        _ if command.starts_with('-') => legacy(&args[command_position..], &profile),
+        _ => fail(&format!("Unknown command `{}`\n\n{}", command, USAGE)),
+    }
+    exit(0);
+}
+
+/// Returns selected profile and position of the command within `args`
+fn parse_global_options(args: &[String]) -> (Option<Profile>, usize) {
+    let mut config_path: Option<String> = None;
+    let mut profile_name: Option<String> = None;
+    let mut position = 1;
+    while position < args.len() {
+        let target = match args[position].as_str() {
+            "--config" => &mut config_path,
+            "--profile" => &mut profile_name,
+            _ => break,
+        };
+        match args.get(position + 1) {
+            None => fail(&format!("{} requires a value", args[position])),
+            Some(value) => *target = Some(value.clone()),
+        }
+        position += 2;
+    }
+
+    let profile_name = match profile_name {
+        None => return (None, position),
+        Some(profile_name) => profile_name,
+    };
+    let config_path = match config_path.or_else(default_config_path) {
+        None => fail("--profile requires --config PATH, no default config file found"),
+        Some(config_path) => config_path,
+    };
+    let config = match Config::load(Path::new(&config_path)) {
+        Err(why) => fail(&why),
+        Ok(config) => config,
+    };
+    match config.profile(&profile_name) {
+        None => fail(&format!(
+            "Profile `{}` not found in {}",
+            profile_name, config_path
+        )),
+        Some(profile) => (Some(profile), position),
+    }
+}
+
+fn default_config_path() -> Option<String> {
+    if let Ok(path) = env::var("LOGMAP_CONFIG") {
+        return Some(path);
+    }
+    let home = env::var("HOME").ok()?;
+    let path = Path::new(&home).join(".config/logmap/config.toml");
+    if !path.exists() {
+        return None;
+    }
+
+    Some(path.to_string_lossy().into_owned())
+}
+
+fn learn(args: &[String], profile: &Option<Profile>) {
     let mut opts = getopts::Options::new();
+    opts.optopt(
+        "l",
+        "load",
+        "Extend filters loaded from given path instead of starting from scratch",
+        "PATH",
+    );
+    opts.optopt(
+        "s",
+        "save",
+        "Save learned filters under given path\ndefault: model of selected profile",
+        "PATH",
+    );
+    add_settings_options(&mut opts);
+    add_rules_option(&mut opts);
+    opts.optopt(
+        "",
+        "threads",
+        "Learn on N threads, each learning its share of lines into its own filters merged at the end\nnote: filters may differ from those learned on one thread, see Readme\ndefault value: 1",
+        "UINT",
+    );
+    opts.optflag(
+        "",
+        "override-settings",
+        "Settings given with -c, -a, -i, -D, -w, -T, -g, -b, -t, -k and -P replace those stored in loaded filters\ndefault: stored settings are kept and differing ones reported",
+    );
+    add_invalid_lines_option(&mut opts);
+    add_help_option(&mut opts);
+    let matches = parse_options(&opts, args, "Usage: logmap learn --save PATH [OPTIONS]");
+    let threads = parse_threads(&matches);
+    let policy = parse_invalid_lines(&matches);
+
+    let save_path = model_path(&matches, "s", profile);
+    // model of the profile is extended as long as it already exists
+    let load_path = matches.opt_str("l").or_else(|| {
+        if matches.opt_present("s") || !Path::new(&save_path).exists() {
+            return None;
+        }
+        Some(save_path.clone())
+    });
+
+    let log_filters = match load_path {
+        None => new_log_filters(&matches, profile),
+        Some(load_path) => {
+            let precedence = if matches.opt_present("override-settings") {
+                Precedence::Given
+            } else {
+                Precedence::Model
+            };
+            let settings = settings_from_options(&matches);
+            load_with_settings(&load_path, &settings, profile, precedence)
+        }
+    };
+    let mut log_filters = log_filters;
+    add_rules(&matches, profile, &mut log_filters);
+    learn_from_stdin(&mut log_filters, threads, policy);
+    log_filters.save(Path::new(&save_path));
+}
 
+fn check(args: &[String], profile: &Option<Profile>) {
+    let mut opts = load_options();
+    add_rules_option(&mut opts);
+    add_alerts_options(&mut opts);
+    opts.optopt(
+        "",
+        "min-severity",
+        "Report only lines of at least given severity, lines without severity are always reported\none of: debug, info, notice, warning, err, crit, alert, emerg",
+        "LEVEL",
+    );
+    opts.optopt(
+        "",
+        "rate-factor",
+        "Report filters matching F times more or less lines within a time window than learned\nnote: model must be learned with -b\ndefault value: 10",
+        "F",
+    );
+    opts.optopt(
+        "",
+        "min-transitions",
+        "Report lines following a line of the same stream in a way seen less than N times while learning\nnote: model must be learned with -t or -k\ndefault value: 1",
+        "UINT",
+    );
+    opts.optopt(
+        "",
+        "threads",
+        "Match lines on N threads, lines are reported in input order\ndefault value: 1",
+        "UINT",
+    );
+    opts.optopt(
+        "",
+        "cache",
+        "Remember outcomes of up to N most recently seen lines, lines made of the same words are not matched again\ncache hit rate is printed along with progress\ndefault value: 0 (no cache)",
+        "UINT",
+    );
+    add_invalid_lines_option(&mut opts);
+    let brief = "Usage: logmap check --load PATH [OPTIONS]\n\n\
+                 Unknown lines are printed as they are, lines matching an alert are\n\
+                 printed as `ALERT NAME: LINE` even if they match a learned filter and\n\
+                 known lines stating higher severity than learned as `ESCALATED LEVEL: LINE`.\n\
+                 Filters matching unusual number of lines are printed as\n\
+                 `RATE #N COUNT lines, usually BASELINE: FILTER` after each time window\n\
+                 (`RATE #N 0 lines in W windows, ...` for W windows without lines)\n\
+                 and known lines following a line in an unusual way as\n\
+                 `TRANSITION #FROM -> #TO seen COUNT times: LINE`.";
+    let matches = parse_options(&opts, args, brief);
+
+    let min_severity = match matches.opt_str("min-severity") {
+        None => profile.as_ref().and_then(|profile| profile.min_severity),
+        Some(level) => match Severity::from_name(&level) {
+            None => fail(&format!("Unknown severity `{}`", level)),
+            severity => severity,
+        },
+    };
+    let rate_factor = match matches.opt_str("rate-factor") {
+        None => profile
+            .as_ref()
+            .and_then(|profile| profile.rate_factor)
+            .unwrap_or(10.0),
+        Some(factor) => match factor.parse::<f64>() {
+            Ok(factor) if factor > 1.0 => factor,
+            _ => fail(&format!(
+                "Couldn't parse `--rate-factor` to number above 1: {}",
+                factor
+            )),
+        },
+    };
+    let mut log_filters = load_with_settings(
+        &model_path(&matches, "l", profile),
+        &LogFiltersConfig::new(),
+        profile,
+        Precedence::Model,
+    );
+    add_rules(&matches, profile, &mut log_filters);
+    add_alerts_from_options(&matches, profile, &mut log_filters);
+    let min_transitions = parse_uint(&matches, "min-transitions")
+        .or_else(|| profile.as_ref().and_then(|profile| profile.min_transitions))
+        .unwrap_or(1);
+    let threads = parse_threads(&matches);
+    let cache = parse_uint(&matches, "cache")
+        .filter(|capacity| *capacity > 0)
+        .map(LineCache::new);
+    let rate_monitor = RateMonitor::new(&log_filters, rate_factor);
+    let transition_monitor = TransitionMonitor::new(&log_filters, min_transitions);
+    check_stdin(
+        &log_filters,
+        threads,
+        cache.as_ref(),
+        parse_invalid_lines(&matches),
+        min_severity,
+        rate_monitor,
+        transition_monitor,
+    );
+}
+
+fn explain(args: &[String], profile: &Option<Profile>) {
+    let mut opts = load_options();
+    add_rules_option(&mut opts);
+    add_alerts_options(&mut opts);
+    add_invalid_lines_option(&mut opts);
+    let matches = parse_options(&opts, args, "Usage: logmap explain --load PATH [OPTIONS]");
+    let policy = parse_invalid_lines(&matches);
+
+    let mut log_filters = load_with_settings(
+        &model_path(&matches, "l", profile),
+        &LogFiltersConfig::new(),
+        profile,
+        Precedence::Model,
+    );
+    add_rules(&matches, profile, &mut log_filters);
+    add_alerts_from_options(&matches, profile, &mut log_filters);
+    let std_in = io::stdin();
+    let mut report = FeedReport::default();
+    for line in lines(std_in.lock()) {
+        let log_line = match policy.apply(line, &mut report) {
+            None => continue,
+            Some(log_line) => log_line,
+        };
+        println!("{}", &log_line);
+        println!("    words:  {:?}", log_filters.line_to_words(&log_line));
+        match log_filters.check_line(&log_line) {
+            LineCheck::Known(filter_index) => println!(
+                "    filter: #{} {}",
+                filter_index,
+                log_filters.filter_to_string(filter_index)
+            ),
+            LineCheck::Alert(filter_index) => println!(
+                "    alert:  {} {}",
+                log_filters
+                    .filter_meta(filter_index)
+                    .alert
+                    .unwrap_or_default(),
+                log_filters.filter_to_string(filter_index)
+            ),
+            LineCheck::Escalated(filter_index, severity) => println!(
+                "    filter: #{} {}\n    severity: {}, learned up to {}",
+                filter_index,
+                log_filters.filter_to_string(filter_index),
+                severity,
+                log_filters
+                    .filter_meta(filter_index)
+                    .severity
+                    .map_or("none", |severity| severity.name())
+            ),
+            LineCheck::NewValues(filter_index, new_values) => println!(
+                "    filter: #{} {}\n    new values: {}",
+                filter_index,
+                log_filters.filter_to_string(filter_index),
+                render_new_values(&new_values)
+            ),
+            LineCheck::Unknown => println!("    filter: none, line is unknown"),
+        }
+    }
+    report_invalid_lines(&report);
+}
+
+fn show(args: &[String], profile: &Option<Profile>) {
+    let opts = load_options();
+    let matches = parse_options(&opts, args, "Usage: logmap show --load PATH");
+
+    LogFilters::load(Path::new(&model_path(&matches, "l", profile))).print();
+}
+
+fn stats(args: &[String], profile: &Option<Profile>) {
+    let mut opts = load_options();
+    opts.optflag("j", "json", "Print report as JSON");
+    opts.optopt(
+        "t",
+        "top",
+        "Number of elements shown in each ranking\ndefault value: 10",
+        "UINT",
+    );
+    let matches = parse_options(&opts, args, "Usage: logmap stats --load PATH [OPTIONS]");
+
+    let log_filters = LogFilters::load(Path::new(&model_path(&matches, "l", profile)));
+    let stats = Stats::new(&log_filters, parse_uint(&matches, "t").unwrap_or(10));
+    if matches.opt_present("j") {
+        println!("{}", stats.to_json(&log_filters));
+    } else {
+        print!("{}", stats.to_text(&log_filters));
+    }
+}
+
+fn merge(args: &[String], profile: &Option<Profile>) {
+    let mut opts = getopts::Options::new();
+    opts.optopt(
+        "s",
+        "save",
+        "Save merged filters under given path\ndefault: model of selected profile",
+        "PATH",
+    );
+    add_help_option(&mut opts);
+    let brief = "Usage: logmap merge --save PATH FILE FILE...\n\n\
+                 Filters of each next FILE are unified with best matching filters of the\n\
+                 previous ones or appended if no filter matches.";
+    let matches = parse_options_with_free(&opts, args, brief);
+    if matches.free.len() < 2 {
+        fail(&format!(
+            "At least two files are required\n\n{}",
+            opts.usage(brief)
+        ));
+    }
+
+    let mut log_filters = LogFilters::load(Path::new(&matches.free[0]));
+    for load_path in &matches.free[1..] {
+        let other = LogFilters::load(Path::new(load_path));
+        match log_filters.merge(&other) {
+            Err(why) => fail(&format!("Couldn't merge {}: {}", load_path, why)),
+            Ok(report) => eprintln!(
+                "Merged {}: {} filters unified, {} filters appended",
+                load_path, report.unified, report.appended
+            ),
+        }
+    }
+    log_filters.save(Path::new(&model_path(&matches, "s", profile)));
+}
+
+fn diff_files(args: &[String]) {
+    let mut opts = getopts::Options::new();
+    add_help_option(&mut opts);
+    let brief = "Usage: logmap diff OLD NEW\n\n\
+                 Lines start with `!` for differing settings, `-` for removed filters,\n\
+                 `+` for added filters and `~` for filters which gained alternatives\n\
+                 (prefixed with `+`) or optional slots. Exits with 1 if files differ.";
+    let matches = parse_options_with_free(&opts, args, brief);
+    if matches.free.len() != 2 {
+        fail(&format!(
+            "Exactly two files are required\n\n{}",
+            opts.usage(brief)
+        ));
+    }
+
+    let old = LogFilters::load(Path::new(&matches.free[0]));
+    let new = LogFilters::load(Path::new(&matches.free[1]));
+    let filters_diff = diff(&old, &new);
+    print!("{}", filters_diff.to_text(&old, &new));
+    if !filters_diff.is_empty() {
+        exit(1);
+    }
+}
+
+fn convert(args: &[String]) {
+    let mut opts = getopts::Options::new();
+    opts.optflag(
+        "b",
+        "binary",
+        "Save in binary format instead of text format",
+    );
+    add_help_option(&mut opts);
+    let brief = "Usage: logmap convert [--binary] IN OUT\n\n\
+                 Filters of IN are loaded from either format and saved to OUT. Binary\n\
+                 files load without parsing, every command reads them like text files.";
+    let matches = parse_options_with_free(&opts, args, brief);
+    if matches.free.len() != 2 {
+        fail(&format!(
+            "Exactly two files are required\n\n{}",
+            opts.usage(brief)
+        ));
+    }
+
+    let log_filters = LogFilters::load(Path::new(&matches.free[0]));
+    let save_path = Path::new(&matches.free[1]);
+    if matches.opt_present("b") {
+        log_filters.save_binary(save_path);
+    } else {
+        log_filters.save(save_path);
+    }
+}
+
+fn prune(args: &[String], profile: &Option<Profile>) {
+    let mut opts = load_options();
+    opts.optopt(
+        "s",
+        "save",
+        "Save pruned filters under given path\ndefault: model of selected profile",
+        "PATH",
+    );
+    opts.optopt(
+        "n",
+        "max-differing-slots",
+        "Merge filters of the same length differing in at most N slots\ndefault value: 1",
+        "UINT",
+    );
+    opts.optopt(
+        "m",
+        "min-hits",
+        "Drop filters matched by less than N learned lines\ndefault value: 0 (nothing is dropped)",
+        "UINT",
+    );
+    let matches = parse_options(
+        &opts,
+        args,
+        "Usage: logmap prune --load PATH --save PATH [OPTIONS]",
+    );
+
+    let mut log_filters = LogFilters::load(Path::new(&model_path(&matches, "l", profile)));
+    let max_differing_slots = parse_uint(&matches, "n").unwrap_or(1);
+    let min_hits = parse_uint(&matches, "m").unwrap_or(0);
+    let report = match log_filters.prune(max_differing_slots, min_hits) {
+        Err(why) => fail(&why),
+        Ok(report) => report,
+    };
+    for (filter_index, sources) in &report.merged {
+        let sources: Vec<String> = sources.iter().map(|index| format!("#{}", index)).collect();
+        eprintln!(
+            "Merged {} into #{} {}",
+            sources.join(", "),
+            filter_index,
+            log_filters.filter_to_string(*filter_index)
+        );
+    }
+    if !report.dropped.is_empty() {
+        let dropped: Vec<String> = report
+            .dropped
+            .iter()
+            .map(|index| format!("#{}", index))
+            .collect();
+        eprintln!("Dropped {}", dropped.join(", "));
+    }
+    eprintln!(
+        "{} filters merged into {}, {} filters dropped, {} filters left",
+        report
+            .merged
+            .iter()
+            .map(|(_, sources)| sources.len())
+            .sum::<usize>(),
+        report.merged.len(),
+        report.dropped.len(),
+        log_filters.filters_count()
+    );
+    log_filters.save(Path::new(&model_path(&matches, "s", profile)));
+}
+
+fn vary(args: &[String], profile: &Option<Profile>) {
+    let mut opts = load_options();
+    opts.optopt(
+        "s",
+        "save",
+        "Save filters under given path\ndefault: loaded path",
+        "PATH",
+    );
+    let brief = "Usage: logmap vary --load PATH [--save PATH] FILTER:SLOT...\n\n\
+                 Words of given slots, counted from 0, are expected to vary so `check`\n\
+                 does not report them as new values.";
+    let matches = parse_options_with_free(&opts, args, brief);
+    if matches.free.is_empty() {
+        fail("At least one FILTER:SLOT expected, e.g. `logmap vary -l PATH 3:4`");
+    }
+
+    let load_path = model_path(&matches, "l", profile);
+    let mut log_filters = LogFilters::load(Path::new(&load_path));
+    for filter_slot in &matches.free {
+        let parsed = filter_slot.find(':').and_then(|position| {
+            filter_slot[..position]
+                .parse::<usize>()
+                .ok()
+                .zip(filter_slot[position + 1..].parse::<usize>().ok())
+        });
+        let (filter_index, slot) = match parsed {
+            None => fail(&format!("FILTER:SLOT expected, found `{}`", filter_slot)),
+            Some(parsed) => parsed,
+        };
+        if let Err(why) = log_filters.set_slot_varying(filter_index, slot) {
+            fail(&why);
+        }
+        eprintln!(
+            "#{} {}",
+            filter_index,
+            log_filters.filter_to_string(filter_index)
+        );
+    }
+    log_filters.save(Path::new(&matches.opt_str("s").unwrap_or(load_path)));
+}
+
+fn legacy_options() -> getopts::Options {
+    let mut opts = getopts::Options::new();
     opts.optopt(
         "l",
         "load",
@@ -23,9 +574,7 @@ pub fn main() {
         "Save filters under given path, does not work when piping",
         "PATH",
     );
-    opts.optopt("c", "columns", "Ignore first N columns of input\ncolumns are created by splitting line by .,:/[]{}() \'\"\ndefault value: 2\nnote: set this value to a number allowing to ignore time stamp)", "UINT");
-    opts.optopt("a", "allowed-alternatives", "during analysis each new line will be allowed not to match N times\ndefault value: 0\nrecommended value when analysing: 1 or 2", "UINT");
-    opts.optflag("i", "ignore-numeric", "DO NOT ignore words containing only numbers\ndefault value: true (words containing only values are removed before analysing)");
+    add_settings_options(&mut opts);
     opts.optflag(
         "m",
         "map",
@@ -37,85 +586,491 @@ pub fn main() {
         "Works only in conjunction with `l`. Analyse logs using loaded filters.",
     );
     opts.optflag("d", "debug", "Print internal data structure");
-    opts.optflag("h", "help", "Print this help menu");
+    add_help_option(&mut opts);
+
+    opts
+}
 
-    let matches = match opts.parse(&args) {
-        Ok(_option) => _option,
-        Err(_) => {
-            println!("{}", opts.usage(""));
-            exit(1);
+fn legacy(args: &[String], profile: &Option<Profile>) {
+    let opts = legacy_options();
+    let matches = parse_options(&opts, args, "Usage: logmap [OPTIONS]");
+
+    if matches.opt_present("m") && matches.opt_present("p") {
+        fail("-m and -p cannot be used together, both consume standard input");
+    }
+    if matches.opt_present("p") && !matches.opt_present("l") {
+        fail("-p requires filters loaded with -l");
+    }
+    if matches.opt_present("m") {
+        eprintln!("Warning: -m is deprecated, use `logmap learn` instead");
+    } else if matches.opt_present("p") {
+        eprintln!("Warning: -p is deprecated, use `logmap check` instead");
+    } else if matches.opt_present("d") {
+        eprintln!("Warning: -d is deprecated, use `logmap show` instead");
+    } else {
+        eprintln!("Warning: flags are deprecated, run `logmap help` to see available commands");
+    }
+
+    let mut log_filters = match matches.opt_str("l") {
+        None => new_log_filters(&matches, profile),
+        Some(load_path) => {
+            let settings = settings_from_options(&matches);
+            load_with_settings(&load_path, &settings, profile, Precedence::Model)
         }
     };
+    if matches.opt_present("m") {
+        learn_from_stdin(&mut log_filters, 1, InvalidLinePolicy::Lossy);
+    }
+    if matches.opt_present("d") {
+        log_filters.print();
+    }
+    if matches.opt_present("p") {
+        print_unknown_lines(&log_filters);
+    }
+    if let Some(save_path) = matches.opt_str("s") {
+        log_filters.save(Path::new(&save_path));
+    }
+}
+
+fn add_settings_options(opts: &mut getopts::Options) {
+    opts.optopt("c", "columns", "Ignore first N columns of input\ncolumns are created by splitting line by .,:/[]{}() \'\"\ndefault value: 2\nnote: set this value to a number allowing to ignore time stamp)", "UINT");
+    opts.optopt("a", "allowed-alternatives", "during analysis each new line will be allowed not to match N times\ndefault value: 0\nrecommended value when analysing: 1 or 2", "UINT");
+    opts.optflag("i", "ignore-numeric", "DO NOT ignore words containing only numbers\ndefault value: true (words containing only values are removed before analysing)");
+    opts.optopt("D", "delimiters", "Characters splitting line into words\ndefault value: .,:/[]{}() \'\"\nnote: [ ] , and . must always be included", "CHARS");
+    opts.optopt("w", "max-slot-alternatives", "Replace words of a column with wildcard once it has more than N alternatives\ndefault value: 0 (no limit)", "UINT");
+    opts.optflag("T", "infer-types", "Replace alternatives of a column with their common type: int, hex, id or ip\nnote: a single word is never replaced");
+    opts.optopt("g", "min-gap-slots", "Replace columns by a gap matching any number of words where a line differs from the filter by at least N consecutive words\ndefault value: 0 (no gaps)", "UINT");
+    opts.optopt("b", "rate-bucket", "Learn how many lines each filter matches within time windows of N seconds\ndefault value: 0 (rates are not learned)", "UINT");
+    opts.optflag(
+        "t",
+        "transitions",
+        "Learn which filters match consecutive lines",
+    );
+    opts.optopt("k", "stream-column", "Learn transitions separately for each value of column N (e.g. host or program), implies -t\ncolumns are counted before any column is ignored", "UINT");
+    opts.optopt("P", "prefix-index", "Find filters matching a line by its first N words and its length instead of by all its words\nnote: lines differing in the first N words from a filter get new filters\ndefault value: 0 (filters are found by all words)", "UINT");
+}
+
+fn load_options() -> getopts::Options {
+    let mut opts = getopts::Options::new();
+    opts.optopt(
+        "l",
+        "load",
+        "Load filters from given path\ndefault: model of selected profile",
+        "PATH",
+    );
+    add_help_option(&mut opts);
+
+    opts
+}
+
+fn add_rules_option(opts: &mut getopts::Options) {
+    opts.optopt(
+        "r",
+        "rules",
+        "Add hand-written filters from given file, learning never changes them\ndefault: rules of selected profile",
+        "PATH",
+    );
+}
 
-    if matches.opt_present("h") {
-        println!("{}", opts.usage(""));
+fn add_alerts_options(opts: &mut getopts::Options) {
+    opts.optopt(
+        "A",
+        "alerts",
+        "Report lines matching alerts from given file, one `NAME: RULE` per line\ndefault: alerts of selected profile",
+        "PATH",
+    );
+    opts.optmulti(
+        "",
+        "alert",
+        "Report lines matching given alert, may be repeated",
+        "'NAME: RULE'",
+    );
+}
+
+fn add_invalid_lines_option(opts: &mut getopts::Options) {
+    opts.optopt(
+        "",
+        "invalid-lines",
+        "What to do with lines which are not valid UTF-8, they are counted once input ends\none of: skip, lossy (invalid bytes become U+FFFD), hex (invalid bytes become \\xNN)\ndefault value: lossy",
+        "POLICY",
+    );
+}
+
+fn add_help_option(opts: &mut getopts::Options) {
+    opts.optflag("h", "help", "Print this help menu");
+}
+
+/// Path given with option `name`, model of the profile otherwise
+fn model_path(matches: &getopts::Matches, name: &str, profile: &Option<Profile>) -> String {
+    if let Some(path) = matches.opt_str(name) {
+        return path;
+    }
+    match profile.as_ref().and_then(|profile| profile.model.clone()) {
+        None => fail(&format!(
+            "-{} PATH is required unless selected profile defines `model`",
+            name
+        )),
+        Some(path) => path,
+    }
+}
+
+fn parse_options(opts: &getopts::Options, args: &[String], brief: &str) -> getopts::Matches {
+    let matches = parse_options_with_free(opts, args, brief);
+    if !matches.free.is_empty() {
+        fail(&format!(
+            "Unexpected argument `{}`\n\n{}",
+            matches.free[0],
+            opts.usage(brief)
+        ));
+    }
+
+    matches
+}
+
+/// Same as `parse_options` but arguments which are not options are allowed
+fn parse_options_with_free(
+    opts: &getopts::Options,
+    args: &[String],
+    brief: &str,
+) -> getopts::Matches {
+    // help must win over missing required options
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", opts.usage(brief));
         exit(0);
     }
+    match opts.parse(args) {
+        Ok(matches) => matches,
+        Err(why) => fail(&format!("{}\n\n{}", why, opts.usage(brief))),
+    }
+}
 
-    let mut log_filters = logmap::LogFilters::new();
-    log_filters.ignore_first_columns = 2;
-    log_filters.max_allowed_new_alternatives = 0;
-    log_filters.ignore_numeric_words = true;
+fn parse_uint(matches: &getopts::Matches, name: &str) -> Option<usize> {
+    matches
+        .opt_str(name)
+        .map(|value| match value.parse::<usize>() {
+            Err(_) => fail(&format!("Couldn't parse `-{}` to UINT: {}", name, value)),
+            Ok(value) => value,
+        })
+}
 
-    if matches.opt_str("c").is_some() {
-        log_filters.ignore_first_columns =
-            match matches.opt_str("c").unwrap().to_string().parse::<usize>() {
-                Err(_) => panic!(
-                    "Couldn't parse `columns` to UINT: {}",
-                    matches.opt_str("c").unwrap()
-                ),
-                Ok(value) => value,
-            };
+fn parse_invalid_lines(matches: &getopts::Matches) -> InvalidLinePolicy {
+    match matches.opt_str("invalid-lines") {
+        None => InvalidLinePolicy::Lossy,
+        Some(name) => match InvalidLinePolicy::from_name(&name) {
+            None => fail(&format!(
+                "Unknown `--invalid-lines` policy `{}`, expected skip, lossy or hex",
+                name
+            )),
+            Some(policy) => policy,
+        },
     }
-    if matches.opt_str("a").is_some() {
-        log_filters.max_allowed_new_alternatives =
-            match matches.opt_str("a").unwrap().to_string().parse::<usize>() {
-                Err(_) => panic!(
-                    "Couldn't parse `columns` to UINT: {}",
-                    matches.opt_str("a").unwrap()
-                ),
-                Ok(value) => value,
-            };
+}
+
+fn report_invalid_lines(report: &FeedReport) {
+    if report.skipped > 0 {
+        eprintln!("Skipped {} lines which are not valid UTF-8", report.skipped);
     }
-    if matches.opt_str("i").is_some() {
-        log_filters.ignore_numeric_words = false;
+    if report.repaired > 0 {
+        eprintln!(
+            "Repaired {} lines which are not valid UTF-8",
+            report.repaired
+        );
     }
-    if matches.opt_str("l").is_some() {
-        let file_path_str = matches.opt_str("l").unwrap();
-        let load_file_path = Path::new(&file_path_str);
-        log_filters = logmap::LogFilters::load(load_file_path);
+}
+
+fn parse_threads(matches: &getopts::Matches) -> usize {
+    match parse_uint(matches, "threads") {
+        None => 1,
+        Some(0) => fail("`--threads` must be at least 1"),
+        Some(threads) => threads,
     }
-    if matches.opt_present("m") {
-        let std_in = io::stdin();
-        let mut icnt = 0;
-        for line in std_in.lock().lines() {
-            let log_line = line.expect("INVALID INPUT!");
-            log_filters.learn_line(&log_line);
+}
 
-            // Debug to help assessing performance
-            icnt += 1;
-            if icnt % 10000 == 0 {
-                eprintln!("Already processed {} lines.", icnt);
-            }
+/// Settings given with -c, -a, -i, -D, -w, -T, -g, -b, -t, -k and -P
+fn settings_from_options(matches: &getopts::Matches) -> LogFiltersConfig {
+    let mut settings = LogFiltersConfig::new();
+    if let Some(columns) = parse_uint(matches, "c") {
+        settings = settings.ignore_first_columns(columns);
+    }
+    if let Some(alternatives) = parse_uint(matches, "a") {
+        settings = settings.max_allowed_new_alternatives(alternatives);
+    }
+    if matches.opt_present("i") {
+        settings = settings.ignore_numeric_words(false);
+    }
+    if let Some(delimiters) = matches.opt_str("D") {
+        settings = settings.delimiters(&delimiters);
+    }
+    if let Some(alternatives) = parse_uint(matches, "w") {
+        settings = settings.max_slot_alternatives(alternatives);
+    }
+    if matches.opt_present("T") {
+        settings = settings.infer_slot_types(true);
+    }
+    if let Some(slots) = parse_uint(matches, "g") {
+        settings = settings.min_gap_slots(slots);
+    }
+    if let Some(seconds) = parse_uint(matches, "b") {
+        settings = settings.rate_bucket_seconds(seconds);
+    }
+    if matches.opt_present("t") {
+        settings = settings.learn_transitions(true);
+    }
+    if let Some(column) = parse_uint(matches, "k") {
+        settings = settings
+            .learn_transitions(true)
+            .transition_stream_column(column);
+    }
+    if let Some(tokens) = parse_uint(matches, "P") {
+        settings = settings.prefix_index_tokens(tokens);
+    }
+
+    settings
+}
+
+/// Settings of the profile overridden by settings given on command line
+fn new_log_filters(matches: &getopts::Matches, profile: &Option<Profile>) -> LogFilters {
+    let mut log_filters = LogFilters::new();
+    if let Some(profile) = profile {
+        if let Err(why) = profile.apply(&mut log_filters) {
+            fail(&format!("Invalid profile `{}`: {}", profile.name, why));
         }
     }
-    if matches.opt_present("d") {
-        log_filters.print();
+    if let Err(why) = settings_from_options(matches).apply(&mut log_filters) {
+        fail(&why);
     }
-    if matches.opt_present("p") {
-        let std_in = io::stdin();
-        for line in std_in.lock().lines() {
-            let log_line = line.expect("INVALID INPUT!");
-            if log_filters.is_line_known(&log_line) {
-                continue;
+
+    log_filters
+}
+
+/// Filters loaded from `load_path` with settings of the profile applied first and
+/// `settings` given on command line then. Values of the profile replace stored
+/// ones unless given on command line, values given on command line only do with
+/// `Precedence::Given`. Each value differing from the stored one is reported.
+fn load_with_settings(
+    load_path: &str,
+    settings: &LogFiltersConfig,
+    profile: &Option<Profile>,
+    precedence: Precedence,
+) -> LogFilters {
+    let mut log_filters = LogFilters::load(Path::new(load_path));
+    if let Some(profile) = profile {
+        let profile_settings = profile.log_filters_config().without(settings);
+        let conflicts = profile_settings.conflicts(&log_filters);
+        if let Err(why) = profile_settings.apply(&mut log_filters) {
+            fail(&format!(
+                "Couldn't apply profile `{}` to {}: {}",
+                profile.name, load_path, why
+            ));
+        }
+        let source = format!("in profile `{}`", profile.name);
+        report_conflicts(load_path, &conflicts, &source, "profile");
+    }
+
+    let conflicts = settings.conflicts(&log_filters);
+    let kept = match precedence {
+        Precedence::Model => "stored",
+        Precedence::Given => "given",
+    };
+    if precedence == Precedence::Given {
+        if let Err(why) = settings.apply(&mut log_filters) {
+            fail(&format!(
+                "Couldn't apply settings to {}: {}",
+                load_path, why
+            ));
+        }
+    }
+    report_conflicts(load_path, &conflicts, "on command line", kept);
+
+    log_filters
+}
+
+fn report_conflicts(load_path: &str, conflicts: &[SettingConflict], source: &str, kept: &str) {
+    for conflict in conflicts {
+        eprintln!(
+            "Warning: `{}` is `{}` in {} but `{}` {}, {} value is used",
+            conflict.name, conflict.stored, load_path, conflict.given, source, kept
+        );
+    }
+}
+
+/// Rules given with -r or rules of the profile
+fn add_rules(matches: &getopts::Matches, profile: &Option<Profile>, log_filters: &mut LogFilters) {
+    let rules_path = matches
+        .opt_str("r")
+        .or_else(|| profile.as_ref().and_then(|profile| profile.rules.clone()));
+    if let Some(rules_path) = rules_path {
+        if let Err(why) = load_rules(log_filters, Path::new(&rules_path)) {
+            fail(&why);
+        }
+    }
+}
+
+/// Alerts from -A file or alerts file of the profile, followed by each --alert
+fn add_alerts_from_options(
+    matches: &getopts::Matches,
+    profile: &Option<Profile>,
+    log_filters: &mut LogFilters,
+) {
+    let alerts_path = matches
+        .opt_str("A")
+        .or_else(|| profile.as_ref().and_then(|profile| profile.alerts.clone()));
+    if let Some(alerts_path) = alerts_path {
+        if let Err(why) = load_alerts(log_filters, Path::new(&alerts_path)) {
+            fail(&why);
+        }
+    }
+    for alert in matches.opt_strs("alert") {
+        if let Err(why) = add_alerts(log_filters, &alert) {
+            fail(&format!("Invalid --alert `{}`: {}", alert, why));
+        }
+    }
+}
+
+fn learn_from_stdin(log_filters: &mut LogFilters, threads: usize, policy: InvalidLinePolicy) {
+    let std_in = io::stdin();
+    // Debug to help assessing performance
+    let report = learn_lines(log_filters, std_in.lock(), threads, policy, |lines| {
+        eprintln!("Already processed {} lines.", lines)
+    });
+    report_invalid_lines(&report);
+}
+
+/// Output of deprecated `-p`, kept as it was for scripts reading it: unknown lines as they
+/// are, nothing is printed for known lines, even those `check` reports new values of
+fn print_unknown_lines(log_filters: &LogFilters) {
+    let std_in = io::stdin();
+    let mut report = FeedReport::default();
+    for line in lines(std_in.lock()) {
+        if let Some(log_line) = InvalidLinePolicy::Lossy.apply(line, &mut report) {
+            if !log_filters.is_line_known(&log_line) {
+                println!("{}", &log_line);
             }
-            println!("{}", &log_line);
         }
     }
-    if matches.opt_str("s").is_some() {
-        let file_path_str = matches.opt_str("s").unwrap();
-        let save_file_path = Path::new(&file_path_str);
-        log_filters.save(&save_file_path);
+    report_invalid_lines(&report);
+}
+
+/// Alerts, rates and transitions are reported regardless of `min_severity`.
+/// Lines are matched on `threads` threads yet reported in the order of input.
+fn check_stdin(
+    log_filters: &LogFilters,
+    threads: usize,
+    cache: Option<&LineCache>,
+    policy: InvalidLinePolicy,
+    min_severity: Option<Severity>,
+    mut rate_monitor: Option<RateMonitor>,
+    mut transition_monitor: Option<TransitionMonitor>,
+) {
+    let print_anomalies = |anomalies: Vec<RateAnomaly>| {
+        for anomaly in anomalies {
+            let windows = match anomaly.windows {
+                1 => String::new(),
+                windows => format!(" in {} windows", windows),
+            };
+            println!(
+                "RATE #{} {} lines{}, usually {:.1}: {}",
+                anomaly.filter_index,
+                anomaly.count,
+                windows,
+                anomaly.baseline,
+                log_filters.filter_to_string(anomaly.filter_index)
+            );
+        }
+    };
+    let is_severe = |log_line: &str| match (min_severity, Severity::of_line(log_line)) {
+        (Some(min_severity), Some(severity)) => severity >= min_severity,
+        _ => true,
+    };
+    let input = io::BufReader::new(io::stdin());
+    let mut icnt = 0;
+    let report = check_lines(
+        log_filters,
+        input,
+        threads,
+        cache,
+        policy,
+        |log_line, line_check| {
+            // Debug to help assessing performance
+            icnt += 1;
+            if icnt % 10000 == 0 {
+                match cache {
+                    None => eprintln!("Already processed {} lines.", icnt),
+                    Some(cache) => eprintln!(
+                        "Already processed {} lines, cache hit rate {:.1}%.",
+                        icnt,
+                        cache.hit_rate() * 100.0
+                    ),
+                }
+            }
+            let filter_index = match line_check {
+                LineCheck::Known(filter_index)
+                | LineCheck::Escalated(filter_index, _)
+                | LineCheck::NewValues(filter_index, _) => Some(filter_index),
+                _ => None,
+            };
+            if let Some(ref mut rate_monitor) = rate_monitor {
+                print_anomalies(rate_monitor.observe(&log_line, filter_index));
+            }
+            // alerts are not learned so they neither follow nor break sequences
+            let rare_transition = match (&mut transition_monitor, &line_check) {
+                (Some(_), LineCheck::Alert(_)) | (None, _) => None,
+                (Some(transition_monitor), _) => {
+                    transition_monitor.observe(log_filters, &log_line, filter_index)
+                }
+            };
+            match line_check {
+                LineCheck::Known(_) => (),
+                LineCheck::Unknown if is_severe(&log_line) => println!("{}", &log_line),
+                LineCheck::Unknown => (),
+                LineCheck::Escalated(_, severity) if is_severe(&log_line) => {
+                    println!("ESCALATED {}: {}", severity, &log_line)
+                }
+                LineCheck::Escalated(_, _) => (),
+                LineCheck::NewValues(filter_index, ref new_values) if is_severe(&log_line) => {
+                    println!(
+                        "NEW #{} {}: {}",
+                        filter_index,
+                        render_new_values(new_values),
+                        &log_line
+                    )
+                }
+                LineCheck::NewValues(_, _) => (),
+                LineCheck::Alert(filter_index) => println!(
+                    "ALERT {}: {}",
+                    log_filters
+                        .filter_meta(filter_index)
+                        .alert
+                        .unwrap_or_default(),
+                    &log_line
+                ),
+            }
+            // lines with new values or higher severity follow their previous line as well
+            if let Some(rare_transition) = rare_transition {
+                println!(
+                    "TRANSITION #{} -> #{} seen {} times: {}",
+                    rare_transition.from, rare_transition.to, rare_transition.count, &log_line
+                );
+            }
+        },
+    );
+    if let Some(ref mut rate_monitor) = rate_monitor {
+        print_anomalies(rate_monitor.finish());
     }
-    exit(0);
+    report_invalid_lines(&report);
+}
+
+/// `slot 3=alice, slot 5=bob`
+fn render_new_values(new_values: &[(usize, String)]) -> String {
+    let rendered: Vec<String> = new_values
+        .iter()
+        .map(|(slot, word)| format!("slot {}={}", slot, word))
+        .collect();
+
+    rendered.join(", ")
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    exit(1);
 }
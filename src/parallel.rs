@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::mem;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+use cache::LineCache;
+use input::{lines, Feed, FeedReport, InvalidLinePolicy};
+use logmap::{LineCheck, LogFilters};
+
+/// Lines read ahead of matchers for each matcher thread, bounds memory use
+/// when matching falls behind reading
+const QUEUED_LINES_PER_THREAD: usize = 64;
+/// Lines learned by a shard in a row, consecutive chunks go to different shards
+const CHUNK_LINES: usize = 10000;
+
+/// Check each line of `input` with `LogFilters::check_line` and pass it along with
+/// the outcome to `handle` in the order of `input`. With more than one thread a
+/// reader thread feeds lines to `threads` matcher threads sharing `log_filters`
+/// while `handle` runs on the calling thread, with one thread lines are checked
+/// on the calling thread. Each line is handed on as soon as all preceding lines
+/// are, so a live stream is followed either way. Lines are checked through `cache`
+/// if given, see `LineCache`. Lines which are not valid UTF-8 are handled by `policy`,
+/// returned report counts them.
+pub fn check_lines<R, F>(
+    log_filters: &LogFilters,
+    input: R,
+    threads: usize,
+    cache: Option<&LineCache>,
+    policy: InvalidLinePolicy,
+    mut handle: F,
+) -> FeedReport
+where
+    R: BufRead + Send,
+    F: FnMut(String, LineCheck),
+{
+    let mut report = FeedReport::default();
+    if threads <= 1 {
+        for line in lines(input) {
+            if let Some(log_line) = policy.apply(line, &mut report) {
+                let line_check = check_line(log_filters, cache, &log_line);
+                handle(log_line, line_check);
+            }
+        }
+        return report;
+    }
+
+    let (line_sender, line_receiver) =
+        mpsc::sync_channel::<(usize, String)>(threads * QUEUED_LINES_PER_THREAD);
+    let line_receiver = Mutex::new(line_receiver);
+    let (check_sender, check_receiver) = mpsc::channel::<(usize, String, LineCheck)>();
+    thread::scope(|scope| {
+        let reader = scope.spawn(move || {
+            let mut report = FeedReport::default();
+            // skipped lines leave no gaps in numbers of lines sent
+            let mut line_number = 0;
+            for line in lines(input) {
+                let log_line = match policy.apply(line, &mut report) {
+                    None => continue,
+                    Some(log_line) => log_line,
+                };
+                if line_sender.send((line_number, log_line)).is_err() {
+                    break;
+                }
+                line_number += 1;
+            }
+            report
+        });
+        for _ in 0..threads {
+            let line_receiver = &line_receiver;
+            let check_sender = check_sender.clone();
+            scope.spawn(move || loop {
+                // receiver is unlocked before the line is checked
+                let next_line = line_receiver.lock().unwrap().recv();
+                let (line_number, log_line) = match next_line {
+                    Err(_) => break,
+                    Ok(next_line) => next_line,
+                };
+                let line_check = check_line(log_filters, cache, &log_line);
+                if check_sender
+                    .send((line_number, log_line, line_check))
+                    .is_err()
+                {
+                    break;
+                }
+            });
+        }
+        // results end once every matcher is done with its copy of the sender
+        drop(check_sender);
+
+        // lines checked ahead of an earlier line wait for it
+        let mut checked: HashMap<usize, (String, LineCheck)> = HashMap::new();
+        let mut next_line_number = 0;
+        for (line_number, log_line, line_check) in check_receiver {
+            checked.insert(line_number, (log_line, line_check));
+            while let Some((log_line, line_check)) = checked.remove(&next_line_number) {
+                handle(log_line, line_check);
+                next_line_number += 1;
+            }
+        }
+        report = reader.join().unwrap();
+    });
+
+    report
+}
+
+fn check_line(log_filters: &LogFilters, cache: Option<&LineCache>, log_line: &str) -> LineCheck {
+    match cache {
+        None => log_filters.check_line(log_line),
+        Some(cache) => cache.check_line(log_filters, log_line),
+    }
+}
+
+/// Learn each line of `input` into `log_filters`, `progress` is called with the number
+/// of lines read so far after every `CHUNK_LINES` lines. With more than one thread
+/// input is split into chunks dealt in turn to `threads` shards of `log_filters`,
+/// see `LogFilters::shard`, each learning its chunks on a thread of its own. Shards
+/// are merged into `log_filters` in order once input ends, so the outcome does not
+/// depend on timing of threads, yet it may differ from learning on a single thread:
+/// a shard does not know filters learned by other shards and transitions between
+/// chunks are not counted. Time windows are counted while lines are read in order.
+/// Lines which are not valid UTF-8 are handled by `policy`, returned report counts them.
+pub fn learn_lines<R, F>(
+    log_filters: &mut LogFilters,
+    input: R,
+    threads: usize,
+    policy: InvalidLinePolicy,
+    mut progress: F,
+) -> FeedReport
+where
+    R: BufRead,
+    F: FnMut(usize),
+{
+    if threads <= 1 {
+        let feed = Feed::new()
+            .progress(CHUNK_LINES, progress)
+            .invalid_lines(move |_, bytes| policy.repair(&bytes));
+        return match log_filters.learn_from_reader(input, feed) {
+            Err(why) => panic!("{}", why),
+            Ok(report) => report,
+        };
+    }
+
+    let mut shards: Vec<LogFilters> = (0..threads).map(|_| log_filters.shard()).collect();
+    let mut report = FeedReport::default();
+    thread::scope(|scope| {
+        let mut chunk_senders = Vec::new();
+        for shard in shards.iter_mut() {
+            // a chunk waits while its shard learns the previous one
+            let (chunk_sender, chunk_receiver) = mpsc::sync_channel::<Vec<String>>(1);
+            chunk_senders.push(chunk_sender);
+            scope.spawn(move || {
+                for chunk in chunk_receiver {
+                    shard.break_sequence();
+                    for log_line in &chunk {
+                        shard.learn_line(log_line);
+                    }
+                }
+            });
+        }
+
+        let mut chunk: Vec<String> = Vec::with_capacity(CHUNK_LINES);
+        let mut chunks = 0;
+        for line in lines(input) {
+            let log_line = match policy.apply(line, &mut report) {
+                None => continue,
+                Some(log_line) => log_line,
+            };
+            log_filters.count_rate_bucket(&log_line);
+            chunk.push(log_line);
+            if chunk.len() < CHUNK_LINES {
+                continue;
+            }
+            let full_chunk = mem::replace(&mut chunk, Vec::with_capacity(CHUNK_LINES));
+            if chunk_senders[chunks % threads].send(full_chunk).is_err() {
+                // shard panicked, the panic is raised once the scope ends
+                return;
+            }
+            chunks += 1;
+            progress(chunks * CHUNK_LINES);
+        }
+        if !chunk.is_empty() {
+            let _ = chunk_senders[chunks % threads].send(chunk);
+        }
+    });
+    for shard in &shards {
+        log_filters.merge_shard(shard);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Filters with sorted alternatives along with their hits, ordered
+    fn learned(log_filters: &LogFilters) -> Vec<(Vec<Vec<String>>, usize)> {
+        let mut learned: Vec<(Vec<Vec<String>>, usize)> = (0..log_filters.filters_count())
+            .map(|filter_index| {
+                let mut filter = log_filters.filter_words(filter_index);
+                for word_alternatives in &mut filter {
+                    word_alternatives.sort();
+                }
+                (filter, log_filters.filter_meta(filter_index).hits)
+            })
+            .collect();
+        learned.sort();
+
+        learned
+    }
+
+    #[test]
+    fn learn_lines() {
+        let mut input = String::new();
+        let lines = 3 * CHUNK_LINES + 123;
+        for line_number in 0..lines {
+            input += &match line_number % 4 {
+                0 => format!(
+                    "Sep 27 09:{:02}:15 host sshd[{}]: Accepted publickey for user_{}\n",
+                    line_number / 1000 % 60,
+                    line_number,
+                    line_number % 7
+                ),
+                1 => format!(
+                    "Sep 27 09:{:02}:16 host cron[1]: job run\n",
+                    line_number / 1000 % 60
+                ),
+                2 => format!(
+                    "Sep 27 09:{:02}:16 host cron[1]: job done\n",
+                    line_number / 1000 % 60
+                ),
+                _ => format!(
+                    "Sep 27 09:{:02}:17 host kernel: usb {} connected\n",
+                    line_number / 1000 % 60,
+                    ["hub", "port", "device"][line_number % 3]
+                ),
+            };
+        }
+        let new_log_filters = || {
+            let mut log_filters = LogFilters::new();
+            log_filters.max_allowed_new_alternatives = 1;
+            log_filters.rate_bucket_seconds = 60;
+            log_filters.learn_transitions = true;
+            log_filters
+        };
+
+        let mut sequential = new_log_filters();
+        let mut progress = Vec::new();
+        super::learn_lines(
+            &mut sequential,
+            Cursor::new(&input),
+            1,
+            InvalidLinePolicy::Lossy,
+            |lines| progress.push(lines),
+        );
+        assert_eq!(
+            progress,
+            vec![CHUNK_LINES, 2 * CHUNK_LINES, 3 * CHUNK_LINES]
+        );
+        assert_eq!(sequential.filters_count(), 3);
+
+        for threads in 2..5 {
+            let mut parallel = new_log_filters();
+            let mut progress = Vec::new();
+            super::learn_lines(
+                &mut parallel,
+                Cursor::new(&input),
+                threads,
+                InvalidLinePolicy::Lossy,
+                |lines| progress.push(lines),
+            );
+            assert_eq!(
+                progress,
+                vec![CHUNK_LINES, 2 * CHUNK_LINES, 3 * CHUNK_LINES]
+            );
+            // same filters and hits, in another order
+            assert_eq!(learned(&parallel), learned(&sequential));
+            assert_eq!(parallel.rate_buckets, sequential.rate_buckets);
+            // transitions from the last line of a chunk to the first line of the next
+            // one are lost, the rest is counted the same
+            let transitions = |log_filters: &LogFilters| -> usize {
+                (0..log_filters.filters_count())
+                    .flat_map(|filter_index| log_filters.filter_meta(filter_index).next)
+                    .map(|(_, count)| count)
+                    .sum()
+            };
+            assert_eq!(transitions(&sequential), lines - 1);
+            assert_eq!(transitions(&parallel), lines - 4);
+        }
+
+        // shards start from filters learned before
+        let mut extended = new_log_filters();
+        extended.learn_line("Sep 26 09:13:15 host cron[1]: job run");
+        super::learn_lines(
+            &mut extended,
+            Cursor::new(&input),
+            3,
+            InvalidLinePolicy::Lossy,
+            |_| (),
+        );
+        assert_eq!(extended.filters_count(), 3);
+        let cron_lines =
+            (0..lines).filter(|line_number| line_number % 4 == 1 || line_number % 4 == 2);
+        assert_eq!(extended.filter_meta(0).hits, 1 + cron_lines.count());
+    }
+
+    #[test]
+    fn check_lines() {
+        let mut log_filters = LogFilters::new();
+        log_filters.learn_line("Sep 26 09:13:15 host sshd[1]: Accepted publickey for alice");
+        log_filters.learn_line("Sep 26 09:13:16 host cron[2]: job run");
+
+        let mut input = String::new();
+        for line_number in 0..1000 {
+            input += &match line_number % 3 {
+                0 => format!(
+                    "Sep 27 09:13:15 host sshd[{}]: Accepted publickey for alice\n",
+                    line_number
+                ),
+                1 => format!("Sep 27 09:13:16 host cron[{}]: job run\n", line_number),
+                _ => format!(
+                    "Sep 27 09:13:17 host kernel: line {} unknown\n",
+                    line_number
+                ),
+            };
+        }
+
+        let mut sequential: Vec<(String, LineCheck)> = Vec::new();
+        super::check_lines(
+            &log_filters,
+            Cursor::new(&input),
+            1,
+            None,
+            InvalidLinePolicy::Lossy,
+            |log_line, line_check| sequential.push((log_line, line_check)),
+        );
+        assert_eq!(sequential.len(), 1000);
+        assert_eq!(sequential[0].1, LineCheck::Known(0));
+        assert_eq!(sequential[1].1, LineCheck::Known(1));
+        assert_eq!(sequential[2].1, LineCheck::Unknown);
+
+        for threads in 2..5 {
+            let mut parallel: Vec<(String, LineCheck)> = Vec::new();
+            super::check_lines(
+                &log_filters,
+                Cursor::new(&input),
+                threads,
+                None,
+                InvalidLinePolicy::Lossy,
+                |log_line, line_check| parallel.push((log_line, line_check)),
+            );
+            assert_eq!(parallel, sequential);
+        }
+
+        // cached outcomes are the same
+        let cache = LineCache::new(16);
+        let mut cached: Vec<(String, LineCheck)> = Vec::new();
+        super::check_lines(
+            &log_filters,
+            Cursor::new(&input),
+            3,
+            Some(&cache),
+            InvalidLinePolicy::Lossy,
+            |log_line, line_check| cached.push((log_line, line_check)),
+        );
+        assert_eq!(cached, sequential);
+        assert!(cache.hit_rate() > 0.9);
+
+        let mut lines = 0;
+        super::check_lines(
+            &log_filters,
+            Cursor::new(""),
+            4,
+            None,
+            InvalidLinePolicy::Lossy,
+            |_, _| lines += 1,
+        );
+        assert_eq!(lines, 0);
+
+        // lines which are not valid UTF-8 are repaired or skipped and counted
+        let input: &[u8] = b"Sep 27 09:13:15 host sshd[1]: Accepted publickey for alice\n\
+                             Sep 27 09:13:16 host \xff\n\
+                             Sep 27 09:13:16 host cron[2]: job run\n";
+        for threads in 1..4 {
+            let mut checked = Vec::new();
+            let report = super::check_lines(
+                &log_filters,
+                Cursor::new(input),
+                threads,
+                None,
+                InvalidLinePolicy::Skip,
+                |_, line_check| checked.push(line_check),
+            );
+            assert_eq!(checked, vec![LineCheck::Known(0), LineCheck::Known(1)]);
+            assert_eq!((report.lines, report.skipped, report.repaired), (3, 1, 0));
+
+            let mut checked = Vec::new();
+            let report = super::check_lines(
+                &log_filters,
+                Cursor::new(input),
+                threads,
+                None,
+                InvalidLinePolicy::Lossy,
+                |log_line, _| checked.push(log_line),
+            );
+            assert_eq!(checked[1], "Sep 27 09:13:16 host \u{fffd}");
+            assert_eq!((report.lines, report.skipped, report.repaired), (3, 0, 1));
+
+            let mut learned = LogFilters::new();
+            let report = super::learn_lines(
+                &mut learned,
+                Cursor::new(input),
+                threads,
+                InvalidLinePolicy::Hex,
+                |_| (),
+            );
+            assert_eq!(learned.filters_count(), 3);
+            assert_eq!(learned.filter_to_string(1), "[\\xff]");
+            assert_eq!((report.lines, report.skipped, report.repaired), (3, 0, 1));
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use logmap::LogFilters;
+use severity::field_value;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+/// Longer jumps of time are taken for a change of clock (another source of timestamps,
+/// a broken year) rather than for windows without lines, see `skipped_windows`
+const MAX_SKIPPED_WINDOWS: usize = 10_000;
+
+/// Filter matched unusual number of lines within a single window, see `RateMonitor`
+#[derive(Debug, PartialEq)]
+pub struct RateAnomaly {
+    pub filter_index: usize,
+    /// Lines matched within the window
+    pub count: usize,
+    /// Lines matched within a window on average while learning
+    pub baseline: f64,
+    /// Windows in a row matching `count` lines, more than 1 only for windows without lines
+    pub windows: usize,
+}
+
+/// Counts lines matched by each filter within windows of `rate_bucket_seconds`
+/// and compares the counts with rates learned by `LogFilters::learn_line`.
+/// Lines without timestamp are counted within the current window.
+pub struct RateMonitor {
+    factor: f64,
+    bucket_seconds: usize,
+    baselines: Vec<Option<f64>>,
+    bucket: Option<usize>,
+    counts: Vec<usize>,
+}
+
+impl RateMonitor {
+    /// Windows are reported when a filter matches `factor` times more or less
+    /// lines than it did on average. `None` if `log_filters` carry no rates.
+    pub fn new(log_filters: &LogFilters, factor: f64) -> Option<Self> {
+        if log_filters.rate_bucket_seconds == 0 || log_filters.rate_buckets == 0 {
+            return None;
+        }
+        let baselines: Vec<Option<f64>> = (0..log_filters.filters_count())
+            .map(|filter_index| log_filters.baseline_rate(filter_index))
+            .collect();
+
+        Some(RateMonitor {
+            factor,
+            bucket_seconds: log_filters.rate_bucket_seconds,
+            counts: vec![0; baselines.len()],
+            baselines,
+            bucket: None,
+        })
+    }
+
+    /// Count the line matched by `filter_index`, `None` for lines matched by no filter.
+    /// Returns anomalies of the previous window once the line starts a new one,
+    /// followed by anomalies of windows without lines up to the new one.
+    pub fn observe(&mut self, log_line: &str, filter_index: Option<usize>) -> Vec<RateAnomaly> {
+        let mut anomalies = Vec::new();
+        if let Some(timestamp) = line_timestamp(log_line) {
+            let bucket = timestamp / self.bucket_seconds;
+            if let Some(previous) = self.bucket {
+                if previous != bucket {
+                    anomalies = self.close_window(true);
+                }
+                let skipped = skipped_windows(previous, bucket);
+                if skipped > 0 {
+                    // counts are reset by closing the previous window so skipped windows
+                    // are all the same and reported once
+                    anomalies.extend(self.close_window(true).into_iter().map(|anomaly| {
+                        RateAnomaly {
+                            windows: skipped,
+                            ..anomaly
+                        }
+                    }));
+                }
+            }
+            self.bucket = Some(bucket);
+        }
+        if let Some(count) = filter_index.and_then(|filter_index| self.counts.get_mut(filter_index))
+        {
+            *count += 1;
+        }
+
+        anomalies
+    }
+
+    /// Anomalies of the last window, which is likely incomplete so only
+    /// filters matching too many lines are reported
+    pub fn finish(&mut self) -> Vec<RateAnomaly> {
+        self.close_window(false)
+    }
+
+    fn close_window(&mut self, is_complete: bool) -> Vec<RateAnomaly> {
+        let mut anomalies = Vec::new();
+        for (filter_index, count) in self.counts.iter_mut().enumerate() {
+            let baseline = match self.baselines[filter_index] {
+                None => continue,
+                Some(baseline) => baseline,
+            };
+            // rare filters are not expected in every window, so are not reported missing
+            // and are reported only once they match `factor` lines or more
+            let is_frequent = *count as f64 > baseline.max(1.0) * self.factor;
+            let is_rare =
+                is_complete && baseline >= self.factor && (*count as f64) < baseline / self.factor;
+            if is_frequent || is_rare {
+                anomalies.push(RateAnomaly {
+                    filter_index,
+                    count: *count,
+                    baseline,
+                    windows: 1,
+                });
+            }
+            *count = 0;
+        }
+
+        anomalies
+    }
+}
+
+/// Windows without lines between windows `previous` and `bucket`, none if time goes
+/// backwards (a new year of syslog timestamps) or jumps over more than `MAX_SKIPPED_WINDOWS`
+pub(crate) fn skipped_windows(previous: usize, bucket: usize) -> usize {
+    let skipped = bucket.saturating_sub(previous).saturating_sub(1);
+    if skipped > MAX_SKIPPED_WINDOWS {
+        return 0;
+    }
+
+    skipped
+}
+
+/// Seconds of the time the line was logged at, taken from journald
+/// `__REALTIME_TIMESTAMP` field or from timestamp the line starts with:
+/// ISO 8601 `2024-09-26T09:13:15` (or with space instead of `T`) or syslog
+/// `Sep 26 09:13:15`. Syslog timestamps carry no year so are counted from
+/// the start of a year.
+pub fn line_timestamp(log_line: &str) -> Option<usize> {
+    if let Some(microseconds) = field_value(log_line, "__REALTIME_TIMESTAMP") {
+        return microseconds
+            .parse::<usize>()
+            .ok()
+            .map(|microseconds| microseconds / 1_000_000);
+    }
+    // syslog priority may precede the timestamp
+    let log_line = log_line
+        .strip_prefix('<')
+        .and_then(|rest| rest.find('>').map(|end| &rest[end + 1..]))
+        .unwrap_or(log_line);
+    let mut tokens = log_line.split_whitespace();
+    let first = tokens.next()?;
+
+    if let Some(month) = MONTHS.iter().position(|month| *month == first) {
+        let day = tokens.next()?.parse::<usize>().ok()?;
+        let seconds = clock_seconds(tokens.next()?)?;
+        if day == 0 || day > 31 {
+            return None;
+        }
+        return Some(days_from_epoch(1970, month + 1, day) * 86400 + seconds);
+    }
+
+    let year = number(first, 0, 4)?;
+    let month = number(first, 5, 7)?;
+    let day = number(first, 8, 10)?;
+    if first.get(4..5) != Some("-") || first.get(7..8) != Some("-") {
+        return None;
+    }
+    if month == 0 || month > 12 || day == 0 || day > 31 {
+        return None;
+    }
+    let clock = match first.get(10..11) {
+        Some("T") => &first[11..],
+        None => tokens.next()?,
+        Some(_) => return None,
+    };
+
+    Some(days_from_epoch(year, month, day) * 86400 + clock_seconds(clock)?)
+}
+
+/// Seconds since midnight of `HH:MM:SS`, fraction of a second or zone may follow
+fn clock_seconds(clock: &str) -> Option<usize> {
+    if clock.get(2..3) != Some(":") || clock.get(5..6) != Some(":") {
+        return None;
+    }
+    let hours = number(clock, 0, 2)?;
+    let minutes = number(clock, 3, 5)?;
+    let seconds = number(clock, 6, 8)?;
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+fn number(text: &str, start: usize, end: usize) -> Option<usize> {
+    let digits = text.get(start..end)?;
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    digits.parse::<usize>().ok()
+}
+
+/// Days since 1970-01-01 of a date in proleptic Gregorian calendar, years before 1970 are not supported
+fn days_from_epoch(year: usize, month: usize, day: usize) -> usize {
+    let is_leap = |year: usize| (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days_before_month = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let mut days = (1970..year)
+        .map(|year| if is_leap(year) { 366 } else { 365 })
+        .sum::<usize>();
+    days += days_before_month[month - 1] + day - 1;
+    if month > 2 && is_leap(year) {
+        days += 1;
+    }
+
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_timestamp() {
+        assert_eq!(
+            super::line_timestamp("2024-09-26T09:13:15.123Z host app: started"),
+            Some(1727341995)
+        );
+        assert_eq!(
+            super::line_timestamp("2024-09-26 09:13:15 host app: started"),
+            Some(1727341995)
+        );
+        assert_eq!(
+            super::line_timestamp("{\"__REALTIME_TIMESTAMP\":\"1727341995123456\"}"),
+            Some(1727341995)
+        );
+        assert_eq!(
+            super::line_timestamp("Feb  1 00:00:10 host app: started"),
+            Some(31 * 86400 + 10)
+        );
+        assert_eq!(
+            super::line_timestamp("<13>Jan 1 00:01:00 host app: started"),
+            Some(60)
+        );
+        assert_eq!(super::line_timestamp("host app: started"), None);
+        assert_eq!(super::line_timestamp("Sep 26 9:13 host"), None);
+        assert_eq!(super::line_timestamp("2024-13-26T09:13:15"), None);
+    }
+
+    #[test]
+    fn rate_monitor() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 3;
+        log_filters.rate_bucket_seconds = 60;
+        for minute in 0..4 {
+            for _ in 0..10 {
+                log_filters.learn_line(&format!("Jan 1 00:0{}:00 host session opened", minute));
+            }
+            log_filters.learn_line(&format!("Jan 1 00:0{}:30 host disk checked", minute));
+        }
+        log_filters.learn_line("Jan 1 00:04:00 host disk full");
+        assert_eq!(log_filters.rate_buckets, 5);
+        assert_eq!(log_filters.baseline_rate(0), Some(8.0));
+
+        let mut rate_monitor = RateMonitor::new(&log_filters, 4.0).unwrap();
+        // a window with expected rates
+        for _ in 0..8 {
+            assert_eq!(
+                rate_monitor.observe("Jan 2 00:00:00 host session opened", Some(0)),
+                vec![]
+            );
+        }
+        // disk checks burst while sessions stop
+        for _ in 0..5 {
+            assert_eq!(
+                rate_monitor.observe("Jan 2 00:01:00 host disk checked", Some(1)),
+                vec![]
+            );
+        }
+        assert_eq!(
+            rate_monitor.observe("Jan 2 00:02:00 host disk checked", Some(1)),
+            vec![
+                RateAnomaly {
+                    filter_index: 0,
+                    count: 0,
+                    baseline: 8.0,
+                    windows: 1,
+                },
+                RateAnomaly {
+                    filter_index: 1,
+                    count: 5,
+                    baseline: 0.8,
+                    windows: 1,
+                },
+            ]
+        );
+        // last window is only checked for bursts
+        assert_eq!(rate_monitor.finish(), vec![]);
+    }
+
+    #[test]
+    fn rate_monitor_learned_gap() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 3;
+        log_filters.rate_bucket_seconds = 60;
+        let session_lines = |minute: usize| -> Vec<String> {
+            (0..10)
+                .map(|_| format!("Jan 1 00:0{}:00 host session opened", minute))
+                .collect()
+        };
+        // sessions are opened every fourth window
+        for minute in &[0, 4, 8] {
+            for log_line in session_lines(*minute) {
+                log_filters.learn_line(&log_line);
+            }
+        }
+        assert_eq!(log_filters.rate_buckets, 9);
+
+        let mut rate_monitor = RateMonitor::new(&log_filters, 4.0).unwrap();
+        for minute in &[0, 4, 8] {
+            for log_line in session_lines(*minute) {
+                assert_eq!(rate_monitor.observe(&log_line, Some(0)), vec![]);
+            }
+        }
+        assert_eq!(rate_monitor.finish(), vec![]);
+    }
+
+    #[test]
+    fn rate_monitor_gap() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 3;
+        log_filters.rate_bucket_seconds = 60;
+        for minute in 0..4 {
+            for _ in 0..10 {
+                log_filters.learn_line(&format!("Jan 1 00:0{}:00 host session opened", minute));
+            }
+            log_filters.learn_line(&format!("Jan 1 00:0{}:30 host disk checked", minute));
+        }
+
+        let mut rate_monitor = RateMonitor::new(&log_filters, 4.0).unwrap();
+        for _ in 0..10 {
+            assert_eq!(
+                rate_monitor.observe("Jan 2 00:00:00 host session opened", Some(0)),
+                vec![]
+            );
+        }
+        // sessions stop and nothing is logged for two windows
+        let missing = |windows: usize| RateAnomaly {
+            filter_index: 0,
+            count: 0,
+            baseline: 10.0,
+            windows,
+        };
+        assert_eq!(
+            rate_monitor.observe("Jan 2 00:03:00 host disk checked", Some(1)),
+            vec![missing(2)]
+        );
+        assert_eq!(
+            rate_monitor.observe("Jan 2 00:06:00 host disk checked", Some(1)),
+            vec![missing(1), missing(2)]
+        );
+        // an earlier timestamp starts a new window without a gap
+        assert_eq!(
+            rate_monitor.observe("Jan 1 00:00:00 host disk checked", Some(1)),
+            vec![missing(1)]
+        );
+        // so does a jump from syslog timestamps, counted from 1970, to journald ones
+        assert_eq!(
+            rate_monitor.observe(
+                "{\"__REALTIME_TIMESTAMP\":\"1727341995123456\",\"MESSAGE\":\"disk checked\"}",
+                Some(1)
+            ),
+            vec![missing(1)]
+        );
+        assert_eq!(
+            rate_monitor.observe(
+                "{\"__REALTIME_TIMESTAMP\":\"1727342295123456\",\"MESSAGE\":\"disk checked\"}",
+                Some(1)
+            ),
+            vec![missing(1), missing(4)]
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use logmap::{FilterMeta, LogFilters, SlotType};
+
+/// Characters with special meaning in rules which cannot split words
+const RULE_SYNTAX: &str = "*?|<>";
+
+/// Hand-written filters, one per line, made of words left after leading
+/// columns and numeric words are dropped from a log line:
+///
+/// ```text
+/// # comment
+/// systemd-logind Removed session <hex>
+/// sshd Accepted publickey for * from **
+/// kernel usb device connected hub?
+/// cron job started|finished
+/// ```
+///
+/// `*` matches any word, `**` any number of words, `word?` may be missing,
+/// `a|b` accepts either word and `<int>`, `<hex>`, `<id>` and `<ip>` accept
+/// any word of given type. Rules are marked as manual so learning never changes them.
+pub fn load_rules(log_filters: &mut LogFilters, path: &Path) -> Result<usize, String> {
+    let rules_str = read_file(path)?;
+    add_rules(log_filters, &rules_str).map_err(|why| format!("{}: {}", path.display(), why))
+}
+
+/// Alerts are rules reported whenever a line matches them, even if the line
+/// matches a learned filter too. Each line holds `NAME: RULE`:
+///
+/// ```text
+/// segfault: ** segfault **
+/// oom: kernel Out of memory Killed process **
+/// ```
+pub fn load_alerts(log_filters: &mut LogFilters, path: &Path) -> Result<usize, String> {
+    let alerts_str = read_file(path)?;
+    add_alerts(log_filters, &alerts_str).map_err(|why| format!("{}: {}", path.display(), why))
+}
+
+fn read_file(path: &Path) -> Result<String, String> {
+    let mut file = match File::open(path) {
+        Err(why) => return Err(format!("Couldn't open {}: {}", path.display(), why)),
+        Ok(file) => file,
+    };
+    let mut file_str = String::new();
+    if let Err(why) = file.read_to_string(&mut file_str) {
+        return Err(format!("Couldn't read {}: {}", path.display(), why));
+    }
+
+    Ok(file_str)
+}
+
+/// Compile rules and add them to `log_filters`, rules present already are skipped.
+/// Returns number of added rules, nothing is added if any rule is invalid.
+pub fn add_rules(log_filters: &mut LogFilters, rules_str: &str) -> Result<usize, String> {
+    let rules = compile_lines(log_filters, rules_str, compile_rule)?;
+
+    let mut added = 0;
+    for rule in rules {
+        let is_known = (0..log_filters.filters.len()).any(|filter_index| {
+            log_filters.filter_meta(filter_index).manual
+                && log_filters.filter_words(filter_index) == rule
+        });
+        if is_known {
+            continue;
+        }
+        log_filters.append_filter(
+            rule,
+            FilterMeta {
+                manual: true,
+                ..Default::default()
+            },
+        );
+        added += 1;
+    }
+
+    Ok(added)
+}
+
+/// Compile alerts and add them to `log_filters`, returns number of added alerts.
+/// Nothing is added if any alert is invalid or its name is taken already.
+pub fn add_alerts(log_filters: &mut LogFilters, alerts_str: &str) -> Result<usize, String> {
+    let alerts = compile_lines(log_filters, alerts_str, compile_alert)?;
+    let mut names: Vec<String> = (0..log_filters.filters_count())
+        .filter_map(|filter_index| log_filters.filter_meta(filter_index).alert)
+        .collect();
+    for (name, _) in &alerts {
+        if names.contains(name) {
+            return Err(format!("Alert `{}` is defined twice", name));
+        }
+        names.push(name.clone());
+    }
+
+    let added = alerts.len();
+    for (name, filter) in alerts {
+        log_filters.append_filter(
+            filter,
+            FilterMeta {
+                manual: true,
+                alert: Some(name),
+                ..Default::default()
+            },
+        );
+    }
+
+    Ok(added)
+}
+
+/// Name and filter notation of a single `NAME: RULE` alert, see `load_alerts`
+pub fn compile_alert(
+    log_filters: &LogFilters,
+    alert: &str,
+) -> Result<(String, Vec<Vec<String>>), String> {
+    let position = match alert.find(':') {
+        None => return Err(format!("`NAME: RULE` expected, found `{}`", alert)),
+        Some(position) => position,
+    };
+    let name = alert[..position].trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("Invalid alert name `{}`", name));
+    }
+
+    Ok((
+        name.to_string(),
+        compile_rule(log_filters, &alert[position + 1..])?,
+    ))
+}
+
+/// Compile each line which is neither empty nor a comment
+fn compile_lines<T, F>(
+    log_filters: &LogFilters,
+    lines_str: &str,
+    compile: F,
+) -> Result<Vec<T>, String>
+where
+    F: Fn(&LogFilters, &str) -> Result<T, String>,
+{
+    if let Some(c) = log_filters
+        .delimiters()
+        .chars()
+        .find(|c| RULE_SYNTAX.contains(*c))
+    {
+        return Err(format!("Delimiter `{}` is used by rules syntax", c));
+    }
+
+    let mut compiled: Vec<T> = Vec::new();
+    for (line_index, line) in lines_str.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        compiled.push(
+            compile(log_filters, line)
+                .map_err(|why| format!("line {}: {}", line_index + 1, why))?,
+        );
+    }
+
+    Ok(compiled)
+}
+
+/// Filter notation of a single rule, see `load_rules`
+pub fn compile_rule(log_filters: &LogFilters, rule: &str) -> Result<Vec<Vec<String>>, String> {
+    let denote_optional = &log_filters.denote_optional;
+    let mut filter: Vec<Vec<String>> = Vec::new();
+    for token in LogFilters::line_split_by(
+        rule,
+        &log_filters.delimiters().chars().collect::<Vec<char>>(),
+    ) {
+        let (token, is_optional) = match token.strip_suffix('?') {
+            None => (token.as_str(), false),
+            Some(token) => (token, true),
+        };
+        if token.is_empty() {
+            return Err("`?` must follow a word".to_string());
+        }
+        if token == "**" {
+            if is_optional {
+                return Err("`**` already matches no words, `?` is not needed".to_string());
+            }
+            filter.push(vec![log_filters.denote_gap.clone()]);
+            continue;
+        }
+
+        let mut word_alternatives: Vec<String> = Vec::new();
+        for word in token.split('|') {
+            let word = if word == "*" {
+                log_filters.denote_wildcard.clone()
+            } else if word == "**" {
+                return Err(format!("`**` cannot have alternatives: {}", token));
+            } else if word.starts_with('<') && word.ends_with('>') && word.len() > 2 {
+                let name = &word[1..word.len() - 1];
+                match SlotType::from_marker(&(denote_optional.clone() + name), denote_optional) {
+                    None => return Err(format!("Unknown type `{}`", word)),
+                    Some(slot_type) => slot_type.marker(denote_optional),
+                }
+            } else if word.is_empty() || word.contains(|c| RULE_SYNTAX.contains(c)) {
+                return Err(format!("Invalid word `{}` in `{}`", word, token));
+            } else {
+                word.to_string()
+            };
+            if !word_alternatives.contains(&word) {
+                word_alternatives.push(word);
+            }
+        }
+        // numeric words never reach filters when they are ignored
+        if log_filters.ignore_numeric_words
+            && word_alternatives
+                .iter()
+                .all(|word| log_filters.is_word_only_numeric(word))
+        {
+            continue;
+        }
+        if is_optional {
+            word_alternatives.push(denote_optional.clone());
+        }
+        filter.push(word_alternatives);
+    }
+
+    let has_word = filter
+        .iter()
+        .flatten()
+        .any(|word| !log_filters.is_marker(word));
+    if !has_word {
+        return Err(format!("Rule must contain at least one word: {}", rule));
+    }
+
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_strings(filter: &[&[&str]]) -> Vec<Vec<String>> {
+        filter
+            .iter()
+            .map(|word_alternatives| word_alternatives.iter().map(|w| w.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn compile_rule() {
+        let log_filters = LogFilters::new();
+        assert_eq!(
+            super::compile_rule(&log_filters, "sshd[*]: Accepted <id> for user? from **"),
+            Ok(to_strings(&[
+                &["sshd"],
+                &[".*"],
+                &["Accepted"],
+                &[".id"],
+                &["for"],
+                &["user", "."],
+                &["from"],
+                &[".**"],
+            ]))
+        );
+        assert_eq!(
+            super::compile_rule(&log_filters, "cron 12 job started|finished|started *?"),
+            Ok(to_strings(&[
+                &["cron"],
+                &["job"],
+                &["started", "finished"],
+                &[".*", "."],
+            ]))
+        );
+
+        assert!(super::compile_rule(&log_filters, "job <float>").is_err());
+        assert!(super::compile_rule(&log_filters, "job ?").is_err());
+        assert!(super::compile_rule(&log_filters, "job a|**").is_err());
+        assert!(super::compile_rule(&log_filters, "job **?").is_err());
+        assert!(super::compile_rule(&log_filters, "job a||b").is_err());
+        assert!(super::compile_rule(&log_filters, "* <int> 12").is_err());
+    }
+
+    #[test]
+    fn add_rules() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 1;
+        log_filters.max_slot_alternatives = 1;
+        let rules = "# comment\n\
+                     \n\
+                     systemd-logind Removed session <hex>\n\
+                     kernel usb device connected|disconnected\n";
+        assert_eq!(super::add_rules(&mut log_filters, rules), Ok(2));
+        // generalisation does not apply to rules
+        assert_eq!(
+            log_filters.to_string(),
+            "[systemd-logind],[Removed],[session],[.hex],\n\
+             [kernel],[usb],[device],[connected,disconnected]"
+        );
+        assert!(log_filters.filter_meta(0).manual);
+        assert_eq!(log_filters.word_filters("kernel"), Some(&vec![1]));
+        // known rules are not added again
+        assert_eq!(super::add_rules(&mut log_filters, rules), Ok(0));
+        assert_eq!(
+            super::add_rules(&mut log_filters, "sshd *\n<bad>"),
+            Err("line 2: Unknown type `<bad>`".to_string())
+        );
+        assert_eq!(log_filters.filters_count(), 2);
+
+        log_filters.set_delimiters(" ,.[]|").unwrap();
+        assert!(super::add_rules(&mut log_filters, "sshd").is_err());
+    }
+
+    #[test]
+    fn add_alerts() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 1;
+        log_filters.learn_line("Sep 26 09:13:15 host kernel: app[12] segfault at 0 ip 7f");
+        let alerts = "segfault: ** segfault **\n\
+                      # comment\n\
+                      oom: kernel Out of memory Killed process **\n";
+        assert_eq!(super::add_alerts(&mut log_filters, alerts), Ok(2));
+        assert_eq!(
+            log_filters.filter_meta(1).alert,
+            Some("segfault".to_string())
+        );
+        assert!(log_filters.filter_meta(2).manual);
+        assert_eq!(
+            super::add_alerts(&mut log_filters, "oom: ** oom **"),
+            Err("Alert `oom` is defined twice".to_string())
+        );
+        assert_eq!(
+            super::add_alerts(&mut log_filters, "ok: a\nno name"),
+            Err("line 2: `NAME: RULE` expected, found `no name`".to_string())
+        );
+        assert!(super::compile_alert(&log_filters, "two words: a").is_err());
+        assert_eq!(log_filters.filters_count(), 3);
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+/// Syslog severity levels, ordered from the least severe
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Err,
+    Crit,
+    Alert,
+    Emerg,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Severity {
+    /// Ordered by syslog priority value
+    const BY_PRIORITY: [Severity; 8] = [
+        Severity::Emerg,
+        Severity::Alert,
+        Severity::Crit,
+        Severity::Err,
+        Severity::Warning,
+        Severity::Notice,
+        Severity::Info,
+        Severity::Debug,
+    ];
+
+    /// Syslog keyword of the level
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Debug => "debug",
+            Severity::Info => "info",
+            Severity::Notice => "notice",
+            Severity::Warning => "warning",
+            Severity::Err => "err",
+            Severity::Crit => "crit",
+            Severity::Alert => "alert",
+            Severity::Emerg => "emerg",
+        }
+    }
+
+    /// Syslog keyword or one of common aliases like `error`, `warn` or `fatal`, any case
+    pub fn from_name(name: &str) -> Option<Severity> {
+        let severity = match name.to_ascii_lowercase().as_str() {
+            "debug" | "trace" => Severity::Debug,
+            "info" | "information" => Severity::Info,
+            "notice" => Severity::Notice,
+            "warning" | "warn" => Severity::Warning,
+            "err" | "error" => Severity::Err,
+            "crit" | "critical" | "fatal" => Severity::Crit,
+            "alert" => Severity::Alert,
+            "emerg" | "emergency" | "panic" => Severity::Emerg,
+            _ => return None,
+        };
+
+        Some(severity)
+    }
+
+    /// Level of syslog priority value (0 is `emerg`, 7 is `debug`)
+    pub fn from_priority(priority: usize) -> Option<Severity> {
+        Severity::BY_PRIORITY.get(priority).cloned()
+    }
+
+    /// Severity stated by the line, looked up in this order:
+    /// - syslog `<PRI>` prefix, e.g. `<11>Sep 26 ...`
+    /// - journald `PRIORITY` field of `-o export`, `-o verbose` or `-o json` output
+    /// - JSON `"level": "..."` or logfmt `level=...` field
+    /// - level word in brackets, e.g. `[error]`, or in capitals, e.g. `WARN:`
+    pub fn of_line(log_line: &str) -> Option<Severity> {
+        if let Some(rest) = log_line.strip_prefix('<') {
+            let priority = rest
+                .find('>')
+                .and_then(|end| rest[..end].parse::<usize>().ok());
+            if let Some(priority) = priority {
+                if priority < 192 {
+                    return Severity::from_priority(priority % 8);
+                }
+            }
+        }
+        if let Some(priority) = field_value(log_line, "PRIORITY") {
+            if let Ok(priority) = priority.parse::<usize>() {
+                return Severity::from_priority(priority);
+            }
+        }
+        if let Some(level) = field_value(log_line, "level").and_then(Severity::from_name) {
+            return Some(level);
+        }
+
+        log_line.split_whitespace().find_map(|token| {
+            let token = token.trim_end_matches(':');
+            let bracketed = [('[', ']'), ('<', '>'), ('(', ')')]
+                .iter()
+                .find_map(|(open, close)| token.strip_prefix(*open)?.strip_suffix(*close));
+            match bracketed {
+                Some(name) => Severity::from_name(name),
+                None if token.chars().all(|c| c.is_ascii_uppercase()) => Severity::from_name(token),
+                None => None,
+            }
+        })
+    }
+}
+
+/// Word following `key=`, `"key":"` or `"key": "` where `key` is not a part of a longer word
+pub(crate) fn field_value<'a>(log_line: &'a str, key: &str) -> Option<&'a str> {
+    for (position, _) in log_line.match_indices(key) {
+        let is_word_start = log_line[..position]
+            .chars()
+            .next_back()
+            .map_or(true, |c| !c.is_ascii_alphanumeric() && c != '_');
+        if !is_word_start {
+            continue;
+        }
+        let rest = &log_line[position + key.len()..];
+        let rest = match rest.strip_prefix('=') {
+            Some(rest) => rest,
+            None => match rest.strip_prefix("\":") {
+                Some(rest) => rest.trim_start(),
+                None => continue,
+            },
+        };
+        let rest = rest.trim_start_matches('"');
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        if end > 0 {
+            return Some(&rest[..end]);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn of_line() {
+        assert_eq!(
+            Severity::of_line("<11>Sep 26 09:13:15 host app: failed"),
+            Some(Severity::Err)
+        );
+        assert_eq!(
+            Severity::of_line("{\"MESSAGE\":\"failed\",\"PRIORITY\":\"4\"}"),
+            Some(Severity::Warning)
+        );
+        assert_eq!(Severity::of_line("PRIORITY=2"), Some(Severity::Crit));
+        assert_eq!(
+            Severity::of_line("{\"level\": \"error\", \"msg\": \"failed\"}"),
+            Some(Severity::Err)
+        );
+        assert_eq!(
+            Severity::of_line("ts=1 level=debug msg=started"),
+            Some(Severity::Debug)
+        );
+        assert_eq!(
+            Severity::of_line("2024/01/01 12:00:00 [error] 12#0: open() failed"),
+            Some(Severity::Err)
+        );
+        assert_eq!(
+            Severity::of_line("12:00:00 WARN: disk almost full"),
+            Some(Severity::Warning)
+        );
+        // level words in text do not count, neither do longer keys
+        assert_eq!(Severity::of_line("Sep 26 host app: no error found"), None);
+        assert_eq!(Severity::of_line("loglevel=info"), None);
+        assert_eq!(Severity::of_line("<999>Sep 26 host"), None);
+    }
+
+    #[test]
+    fn order() {
+        assert!(Severity::Err > Severity::Warning);
+        assert_eq!(Severity::from_priority(0), Some(Severity::Emerg));
+        assert_eq!(Severity::from_priority(8), None);
+        assert_eq!(Severity::from_name("FATAL"), Some(Severity::Crit));
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+
+use logmap::LogFilters;
+
+/// Summary of a filters model, see `Stats::to_text` and `Stats::to_json`
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+    pub filters: usize,
+    pub unique_words: usize,
+    /// Number of filters of each length
+    pub filter_lengths: BTreeMap<usize, usize>,
+    pub slots: usize,
+    /// Slots accepting more than one word
+    pub slots_with_alternatives: usize,
+    /// Slots which may be missing from the line
+    pub optional_slots: usize,
+    /// Slots matching any word
+    pub wildcard_slots: usize,
+    /// Slots matching words of a type
+    pub typed_slots: usize,
+    /// Slots matching any number of words
+    pub gap_slots: usize,
+    /// Words referenced by the highest number of filters, as (word, filters)
+    pub top_words: Vec<(String, usize)>,
+    /// Slots with the highest number of words, as (filter index, slot index, words)
+    pub largest_alternatives: Vec<(usize, usize, Vec<String>)>,
+    /// Filters matched by the highest number of lines, as (filter index, hits);
+    /// empty when the model carries no hit counts
+    pub top_filters: Vec<(usize, usize)>,
+}
+
+impl Stats {
+    /// Gather statistics, lists are limited to `top` elements
+    pub fn new(log_filters: &LogFilters, top: usize) -> Self {
+        let mut stats = Stats {
+            filters: log_filters.filters.len(),
+            unique_words: log_filters.words_hash.len(),
+            ..Default::default()
+        };
+
+        for (filter_index, filter) in log_filters.filters.iter().enumerate() {
+            *stats.filter_lengths.entry(filter.len()).or_insert(0) += 1;
+            for (slot_index, word_alternatives) in filter.iter().enumerate() {
+                let words: Vec<String> = word_alternatives
+                    .iter()
+                    .filter(|word| !log_filters.is_marker_id(**word))
+                    .map(|word| log_filters.word(*word).to_string())
+                    .collect();
+                stats.slots += 1;
+                if log_filters.is_optional(word_alternatives) {
+                    stats.optional_slots += 1;
+                }
+                if log_filters.is_wildcard(word_alternatives) {
+                    stats.wildcard_slots += 1;
+                } else if log_filters.slot_type(word_alternatives).is_some() {
+                    stats.typed_slots += 1;
+                } else if log_filters.is_gap(word_alternatives) {
+                    stats.gap_slots += 1;
+                }
+                if words.len() > 1 {
+                    stats.slots_with_alternatives += 1;
+                    stats
+                        .largest_alternatives
+                        .push((filter_index, slot_index, words));
+                }
+            }
+            let hits = log_filters.filter_meta(filter_index).hits;
+            if hits > 0 {
+                stats.top_filters.push((filter_index, hits));
+            }
+        }
+
+        stats.top_words = log_filters
+            .words_hash
+            .iter()
+            .map(|(word, filter_indexes)| {
+                (log_filters.word(*word).to_string(), filter_indexes.len())
+            })
+            .collect();
+        // ties are ordered alphabetically to keep the report stable
+        stats
+            .top_words
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        stats.top_words.truncate(top);
+        stats.largest_alternatives.sort_by(|a, b| {
+            b.2.len()
+                .cmp(&a.2.len())
+                .then_with(|| (a.0, a.1).cmp(&(b.0, b.1)))
+        });
+        stats.largest_alternatives.truncate(top);
+        stats
+            .top_filters
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        stats.top_filters.truncate(top);
+
+        stats
+    }
+
+    pub fn to_text(&self, log_filters: &LogFilters) -> String {
+        let mut text = String::new();
+        text += &format!("Filters:                 {}\n", self.filters);
+        text += &format!("Unique words:            {}\n", self.unique_words);
+        text += &format!("Slots:                   {}\n", self.slots);
+        text += &format!(
+            "Slots with alternatives: {}\n",
+            self.slots_with_alternatives
+        );
+        text += &format!("Optional slots:          {}\n", self.optional_slots);
+        text += &format!("Wildcard slots:          {}\n", self.wildcard_slots);
+        text += &format!("Typed slots:             {}\n", self.typed_slots);
+        text += &format!("Gap slots:               {}\n", self.gap_slots);
+
+        text += "\nFilter lengths (words: filters):\n";
+        for (length, count) in &self.filter_lengths {
+            text += &format!("    {:>5}: {}\n", length, count);
+        }
+
+        text += "\nMost common words (word: filters):\n";
+        for (word, count) in &self.top_words {
+            text += &format!("    {}: {}\n", word, count);
+        }
+
+        text += "\nLargest alternative sets (filter/slot: words):\n";
+        for (filter_index, slot_index, words) in &self.largest_alternatives {
+            text += &format!(
+                "    #{}/{}: {} [{}]\n",
+                filter_index,
+                slot_index,
+                words.len(),
+                words.join(",")
+            );
+        }
+
+        if !self.top_filters.is_empty() {
+            text += "\nTop filters (hits: filter):\n";
+            for (filter_index, hits) in &self.top_filters {
+                text += &format!(
+                    "    {}: #{} {}\n",
+                    hits,
+                    filter_index,
+                    log_filters.filter_to_string(*filter_index)
+                );
+            }
+        }
+
+        text
+    }
+
+    pub fn to_json(&self, log_filters: &LogFilters) -> String {
+        let filter_lengths: Vec<String> = self
+            .filter_lengths
+            .iter()
+            .map(|(length, count)| format!("{{\"length\":{},\"filters\":{}}}", length, count))
+            .collect();
+        let top_words: Vec<String> = self
+            .top_words
+            .iter()
+            .map(|(word, count)| {
+                format!("{{\"word\":{},\"filters\":{}}}", json_string(word), count)
+            })
+            .collect();
+        let largest_alternatives: Vec<String> = self
+            .largest_alternatives
+            .iter()
+            .map(|(filter_index, slot_index, words)| {
+                let words: Vec<String> = words.iter().map(|word| json_string(word)).collect();
+                format!(
+                    "{{\"filter\":{},\"slot\":{},\"words\":[{}]}}",
+                    filter_index,
+                    slot_index,
+                    words.join(",")
+                )
+            })
+            .collect();
+        let top_filters: Vec<String> = self
+            .top_filters
+            .iter()
+            .map(|(filter_index, hits)| {
+                format!(
+                    "{{\"filter\":{},\"hits\":{},\"template\":{}}}",
+                    filter_index,
+                    hits,
+                    json_string(&log_filters.filter_to_string(*filter_index))
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"filters\":{},\"unique_words\":{},\"slots\":{},\"slots_with_alternatives\":{},\
+             \"optional_slots\":{},\"wildcard_slots\":{},\"typed_slots\":{},\"gap_slots\":{},\
+             \"filter_lengths\":[{}],\"top_words\":[{}],\
+             \"largest_alternatives\":[{}],\"top_filters\":[{}]}}",
+            self.filters,
+            self.unique_words,
+            self.slots,
+            self.slots_with_alternatives,
+            self.optional_slots,
+            self.wildcard_slots,
+            self.typed_slots,
+            self.gap_slots,
+            filter_lengths.join(","),
+            top_words.join(","),
+            largest_alternatives.join(","),
+            top_filters.join(",")
+        )
+    }
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            '\r' => escaped += "\\r",
+            '\t' => escaped += "\\t",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_test_data() -> LogFilters {
+        let mut log_filters = LogFilters::new();
+        log_filters.max_allowed_new_alternatives = 1;
+        log_filters.ignore_first_columns = 1;
+        log_filters.learn_line("Sep 26 09:13:15 host systemd-logind[572]: Removed session c524.");
+        log_filters.learn_line("Sep 27 19:27:53 host systemd-logind[572]: Removed session c525.");
+        log_filters.learn_line("Sep 28 13:41:26 host systemd-logind[572]: Removed session c526.");
+        log_filters.learn_line("Sep 28 13:41:26 host kernel: usb device connected");
+        log_filters
+    }
+
+    #[test]
+    fn new() {
+        let stats = Stats::new(&LogFilters::new(), 10);
+        assert_eq!(stats, Stats::default());
+
+        let log_filters = init_test_data();
+        let stats = Stats::new(&log_filters, 2);
+        assert_eq!(stats.filters, 2);
+        assert_eq!(stats.unique_words, 11);
+        assert_eq!(stats.filter_lengths.get(&5), Some(&2));
+        assert_eq!(stats.slots, 10);
+        assert_eq!(stats.slots_with_alternatives, 1);
+        assert_eq!(stats.optional_slots, 0);
+        assert_eq!(
+            stats.top_words,
+            vec![("host".to_string(), 2), ("Removed".to_string(), 1)]
+        );
+        assert_eq!(
+            stats.largest_alternatives,
+            vec![(
+                0,
+                4,
+                vec!["c524".to_string(), "c525".to_string(), "c526".to_string()]
+            )]
+        );
+        assert_eq!(stats.top_filters, vec![(0, 3), (1, 1)]);
+
+        // words replaced by wildcard are no longer listed
+        let mut log_filters = init_test_data();
+        log_filters.max_slot_alternatives = 2;
+        log_filters.learn_line("Sep 28 13:41:26 host systemd-logind[572]: Removed session c527.");
+        let stats = Stats::new(&log_filters, 2);
+        assert_eq!(stats.unique_words, 8);
+        assert_eq!(stats.slots_with_alternatives, 0);
+        assert_eq!(stats.wildcard_slots, 1);
+        assert_eq!(stats.typed_slots, 0);
+        assert_eq!(stats.largest_alternatives, vec![]);
+    }
+
+    #[test]
+    fn to_json() {
+        let log_filters = init_test_data();
+        let stats = Stats::new(&log_filters, 1);
+        assert_eq!(
+            stats.to_json(&log_filters),
+            "{\"filters\":2,\"unique_words\":11,\"slots\":10,\"slots_with_alternatives\":1,\
+             \"optional_slots\":0,\"wildcard_slots\":0,\"typed_slots\":0,\"gap_slots\":0,\
+             \"filter_lengths\":[{\"length\":5,\"filters\":2}],\
+             \"top_words\":[{\"word\":\"host\",\"filters\":2}],\
+             \"largest_alternatives\":[{\"filter\":0,\"slot\":4,\"words\":[\"c524\",\"c525\",\"c526\"]}],\
+             \"top_filters\":[{\"filter\":0,\"hits\":3,\
+             \"template\":\"[host],[systemd-logind],[Removed],[session],[c524,c525,c526]\"}]}"
+        );
+    }
+
+    #[test]
+    fn json_string() {
+        assert_eq!(super::json_string("abc"), "\"abc\"");
+        assert_eq!(super::json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+        assert_eq!(super::json_string("\u{1}"), "\"\\u0001\"");
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Identifier of a word stored in `Symbols`
+pub type WordId = u32;
+
+/// Table of unique words, markers included, each stored once and referred
+/// to by its `WordId`. The index of identifiers shares each word with the table.
+/// Words are never removed, a table holding only the words still in use is
+/// interned anew instead, see `LogFilters::compact_symbols`.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    ids: HashMap<Arc<str>, WordId>,
+    words: Vec<Arc<str>>,
+}
+
+impl Symbols {
+    /// Identifier of the word, the word is added if it is not stored yet
+    pub fn intern(&mut self, word: &str) -> WordId {
+        if let Some(id) = self.ids.get(word) {
+            return *id;
+        }
+        let id = self.words.len() as WordId;
+        let word: Arc<str> = Arc::from(word);
+        self.ids.insert(word.clone(), id);
+        self.words.push(word);
+
+        id
+    }
+
+    /// Identifier of the word, `None` if it was never interned
+    pub fn get(&self, word: &str) -> Option<WordId> {
+        self.ids.get(word).cloned()
+    }
+
+    pub fn word(&self, id: WordId) -> &str {
+        match self.words.get(id as usize) {
+            None => panic!("Unknown word id {}", id),
+            Some(word) => word,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern() {
+        let mut symbols = Symbols::default();
+        let sshd = symbols.intern("sshd");
+        let session = symbols.intern("session");
+        assert_ne!(sshd, session);
+        assert_eq!(symbols.intern("sshd"), sshd);
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.get("session"), Some(session));
+        assert_eq!(symbols.get("kernel"), None);
+        assert_eq!(symbols.word(sshd), "sshd");
+    }
+}
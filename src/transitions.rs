@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use logmap::LogFilters;
+
+/// Consecutive lines of a stream matched filters which rarely followed each other while learning
+#[derive(Debug, PartialEq)]
+pub struct RareTransition {
+    pub from: usize,
+    pub to: usize,
+    /// How many times the transition was seen while learning
+    pub count: usize,
+}
+
+/// Follows filters matched by consecutive lines of each stream, see
+/// `LogFilters::learn_transitions`, and compares transitions with learned ones
+pub struct TransitionMonitor {
+    min_count: usize,
+    last_stream_filters: HashMap<String, usize>,
+}
+
+impl TransitionMonitor {
+    /// Transitions seen less than `min_count` times while learning are reported.
+    /// `None` if `log_filters` carry no transitions.
+    pub fn new(log_filters: &LogFilters, min_count: usize) -> Option<Self> {
+        if !log_filters.learn_transitions {
+            return None;
+        }
+
+        Some(TransitionMonitor {
+            min_count,
+            last_stream_filters: HashMap::new(),
+        })
+    }
+
+    /// Follow the line matched by `filter_index`, `None` for lines matched by no
+    /// filter which break the sequence of their stream
+    pub fn observe(
+        &mut self,
+        log_filters: &LogFilters,
+        log_line: &str,
+        filter_index: Option<usize>,
+    ) -> Option<RareTransition> {
+        let stream = log_filters.stream_key(log_line);
+        let to = match filter_index {
+            None => {
+                self.last_stream_filters.remove(&stream);
+                return None;
+            }
+            Some(filter_index) => filter_index,
+        };
+        let from = self.last_stream_filters.insert(stream, to)?;
+        let count = log_filters.transition_count(from, to);
+        if count >= self.min_count {
+            return None;
+        }
+
+        Some(RareTransition { from, to, count })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observe() {
+        let mut log_filters = LogFilters::new();
+        log_filters.ignore_first_columns = 1;
+        log_filters.learn_transitions = true;
+        // program name follows host
+        log_filters.transition_stream_column = Some(6);
+        for _ in 0..3 {
+            log_filters.learn_line("Sep 26 09:13:15 host app[1]: Starting worker");
+            log_filters.learn_line("Sep 26 09:13:15 host cron[2]: job run");
+            log_filters.learn_line("Sep 26 09:13:16 host app[1]: Started worker");
+        }
+        assert_eq!(log_filters.transition_count(0, 2), 3);
+        assert_eq!(log_filters.transition_count(2, 0), 2);
+        assert_eq!(log_filters.transition_count(0, 1), 0);
+        assert_eq!(log_filters.transition_count(1, 1), 2);
+
+        let mut transition_monitor = TransitionMonitor::new(&log_filters, 3).unwrap();
+        let lines = [
+            ("Sep 27 09:13:15 host app[1]: Starting worker", Some(0)),
+            ("Sep 27 09:13:15 host cron[2]: job run", Some(1)),
+            ("Sep 27 09:13:16 host app[1]: Started worker", Some(2)),
+            ("Sep 27 09:13:16 host app[1]: Starting worker", Some(0)),
+            ("Sep 27 09:13:16 host app[1]: Starting worker", Some(0)),
+        ];
+        let rare: Vec<Option<RareTransition>> = lines
+            .iter()
+            .map(|(line, filter_index)| {
+                transition_monitor.observe(&log_filters, line, *filter_index)
+            })
+            .collect();
+        assert_eq!(
+            rare,
+            vec![
+                None,
+                None,
+                None,
+                Some(RareTransition {
+                    from: 2,
+                    to: 0,
+                    count: 2,
+                }),
+                Some(RareTransition {
+                    from: 0,
+                    to: 0,
+                    count: 0,
+                }),
+            ]
+        );
+        // unknown line breaks the sequence
+        assert_eq!(
+            transition_monitor.observe(&log_filters, "Sep 27 09:13:17 host app[1]: crashed", None),
+            None
+        );
+        assert_eq!(
+            transition_monitor.observe(
+                &log_filters,
+                "Sep 27 09:13:18 host app[1]: Starting worker",
+                Some(0)
+            ),
+            None
+        );
+    }
+}
@@ -168,3 +168,143 @@ fn one_alt_no_nums_one_col_skipped_repeated_filter_word_at_the_log_end() {
 
     assert_eq!(log_filters.to_string(), expected);
 }
+
+#[test]
+fn one_alt_no_nums_one_col_skipped_hits_counted() {
+    let mut log_filters = logmap::logmap::LogFilters::new();
+    log_filters.max_allowed_new_alternatives = 1;
+    log_filters.ignore_numeric_words = true;
+    log_filters.ignore_first_columns = 1;
+
+    log_filters.learn_line("Sep 26 09:13:15 anonymous_hostname systemd-logind[572]: Removed session c524.");
+    log_filters.learn_line("Sep 27 19:27:53 anonymous_hostname systemd-logind[572]: Removed session c525.");
+    log_filters.learn_line("Sep 28 13:41:26 anonymous_hostname");
+    log_filters.learn_line("Sep 28 13:41:26 anonymous_hostname systemd-logind[572]: Removed session c526.");
+    log_filters.learn_line("Sep 28 13:41:26");
+
+    assert_eq!(log_filters.filters_count(), 2);
+    assert_eq!(log_filters.filter_meta(0).hits, 3);
+    assert_eq!(log_filters.filter_meta(1).hits, 1);
+}
+
+#[test]
+fn one_alt_no_nums_one_col_skipped_wildcard_promoted() {
+    let mut log_filters = logmap::logmap::LogFilters::new();
+    log_filters.max_allowed_new_alternatives = 1;
+    log_filters.ignore_numeric_words = true;
+    log_filters.ignore_first_columns = 1;
+    log_filters.max_slot_alternatives = 2;
+
+    log_filters.learn_line("Sep 26 09:13:15 anonymous_hostname systemd-logind[572]: Removed session c524.");
+    log_filters.learn_line("Sep 27 19:27:53 anonymous_hostname systemd-logind[572]: Removed session c525.");
+
+    assert_eq!(log_filters.to_string(), "[anonymous_hostname],[systemd-logind],[Removed],[session],[c524,c525]");
+
+    log_filters.learn_line("Sep 28 13:41:26 anonymous_hostname systemd-logind[572]: Removed session c526.");
+    log_filters.learn_line("Sep 28 13:41:26 anonymous_hostname systemd-logind[572]: Removed session c527.");
+
+    assert_eq!(log_filters.to_string(), "[anonymous_hostname],[systemd-logind],[Removed],[session],[.*]");
+
+    // Wildcard matches any word even when no new alternatives are allowed
+    log_filters.max_allowed_new_alternatives = 0;
+    assert_eq!(log_filters.find_line_filter("Sep 29 10:00:00 anonymous_hostname systemd-logind[572]: Removed session c999."), Some(0));
+    assert_eq!(log_filters.find_line_filter("Sep 29 10:00:00 anonymous_hostname systemd-logind[572]: Added session c999."), None);
+}
+
+#[test]
+fn two_alts_include_num_five_cols_skipped_slot_types_inferred() {
+    let mut log_filters = logmap::logmap::LogFilters::new();
+    log_filters.max_allowed_new_alternatives = 2;
+    log_filters.ignore_numeric_words = false;
+    log_filters.ignore_first_columns = 5;
+    log_filters.infer_slot_types = true;
+
+    log_filters.learn_line("Sep 26 09:13:15 anonymous_hostname systemd-logind[572]: Removed session c524.");
+    log_filters.learn_line("Sep 27 19:27:53 anonymous_hostname systemd-logind[573]: Removed session c525.");
+
+    assert_eq!(log_filters.to_string(), "[anonymous_hostname],[systemd-logind],[.int],[Removed],[session],[.hex]");
+
+    // Passive mode accepts new values of learned type only
+    log_filters.max_allowed_new_alternatives = 0;
+    assert_eq!(log_filters.find_line_filter("Sep 27 19:27:53 anonymous_hostname systemd-logind[1]: Removed session c999."), Some(0));
+    assert_eq!(log_filters.find_line_filter("Sep 27 19:27:53 anonymous_hostname systemd-logind[abc1]: Removed session c999."), None);
+    assert_eq!(log_filters.find_line_filter("Sep 27 19:27:53 anonymous_hostname systemd-logind[1]: Removed session user_1."), None);
+}
+
+#[test]
+fn three_alts_no_nums_one_col_skipped_gap_introduced() {
+    let mut log_filters = logmap::logmap::LogFilters::new();
+    log_filters.max_allowed_new_alternatives = 3;
+    log_filters.ignore_numeric_words = true;
+    log_filters.ignore_first_columns = 1;
+    log_filters.min_gap_slots = 2;
+
+    log_filters.learn_line("Sep 26 09:13:15 anonymous_hostname systemd[1]: Failed to start Network Manager.");
+    log_filters.learn_line("Sep 26 09:13:15 anonymous_hostname systemd[1]: Failed to start Load Kernel Modules.");
+
+    assert_eq!(log_filters.to_string(), "[anonymous_hostname],[systemd],[Failed],[to],[start],[.**]");
+
+    log_filters.learn_line("Sep 26 09:13:15 anonymous_hostname systemd[1]: Failed to start Apply Kernel Variables for all users.");
+
+    assert_eq!(log_filters.to_string(), "[anonymous_hostname],[systemd],[Failed],[to],[start],[.**]");
+    assert_eq!(log_filters.filter_meta(0).hits, 3);
+
+    // Gap matches descriptions of any length in passive mode
+    log_filters.max_allowed_new_alternatives = 0;
+    assert_eq!(log_filters.find_line_filter("Sep 26 09:13:15 anonymous_hostname systemd[1]: Failed to start Journal Service."), Some(0));
+    assert_eq!(log_filters.find_line_filter("Sep 26 09:13:15 anonymous_hostname systemd[1]: Failed to start"), Some(0));
+    assert_eq!(log_filters.find_line_filter("Sep 26 09:13:15 anonymous_hostname systemd[1]: Started Journal Service."), None);
+}
+
+fn learn_corpus(prefix_index_tokens: usize, lines: &[&str]) -> logmap::logmap::LogFilters {
+    let mut log_filters = logmap::logmap::LogFilters::new();
+    log_filters.max_allowed_new_alternatives = 1;
+    log_filters.ignore_numeric_words = true;
+    log_filters.ignore_first_columns = 1;
+    log_filters.set_prefix_index_tokens(prefix_index_tokens);
+    for line in lines {
+        log_filters.learn_line(line);
+    }
+    log_filters
+}
+
+#[test]
+fn one_alt_no_nums_one_col_skipped_prefix_index_same_leading_words() {
+    // Lines sharing their first words are learned the same way by both indexes
+    let lines = [
+        "Sep 26 09:13:15 anonymous_hostname systemd-logind[572]: Removed session c524.",
+        "Sep 27 19:27:53 anonymous_hostname systemd-logind[572]: Removed session c525.",
+        "Sep 28 13:41:26 anonymous_hostname",
+        "Sep 28 13:41:26 anonymous_hostname systemd-logind[572]: Removed session c526.",
+        "Sep 22 22:27:52 anonymous_hostname dolphin[7229]: org.kde.dolphin: slotUrlSelectionRequested:  QUrl(\"file:///some/path/dir1\")",
+        "Sep 22 22:28:40 anonymous_hostname dolphin[7229]: org.kde.dolphin: slotUrlSelectionRequested:  QUrl(\"file:///some/path/dir2\")",
+    ];
+    let words_index = learn_corpus(0, &lines);
+    for tokens in 1..4 {
+        let prefix_index = learn_corpus(tokens, &lines);
+        assert_eq!(prefix_index.to_string(), words_index.to_string());
+    }
+}
+
+#[test]
+fn one_alt_no_nums_one_col_skipped_prefix_index_differing_leading_words() {
+    // A line differing in one of the first words is matched to the filter
+    // by words index only, prefix index learns a new filter instead
+    let lines = [
+        "Sep 26 09:13:15 anonymous_hostname systemd-logind[572]: Removed session c524.",
+        "Sep 27 19:27:53 other_hostname systemd-logind[572]: Removed session c524.",
+        "Sep 28 13:41:26 anonymous_hostname systemd-logind[572]: Added session c524.",
+    ];
+    let words_index = learn_corpus(0, &lines);
+    assert_eq!(words_index.to_string(), "[anonymous_hostname,other_hostname],[systemd-logind],[Removed,Added],[session],[c524]");
+
+    let prefix_index = learn_corpus(2, &lines);
+    let mut expected: String = "[anonymous_hostname],[systemd-logind],[Removed,Added],[session],[c524],".to_string();
+                 expected += "\n[other_hostname],[systemd-logind],[Removed],[session],[c524]";
+    assert_eq!(prefix_index.to_string(), expected);
+
+    // Words following the first ones may still differ
+    let prefix_index = learn_corpus(1, &lines);
+    assert_eq!(prefix_index.filters_count(), 2);
+    assert_eq!(prefix_index.filter_meta(0).hits, 2);
+}